@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// the RoR the webview calculated before the backend did, to test
+// analytics::compute against. calculateRor and findRorOutlier of
+// src/calculate.ts, not recording, with mean and standardDeviation of
+// simple-statistics
+//
+//   node baseline.js roast.json > roast.baseline.json
+//
+// roast.json is { "data": [{ "timestamp", "value" }], "drop": seconds or null }
+
+const fs = require("fs");
+
+// simple-statistics
+function sum(x) {
+    if (x.length === 0) return 0;
+    let sum = x[0];
+    let correction = 0;
+    let transition;
+    for (let i = 1; i < x.length; i++) {
+        transition = sum + x[i];
+        if (Math.abs(sum) >= Math.abs(x[i])) {
+            correction += sum - transition + x[i];
+        } else {
+            correction += x[i] - transition + sum;
+        }
+        sum = transition;
+    }
+    return sum + correction;
+}
+
+function mean(x) {
+    return sum(x) / x.length;
+}
+
+function standardDeviation(x) {
+    const m = mean(x);
+    let v = 0;
+    for (let i = 0; i < x.length; i++) {
+        v += Math.pow(x[i] - m, 2);
+    }
+    return Math.sqrt(v / x.length);
+}
+
+// calculate.ts
+function hann(i, N) {
+    return 0.5 * (1 - Math.cos(6.283185307179586 * i / (N - 1)))
+}
+
+function convValid(f, g) {
+    const nf = f.length;
+    const ng = g.length;
+    const minV = (nf < ng) ? f : g;
+    const maxV = (nf < ng) ? g : f;
+    const n = Math.max(nf, ng) - Math.min(nf, ng) + 1;
+    const out = new Array(n).fill(0);
+
+    for (let i = 0; i < n; ++i) {
+        for (let j = minV.length - 1, k = i; j >= 0; --j) {
+            out[i] += minV[j] * maxV[k];
+            ++k;
+        }
+    }
+
+    return out;
+}
+
+function calculateRor(data, drop) {
+    let ror_array = [];
+
+    for (let i = 0; i < data.length; i++) {
+        let window_size = 5
+        let window = data.slice(Math.max(0, i - window_size + 1), i + 1);
+
+        let delta = window[window.length - 1].value - window[0].value;
+        let time_elapsed_sec = window[window.length - 1].timestamp - window[0].timestamp;
+
+        let ror = (Math.floor(delta / time_elapsed_sec * 60 * 10)) / 10 || 0
+
+        ror_array.push({ timestamp: data[i].timestamp, value: ror });
+    }
+
+    if (drop != null) {
+        ror_array = ror_array.filter((p) => p.timestamp <= drop);
+    }
+    return ror_array;
+}
+
+function findRorOutlier(ror) {
+    let ror_outlier = [];
+    let ror_filtered = [];
+
+    for (let i = 0; i < ror.length; i++) {
+        if (i == 0) {
+            continue;
+        }
+
+        let window_size = 5
+        let window = ror.slice(Math.max(0, i - window_size), i).map(r => r.value);
+
+        let ma = mean(window);
+        let sd = standardDeviation(window);
+        let zScore = Math.abs((ror[i].value - ma) / sd);
+
+        if (zScore > 3) {
+            ror_outlier.push(ror[i])
+        } else {
+            ror_filtered.push(ror[i])
+        }
+    }
+
+    let hann_window = [];
+    let window_len = 11;
+    let half_window_len = Math.floor((window_len - 1) / 2);
+
+    for (let i = 0; i < window_len; i++) {
+        hann_window.push(hann(i, window_len))
+    }
+
+    let sum = hann_window.reduce((partialSum, a) => partialSum + a, 0);
+
+    let filter = hann_window.map((m) => (m / sum));
+
+    let data = ror_filtered.map(p => p.value);
+    let dataLeft = data.slice(1, window_len).reverse();
+    let dataRight = data.slice(-window_len, -1).reverse();
+    let input = [...dataLeft, ...data, ...dataRight];
+    let conv = convValid(filter, input);
+    let result = conv.slice(half_window_len, -half_window_len);
+
+    // short of the window, the last points have no value and are null in json
+    let ror_convolve = ror_filtered.map((p, i) => ({ timestamp: p.timestamp, value: result[i] ?? null }));
+
+    return { outliers: ror_outlier, filtered: ror_filtered, smoothed: ror_convolve };
+}
+
+const roast = JSON.parse(fs.readFileSync(process.argv[2]));
+const ror = calculateRor(roast.data, roast.drop);
+console.log(JSON.stringify({ ror: ror, ...findRorOutlier(ror) }));
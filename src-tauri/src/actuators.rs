@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::{sleep, timeout, Duration};
+
+use crate::config::{Config, ManualChannel};
+use crate::devices::WriteRequest;
+
+pub mod http;
+pub mod modbus;
+pub mod mqtt;
+pub mod tc4;
+
+// an actuator that does not confirm within this is taken as failed
+const SET_TIMEOUT: Duration = Duration::from_secs(5);
+
+// an output of the roaster, driven by a manual channel
+#[async_trait]
+pub trait Actuator {
+    async fn set(self: &mut Self, value: f64) -> Result<(), Error>;
+}
+
+// one applied output change, kept for the roast record
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OutputChange {
+    pub channel_id: String,
+    pub value: f64,
+    pub source: String,  // manual, pid, autopilot, safety, ...
+    pub timestamp: u128, // unix time in milliseconds
+}
+
+pub fn is_bound(channel: &ManualChannel) -> bool {
+    channel.modbus.is_some()
+        || channel.http.is_some()
+        || channel.tc4.is_some()
+        || channel.mqtt.is_some()
+}
+
+pub fn unix_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+// each actuator has its own lock, a slow output only holds up its own channel
+pub struct Outputs {
+    actuators: HashMap<String, Arc<Mutex<Box<dyn Actuator + Send>>>>,
+    last_write: HashMap<String, Instant>,
+    latest_request: HashMap<String, u64>,
+    next_request: u64,
+    log: Vec<OutputChange>,
+}
+
+impl Outputs {
+    pub fn new() -> Self {
+        Self {
+            actuators: HashMap::new(),
+            last_write: HashMap::new(),
+            latest_request: HashMap::new(),
+            next_request: 0,
+            log: Vec::new(),
+        }
+    }
+
+    // build an actuator for every bound manual channel,
+    // modbus outputs go through the reader loop owning the serial port
+    pub fn connect(&mut self, config: &Config, write_sender: &mpsc::Sender<WriteRequest>) {
+        self.actuators.clear();
+        self.last_write.clear();
+
+        for channel in config.manual_channel.iter().flatten() {
+            let actuator: Box<dyn Actuator + Send> = if channel.modbus.is_some() {
+                Box::new(modbus::ModbusActuator::new(channel, write_sender))
+            } else if let Some(target) = &channel.http {
+                Box::new(http::HttpActuator::new(channel, target))
+            } else if let Some(target) = &channel.tc4 {
+                match tc4::Tc4Actuator::new(target) {
+                    Ok(a) => Box::new(a),
+                    Err(e) => {
+                        warn!("failed to open tc4 output {} : {}", channel.channel_id, e);
+                        continue;
+                    }
+                }
+            } else if let Some(target) = &channel.mqtt {
+                Box::new(mqtt::MqttActuator::new(target))
+            } else {
+                continue;
+            };
+
+            self.actuators
+                .insert(channel.channel_id.clone(), Arc::new(Mutex::new(actuator)));
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        self.actuators.clear();
+    }
+
+    pub fn log(&self) -> &Vec<OutputChange> {
+        &self.log
+    }
+
+    pub fn reset_log(&mut self) {
+        self.log.clear();
+    }
+
+    // last value sent to the channel, or its default before any
+    pub fn last_value(&self, channel: &ManualChannel) -> f64 {
+        self.log
+            .iter()
+            .rev()
+            .find(|c| c.channel_id == channel.channel_id)
+            .map(|c| c.value)
+            .unwrap_or(channel.default_value as f64)
+    }
+}
+
+// clamp value to the channel range and send it to its actuator.
+// calls faster than rate_limit are held back, and only the latest of them
+// is written; superseded ones return Ok(None), as do unbound channels.
+// an actuator not confirming within SET_TIMEOUT fails the call
+pub async fn set_output(
+    outputs: &Mutex<Outputs>,
+    channel: &ManualChannel,
+    value: f64,
+    source: &str,
+) -> Result<Option<OutputChange>, Error> {
+    if !is_bound(channel) {
+        return Ok(None);
+    }
+
+    let value = value.clamp(channel.min as f64, channel.max as f64);
+    let rate_limit = Duration::from_millis(channel.rate_limit.unwrap_or(0));
+    let id = &channel.channel_id;
+
+    let (request, wait) = {
+        let mut o = outputs.lock().await;
+        o.next_request += 1;
+        let request = o.next_request;
+        o.latest_request.insert(id.clone(), request);
+
+        let wait = match o.last_write.get(id) {
+            Some(t) => (*t + rate_limit).saturating_duration_since(Instant::now()),
+            None => Duration::ZERO,
+        };
+        (request, wait)
+    };
+
+    if !wait.is_zero() {
+        sleep(wait).await;
+    }
+
+    let actuator = {
+        let o = outputs.lock().await;
+        if o.latest_request.get(id) != Some(&request) {
+            return Ok(None);
+        }
+        o.actuators.get(id).cloned().ok_or(Error::new(
+            ErrorKind::NotConnected,
+            "outputs are not connected, turn the device on first",
+        ))?
+    };
+
+    // the outputs stay unlocked while the actuator is written
+    let mut actuator = actuator.lock().await;
+    timeout(SET_TIMEOUT, actuator.set(value))
+        .await
+        .map_err(|_| {
+            Error::new(
+                ErrorKind::TimedOut,
+                format!("no confirmation in {} s", SET_TIMEOUT.as_secs()),
+            )
+        })??;
+
+    let mut o = outputs.lock().await;
+    o.last_write.insert(id.clone(), Instant::now());
+
+    let change = OutputChange {
+        channel_id: id.clone(),
+        value,
+        source: source.to_string(),
+        timestamp: unix_millis(),
+    };
+    info!("output {} set to {} by {}", id, value, source);
+    o.log.push(change.clone());
+
+    Ok(Some(change))
+}
+
+// set_output for automated sources, reporting to the webview instead of a caller
+pub async fn apply_output(
+    app: &AppHandle,
+    outputs: &Mutex<Outputs>,
+    channel: &ManualChannel,
+    value: f64,
+    source: &str,
+) -> Option<OutputChange> {
+    match set_output(outputs, channel, value, source).await {
+        Ok(Some(change)) => {
+            app.emit_all("output_changed", change.clone()).unwrap();
+            Some(change)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            let message = format!("{} failed to set {} : {}", source, channel.channel_id, e);
+            warn!("{}", message);
+            app.emit_all("log_event", message).unwrap();
+            None
+        }
+    }
+}
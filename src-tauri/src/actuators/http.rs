@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use async_trait::async_trait;
+use serde_json::json;
+use std::io::{Error, ErrorKind};
+
+use super::Actuator;
+use crate::config::{HttpTarget, ManualChannel};
+
+pub struct HttpActuator {
+    channel_id: String,
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpActuator {
+    pub fn new(channel: &ManualChannel, target: &HttpTarget) -> HttpActuator {
+        HttpActuator {
+            channel_id: channel.channel_id.clone(),
+            url: target.url.clone(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Actuator for HttpActuator {
+    async fn set(self: &mut Self, value: f64) -> Result<(), Error> {
+        let body = json!({ "channel_id": self.channel_id, "value": value });
+
+        let res = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::Other,
+                format!("{} answered {}", self.url, res.status()),
+            ))
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use async_trait::async_trait;
+use std::io::{Error, ErrorKind};
+use tokio::sync::{mpsc, oneshot};
+
+use super::Actuator;
+use crate::config::ManualChannel;
+use crate::devices::WriteRequest;
+
+pub struct ModbusActuator {
+    channel: ManualChannel,
+    write_sender: mpsc::Sender<WriteRequest>,
+}
+
+impl ModbusActuator {
+    pub fn new(
+        channel: &ManualChannel,
+        write_sender: &mpsc::Sender<WriteRequest>,
+    ) -> ModbusActuator {
+        ModbusActuator {
+            channel: channel.clone(),
+            write_sender: write_sender.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Actuator for ModbusActuator {
+    async fn set(self: &mut Self, value: f64) -> Result<(), Error> {
+        let (reply, confirmation) = oneshot::channel();
+
+        self.write_sender
+            .send(WriteRequest {
+                channel: self.channel.clone(),
+                value,
+                reply,
+            })
+            .await
+            .map_err(|_| Error::new(ErrorKind::NotConnected, "device is not running"))?;
+
+        match confirmation.await {
+            Ok(res) => res,
+            Err(_) => Err(Error::new(
+                ErrorKind::NotConnected,
+                "device stopped before writing",
+            )),
+        }
+    }
+}
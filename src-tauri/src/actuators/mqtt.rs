@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use async_trait::async_trait;
+use log::debug;
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::Actuator;
+use crate::config::MqttTarget;
+
+// publishes the value as text to the topic, MQTT 3.1.1 with QoS 0
+pub struct MqttActuator {
+    target: MqttTarget,
+    stream: Option<TcpStream>,
+}
+
+impl MqttActuator {
+    pub fn new(target: &MqttTarget) -> MqttActuator {
+        MqttActuator {
+            target: target.clone(),
+            stream: None,
+        }
+    }
+
+    async fn connect(&self) -> Result<TcpStream, Error> {
+        let mut stream = TcpStream::connect((self.target.ip.as_str(), self.target.port)).await?;
+
+        let client_id = self
+            .target
+            .client_id
+            .clone()
+            .unwrap_or(String::from("roastcraft"));
+
+        // protocol name, level 4, clean session, keep alive disabled
+        let mut body = vec![0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02, 0x00, 0x00];
+        push_str(&mut body, &client_id);
+        stream.write_all(&packet(0x10, &body)).await?;
+
+        let mut connack = [0u8; 4];
+        tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut connack))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "no CONNACK from broker"))??;
+
+        if connack[0] != 0x20 || connack[3] != 0 {
+            return Err(Error::new(
+                ErrorKind::ConnectionRefused,
+                format!("broker refused connection, code {}", connack[3]),
+            ));
+        }
+
+        debug!(
+            "connected to mqtt broker {}:{}",
+            self.target.ip, self.target.port
+        );
+        Ok(stream)
+    }
+
+    async fn publish(&mut self, payload: &str) -> Result<(), Error> {
+        if self.stream.is_none() {
+            self.stream = Some(self.connect().await?);
+        }
+
+        let mut body = Vec::new();
+        push_str(&mut body, &self.target.topic);
+        body.extend_from_slice(payload.as_bytes());
+
+        let res = self
+            .stream
+            .as_mut()
+            .unwrap()
+            .write_all(&packet(0x30, &body))
+            .await;
+        if res.is_err() {
+            self.stream = None;
+        }
+        res
+    }
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+
+    // variable length encoding of the remaining length
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+
+    packet.extend_from_slice(body);
+    packet
+}
+
+#[async_trait]
+impl Actuator for MqttActuator {
+    async fn set(self: &mut Self, value: f64) -> Result<(), Error> {
+        let payload = value.to_string();
+
+        // the broker may have dropped an idle connection, reconnect once
+        match self.publish(&payload).await {
+            Ok(()) => Ok(()),
+            Err(_) => self.publish(&payload).await,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use async_trait::async_trait;
+use std::io::Error;
+use std::time::Duration;
+
+use super::Actuator;
+use crate::config::{Serial, Tc4Target};
+use crate::devices::serial::SerialWorker;
+
+// TC4 / aArtisanQ_PID firmware, e.g. "OT1;40\n" sets heater duty to 40%
+pub struct Tc4Actuator {
+    worker: SerialWorker,
+    command: String,
+}
+
+impl Tc4Actuator {
+    pub fn new(target: &Tc4Target) -> Result<Tc4Actuator, Error> {
+        let serial = Serial {
+            port: target.port.clone(),
+            baud_rate: target.baud_rate,
+            data_bits: 8,
+            parity: String::from("none"),
+            stop_bits: 1,
+            modbus: None,
+            ta612c: None,
+        };
+
+        Ok(Tc4Actuator {
+            worker: SerialWorker::open(&serial)?,
+            command: target.command.clone().unwrap_or(String::from("OT1")),
+        })
+    }
+}
+
+#[async_trait]
+impl Actuator for Tc4Actuator {
+    async fn set(self: &mut Self, value: f64) -> Result<(), Error> {
+        let line = format!("{};{}\n", self.command, value.round() as i64);
+
+        self.worker
+            .transact(Duration::from_secs(2), move |stream| {
+                stream.write_all(line.as_bytes())?;
+                stream.flush()
+            })
+            .await
+    }
+}
@@ -0,0 +1,282 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::actuators::unix_millis;
+use crate::config::{AlarmOutput, AlarmRule, Config};
+use crate::roastfile::ROAST_EVENT_IDS;
+
+// what the webview shows for each rule
+#[derive(Serialize, Clone)]
+pub struct AlarmState {
+    pub label: String,
+    pub triggered: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AlarmFired {
+    pub index: usize,
+    pub label: String,
+    pub source: String,
+    pub value: f64, // value of the source when fired
+    pub sound: bool,
+    pub roast_event: Option<String>,
+    pub timestamp: u128, // unix time in milliseconds
+}
+
+struct Rule {
+    rule: AlarmRule,
+    label: String,
+    armed: bool,
+    triggered: bool,
+}
+
+// evaluates the [[alarm]] rules on every sample of the reader loop
+pub struct AlarmEngine {
+    rules: Vec<Rule>,
+    roast_start: Option<Instant>, // zero of the session timer
+    events: HashMap<String, f64>, // roast event id, seconds on the timer
+}
+
+impl AlarmEngine {
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            roast_start: None,
+            events: HashMap::new(),
+        }
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        // the old alarms list, BT temperatures spoken after TP
+        let legacy = config.alarms.iter().map(|t| AlarmRule {
+            label: Some(format!("{}°", t)),
+            source: String::from("BT"),
+            condition: String::from("above"),
+            value: *t as f64,
+            hysteresis: None,
+            after: Some(String::from("TP")),
+            repeat: None,
+            sound: Some(true),
+            set_output: None,
+            roast_event: None,
+        });
+
+        self.rules = legacy
+            .chain(config.alarm.iter().flatten().cloned())
+            .map(|mut rule| {
+                let label = rule
+                    .label
+                    .clone()
+                    .unwrap_or(format!("{} {} {}", rule.source, rule.condition, rule.value));
+                if let Some(id) = &rule.roast_event {
+                    if !ROAST_EVENT_IDS.contains(&id.as_str()) {
+                        warn!("alarm {} : unknown roast event {}", label, id);
+                        rule.roast_event = None;
+                    }
+                }
+                Rule {
+                    label,
+                    rule,
+                    armed: true,
+                    triggered: false,
+                }
+            })
+            .collect();
+    }
+
+    pub fn reset(&mut self) {
+        for r in self.rules.iter_mut() {
+            r.armed = true;
+            r.triggered = false;
+        }
+    }
+
+    // roast events of the session, timer is the session time in seconds.
+    // no events means the roast was reset
+    pub fn set_roast_events(&mut self, timer: f64, events: HashMap<String, f64>) {
+        self.roast_start = Instant::now().checked_sub(Duration::from_secs_f64(timer.max(0.0)));
+        if events.is_empty() && !self.events.is_empty() {
+            self.reset();
+        }
+        self.events = events;
+    }
+
+    pub fn states(&self) -> Vec<AlarmState> {
+        self.rules
+            .iter()
+            .map(|r| AlarmState {
+                label: r.label.clone(),
+                triggered: r.triggered,
+            })
+            .collect()
+    }
+
+    // output changes of the fired alarm
+    pub fn output(&self, index: usize) -> Option<AlarmOutput> {
+        self.rules.get(index)?.rule.set_output.clone()
+    }
+
+    // percent of the roast so far spent in a phase, like calculatePhases()
+    fn phase_percent(&self, from: &str, to: &str, now: f64) -> Option<f64> {
+        let charge = *self.events.get("CHARGE")?;
+        let end = self.events.get("DROP").copied().unwrap_or(now);
+        let total = end - charge;
+        if total <= 0.0 {
+            return None;
+        }
+
+        let start = match self.events.get(from) {
+            Some(t) => *t,
+            None => return Some(0.0),
+        };
+        let stop = self.events.get(to).copied().unwrap_or(end);
+        Some((stop - start).max(0.0) / total * 100.0)
+    }
+
+    fn source_value(
+        &self,
+        source: &str,
+        channels: &Value,
+        ror: &BTreeMap<String, f64>,
+    ) -> Option<f64> {
+        let now = || self.roast_start.map(|s| s.elapsed().as_secs_f64());
+        match source {
+            "time" => Some(now()? - self.events.get("CHARGE")?),
+            "dry_percent" => self.phase_percent("CHARGE", "DRY_END", now()?),
+            "mai_percent" => self.phase_percent("DRY_END", "FC_START", now()?),
+            "dev_percent" => self.phase_percent("FC_START", "DROP", now()?),
+            s => match s.strip_suffix("_ror") {
+                Some(channel_id) => ror.get(channel_id).copied(),
+                None => channels.get(s)?.as_f64(),
+            },
+        }
+    }
+
+    // feed a read_channels payload and the current RoR of the analytics,
+    // returns the alarms fired by it
+    pub fn sample(&mut self, channels: &Value, ror: &BTreeMap<String, f64>) -> Vec<AlarmFired> {
+        let values: Vec<Option<f64>> = self
+            .rules
+            .iter()
+            .map(|r| self.source_value(&r.rule.source, channels, ror))
+            .collect();
+
+        let mut fired = Vec::new();
+        for (index, (r, v)) in self.rules.iter_mut().zip(values).enumerate() {
+            let v = match v {
+                Some(v) => v,
+                None => continue,
+            };
+            if let Some(after) = &r.rule.after {
+                if !self.events.contains_key(after) {
+                    continue;
+                }
+            }
+
+            let hysteresis = r.rule.hysteresis.unwrap_or(0.0);
+            let (hit, rearm) = if r.rule.condition == "below" {
+                (v <= r.rule.value, v > r.rule.value + hysteresis)
+            } else {
+                (v >= r.rule.value, v < r.rule.value - hysteresis)
+            };
+
+            if r.armed && hit {
+                r.armed = false;
+                r.triggered = true;
+                info!("alarm {} fired at {}", r.label, v);
+                fired.push(AlarmFired {
+                    index,
+                    label: r.label.clone(),
+                    source: r.rule.source.clone(),
+                    value: v,
+                    sound: r.rule.sound.unwrap_or(false),
+                    roast_event: r.rule.roast_event.clone(),
+                    timestamp: unix_millis(),
+                });
+            } else if !r.armed && rearm && r.rule.repeat.unwrap_or(false) {
+                r.armed = true;
+            }
+        }
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn engine(rules: &str) -> AlarmEngine {
+        let config: Config = toml::from_str(&format!(
+            "version = 'v1'\nbrand = ''\nmodel = ''\ntemperature_unit = 'C'\nalarms = []\n{}",
+            rules
+        ))
+        .unwrap();
+        let mut engine = AlarmEngine::new();
+        engine.configure(&config);
+        engine
+    }
+
+    fn fired(engine: &mut AlarmEngine, bt: f64) -> Vec<String> {
+        engine
+            .sample(&json!({ "BT": bt }), &BTreeMap::new())
+            .into_iter()
+            .map(|a| a.label)
+            .collect()
+    }
+
+    #[test]
+    fn repeat_rearms_past_the_hysteresis() {
+        let mut engine = engine(
+            "[[alarm]]\nlabel = 'hot'\nsource = 'BT'\ncondition = 'above'\nvalue = 200.0\n\
+             hysteresis = 5.0\nrepeat = true\n",
+        );
+        assert!(fired(&mut engine, 199.0).is_empty());
+        assert_eq!(fired(&mut engine, 201.0), ["hot"]);
+        assert!(fired(&mut engine, 202.0).is_empty());
+        // back under the value, but not by the hysteresis
+        assert!(fired(&mut engine, 197.0).is_empty());
+        assert!(fired(&mut engine, 201.0).is_empty());
+        assert!(fired(&mut engine, 194.0).is_empty());
+        assert_eq!(fired(&mut engine, 201.0), ["hot"]);
+    }
+
+    #[test]
+    fn fires_once_without_repeat_until_reset() {
+        let mut engine = engine(
+            "[[alarm]]\nlabel = 'preheat'\nsource = 'BT'\ncondition = 'above'\nvalue = 150.0\n",
+        );
+        // while preheating, before any roast event
+        assert_eq!(fired(&mut engine, 160.0), ["preheat"]);
+        assert!(fired(&mut engine, 100.0).is_empty());
+        assert!(fired(&mut engine, 160.0).is_empty());
+        assert!(engine.states()[0].triggered);
+
+        // the next roast starts, no roast event was ever marked
+        engine.reset();
+        engine.set_roast_events(0.0, HashMap::new());
+        assert!(!engine.states()[0].triggered);
+        assert_eq!(fired(&mut engine, 160.0), ["preheat"]);
+    }
+
+    #[test]
+    fn after_waits_for_the_roast_event() {
+        let mut engine = engine(
+            "[[alarm]]\nlabel = 'below'\nsource = 'BT'\ncondition = 'below'\nvalue = 100.0\n\
+             after = 'CHARGE'\n",
+        );
+        assert!(fired(&mut engine, 90.0).is_empty());
+        engine.set_roast_events(10.0, HashMap::from([(String::from("CHARGE"), 5.0)]));
+        assert_eq!(fired(&mut engine, 90.0), ["below"]);
+
+        // reset of the session, the events are gone
+        engine.set_roast_events(0.0, HashMap::new());
+        assert!(fired(&mut engine, 90.0).is_empty());
+        assert!(!engine.states()[0].triggered);
+    }
+}
@@ -0,0 +1,735 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::warn;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+use crate::config::{Config, Ror, RorSmoothing};
+use crate::session::Point;
+
+// latest smoothed points left out while recording, they would jump up and down
+const PROVISIONAL_LAG: usize = 3;
+
+// how the filtered RoR of a channel is smoothed
+#[derive(Clone)]
+pub enum Smoothing {
+    Kernel(Vec<f64>), // centered, hann or savitzky_golay, final once half the window is known
+    Ema(f64),         // weight of the newest point
+    Kalman {
+        process_noise: f64,     // variance of the RoR acceleration
+        measurement_noise: f64, // variance of the filtered RoR
+    },
+    Regression(f64), // seconds of RoR the line is fitted to
+}
+
+impl Smoothing {
+    // no [ror.smoothing] is the hann window of smoothing_window samples
+    fn new(config: Option<&RorSmoothing>, default_window: usize) -> Self {
+        let config = match config {
+            Some(config) => config,
+            None => return Smoothing::Kernel(hann_kernel(default_window)),
+        };
+        let window = config.window.unwrap_or(default_window).max(3) | 1;
+        match config.method.as_str() {
+            "hann" => Smoothing::Kernel(hann_kernel(window)),
+            "savitzky_golay" => {
+                Smoothing::Kernel(savitzky_golay_kernel(window, config.order.unwrap_or(2)))
+            }
+            "ema" => Smoothing::Ema(config.alpha.unwrap_or(0.3).clamp(0.01, 1.0)),
+            "kalman" => Smoothing::Kalman {
+                process_noise: config.process_noise.unwrap_or(0.01),
+                measurement_noise: config.measurement_noise.unwrap_or(4.0),
+            },
+            "regression" => Smoothing::Regression(config.span.unwrap_or(15.0).max(1.0)),
+            method => {
+                warn!("unknown RoR smoothing {}, hann used", method);
+                Smoothing::Kernel(hann_kernel(window))
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RorSettings {
+    pub window: usize,         // samples the raw RoR spans
+    pub outlier_window: usize, // RoR before a point its z-score is taken against
+    pub z_score: f64,
+    smoothing: Smoothing,
+    channels: BTreeMap<String, Smoothing>, // channel_id, smoothing of [[ror.channel]]
+}
+
+impl RorSettings {
+    pub fn new(config: Option<&Ror>) -> Self {
+        let window = config.and_then(|r| r.window).unwrap_or(5).max(2);
+        let outlier_window = config.and_then(|r| r.outlier_window).unwrap_or(5).max(1);
+        let z_score = config.and_then(|r| r.outlier_z_score).unwrap_or(3.0);
+        let smoothing_window = config.and_then(|r| r.smoothing_window).unwrap_or(11).max(3) | 1;
+        let smoothing = Smoothing::new(config.and_then(|r| r.smoothing.as_ref()), smoothing_window);
+        let channels = config
+            .and_then(|r| r.channel.as_ref())
+            .iter()
+            .flat_map(|c| c.iter())
+            .map(|c| {
+                (
+                    c.channel_id.clone(),
+                    Smoothing::new(Some(&c.smoothing), smoothing_window),
+                )
+            })
+            .collect();
+        Self {
+            window,
+            outlier_window,
+            z_score,
+            smoothing,
+            channels,
+        }
+    }
+
+    pub fn smoothing(&self, channel_id: &str) -> &Smoothing {
+        self.channels.get(channel_id).unwrap_or(&self.smoothing)
+    }
+}
+
+// the RoR of one channel, new points or whole arrays
+#[derive(Serialize, Clone, Default)]
+pub struct RorPoints {
+    pub replace: bool, // the arrays are whole, not only the new points
+    pub ror: Vec<Point>,
+    pub outliers: Vec<Point>,
+    pub filtered: Vec<Point>,
+    pub smoothed: Vec<Point>, // final, the neighbours on both sides are known
+    pub smoothed_tail: Vec<Point>, // provisional, replaced by the next update
+}
+
+// degrees per minute over the window ending at i, floored to 0.1 like the webview did
+pub fn raw_ror(data: &[Point], i: usize, window: usize) -> f64 {
+    let first = &data[(i + 1).saturating_sub(window)];
+    let last = &data[i];
+    let ror = ((last.value - first.value) / (last.timestamp - first.timestamp) * 60.0 * 10.0)
+        .floor()
+        / 10.0;
+    // the first sample, or two samples at the same time
+    if ror.is_finite() {
+        ror
+    } else {
+        0.0
+    }
+}
+
+// z-score against the RoR before i. a flat window has no deviation, so any
+// change after it is an outlier
+fn is_outlier(ror: &[Point], i: usize, settings: &RorSettings) -> bool {
+    let window = &ror[i.saturating_sub(settings.outlier_window)..i];
+    let n = window.len() as f64;
+    let mean = window.iter().map(|p| p.value).sum::<f64>() / n;
+    let sd = (window.iter().map(|p| (p.value - mean).powi(2)).sum::<f64>() / n).sqrt();
+    (ror[i].value - mean).abs() / sd > settings.z_score
+}
+
+fn hann_kernel(len: usize) -> Vec<f64> {
+    let window: Vec<f64> = (0..len)
+        .map(|i| 0.5 * (1.0 - (2.0 * PI * i as f64 / (len - 1) as f64).cos()))
+        .collect();
+    let sum: f64 = window.iter().sum();
+    window.iter().map(|w| w / sum).collect()
+}
+
+// least squares fit of a polynomial of order to the window, evaluated at its
+// center. the same for every point, so it is a kernel like the hann window
+fn savitzky_golay_kernel(len: usize, order: usize) -> Vec<f64> {
+    let half = (len / 2) as f64;
+    let order = order.min(len - 1);
+    let n = order + 1;
+
+    // normal equations (A^T A) y = e0, A[j][k] = (j - half)^k
+    let mut m: Vec<Vec<f64>> = (0..n)
+        .map(|r| {
+            let mut row: Vec<f64> = (0..n)
+                .map(|c| {
+                    (0..len)
+                        .map(|j| (j as f64 - half).powi((r + c) as i32))
+                        .sum()
+                })
+                .collect();
+            row.push(if r == 0 { 1.0 } else { 0.0 });
+            row
+        })
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|a, b| m[*a][col].abs().total_cmp(&m[*b][col].abs()))
+            .unwrap();
+        m.swap(col, pivot);
+        let pivot = m[col].clone();
+        for (r, row) in m.iter_mut().enumerate() {
+            if r != col {
+                let f = row[col] / pivot[col];
+                for (x, p) in row.iter_mut().zip(pivot.iter()).skip(col) {
+                    *x -= f * p;
+                }
+            }
+        }
+    }
+    let y: Vec<f64> = m
+        .iter()
+        .enumerate()
+        .map(|(r, row)| row[n] / row[r])
+        .collect();
+
+    (0..len)
+        .map(|j| {
+            let x = j as f64 - half;
+            y.iter()
+                .enumerate()
+                .map(|(k, yk)| yk * x.powi(k as i32))
+                .sum()
+        })
+        .collect()
+}
+
+// the ends are mirrored, without repeating the end point
+fn reflect(j: isize, n: usize) -> usize {
+    if n == 1 {
+        return 0;
+    }
+    let period = 2 * (n as isize - 1);
+    let m = j.rem_euclid(period);
+    if m < n as isize {
+        m as usize
+    } else {
+        (period - m) as usize
+    }
+}
+
+// the kernel centered on i, hann or savitzky_golay. it is run backwards, in
+// the order the webview convolution summed the hann window
+fn smoothed(points: &[Point], i: usize, kernel: &[f64]) -> Point {
+    let half = kernel.len() / 2;
+    let value = (0..kernel.len())
+        .map(|k| {
+            let j = reflect(i as isize + k as isize - half as isize, points.len());
+            kernel[kernel.len() - 1 - k] * points[j].value
+        })
+        .sum();
+    Point {
+        timestamp: points[i].timestamp,
+        value,
+    }
+}
+
+// line fitted to the points of the last span seconds, its value at the last
+// one and its slope per second
+pub fn linear_fit(points: &[Point], span: f64) -> (f64, f64) {
+    let last = &points[points.len() - 1];
+    let start = points
+        .iter()
+        .rposition(|p| p.timestamp < last.timestamp - span)
+        .map_or(0, |i| i + 1);
+    let window = &points[start..];
+
+    let n = window.len() as f64;
+    let mean_t = window.iter().map(|p| p.timestamp).sum::<f64>() / n;
+    let mean_v = window.iter().map(|p| p.value).sum::<f64>() / n;
+    let stt: f64 = window.iter().map(|p| (p.timestamp - mean_t).powi(2)).sum();
+    let stv: f64 = window
+        .iter()
+        .map(|p| (p.timestamp - mean_t) * (p.value - mean_v))
+        .sum();
+    let slope = if stt > 0.0 { stv / stt } else { 0.0 };
+    (mean_v + slope * (last.timestamp - mean_t), slope)
+}
+
+fn regression(points: &[Point], span: f64) -> Point {
+    Point {
+        timestamp: points[points.len() - 1].timestamp,
+        value: linear_fit(points, span).0,
+    }
+}
+
+// x is the RoR and its change per second, which only drifts by the process noise
+#[derive(Clone)]
+struct Kalman {
+    x: [f64; 2],
+    p: [[f64; 2]; 2],
+    timestamp: f64,
+}
+
+impl Kalman {
+    fn new(point: &Point, measurement_noise: f64) -> Self {
+        Self {
+            x: [point.value, 0.0],
+            p: [[measurement_noise, 0.0], [0.0, 1.0]],
+            timestamp: point.timestamp,
+        }
+    }
+
+    fn step(&mut self, point: &Point, process_noise: f64, measurement_noise: f64) -> Point {
+        // predict
+        let dt = point.timestamp - self.timestamp;
+        let p = self.p;
+        let fp = [
+            [p[0][0] + dt * p[1][0], p[0][1] + dt * p[1][1]],
+            [p[1][0], p[1][1]],
+        ];
+        let q = process_noise;
+        self.x[0] += dt * self.x[1];
+        self.p = [
+            [
+                fp[0][0] + dt * fp[0][1] + q * dt.powi(4) / 4.0,
+                fp[0][1] + q * dt.powi(3) / 2.0,
+            ],
+            [
+                fp[1][0] + dt * fp[1][1] + q * dt.powi(3) / 2.0,
+                fp[1][1] + q * dt.powi(2),
+            ],
+        ];
+
+        // update with the measured RoR
+        let p = self.p;
+        let s = p[0][0] + measurement_noise;
+        let k = [p[0][0] / s, p[1][0] / s];
+        let y = point.value - self.x[0];
+        self.x[0] += k[0] * y;
+        self.x[1] += k[1] * y;
+        self.p = [
+            [(1.0 - k[0]) * p[0][0], (1.0 - k[0]) * p[0][1]],
+            [p[1][0] - k[1] * p[0][0], p[1][1] - k[1] * p[0][1]],
+        ];
+        self.timestamp = point.timestamp;
+
+        Point {
+            timestamp: point.timestamp,
+            value: self.x[0],
+        }
+    }
+}
+
+struct ChannelRor {
+    data: Vec<Point>,
+    ror: Vec<Point>,
+    outliers: Vec<Point>,
+    filtered: Vec<Point>,
+    smoothing: Smoothing,
+    smoothed: Vec<Point>, // final, later points do not change them
+    kalman: Option<Kalman>,
+}
+
+impl ChannelRor {
+    fn new(smoothing: Smoothing) -> Self {
+        Self {
+            data: Vec::new(),
+            ror: Vec::new(),
+            outliers: Vec::new(),
+            filtered: Vec::new(),
+            smoothing,
+            smoothed: Vec::new(),
+            kalman: None,
+        }
+    }
+
+    // a new sample, which only adds points. the first RoR is neither filtered
+    // nor an outlier, it has nothing to compare with
+    fn push(&mut self, point: Point, settings: &RorSettings) -> RorPoints {
+        let mut new = RorPoints::default();
+
+        self.data.push(point);
+        let i = self.data.len() - 1;
+        let ror = Point {
+            timestamp: self.data[i].timestamp,
+            value: raw_ror(&self.data, i, settings.window),
+        };
+        self.ror.push(ror.clone());
+        new.ror.push(ror.clone());
+
+        if i > 0 {
+            if is_outlier(&self.ror, i, settings) {
+                self.outliers.push(ror.clone());
+                new.outliers.push(ror);
+            } else {
+                self.filtered.push(ror.clone());
+                new.filtered.push(ror);
+                new.smoothed = self.smooth();
+            }
+        }
+        new.smoothed_tail = self.tail();
+        new
+    }
+
+    // smoothed points which became final with the last filtered point
+    fn smooth(&mut self) -> Vec<Point> {
+        let n = self.filtered.len();
+        let last = &self.filtered[n - 1];
+        let new = match &self.smoothing {
+            Smoothing::Kernel(kernel) => (self.smoothed.len()..n.saturating_sub(kernel.len() / 2))
+                .map(|i| smoothed(&self.filtered, i, kernel))
+                .collect(),
+            Smoothing::Ema(alpha) => {
+                let value = match self.smoothed.last() {
+                    Some(p) => alpha * last.value + (1.0 - alpha) * p.value,
+                    None => last.value,
+                };
+                vec![Point {
+                    timestamp: last.timestamp,
+                    value,
+                }]
+            }
+            Smoothing::Kalman {
+                process_noise,
+                measurement_noise,
+            } => match self.kalman.as_mut() {
+                Some(kalman) => vec![kalman.step(last, *process_noise, *measurement_noise)],
+                None => {
+                    self.kalman = Some(Kalman::new(last, *measurement_noise));
+                    vec![last.clone()]
+                }
+            },
+            Smoothing::Regression(span) => vec![regression(&self.filtered, *span)],
+        };
+        self.smoothed.extend(new.iter().cloned());
+        new
+    }
+
+    // provisional points after the final ones, only a centered window has them
+    fn tail(&self) -> Vec<Point> {
+        match &self.smoothing {
+            Smoothing::Kernel(kernel) => (self.smoothed.len()
+                ..self.filtered.len().saturating_sub(PROVISIONAL_LAG))
+                .map(|i| smoothed(&self.filtered, i, kernel))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // everything, the end of a centered window smoothed against its mirror
+    fn whole(&self) -> RorPoints {
+        let mut smoothed_all = self.smoothed.clone();
+        if let Smoothing::Kernel(kernel) = &self.smoothing {
+            smoothed_all.extend(
+                (self.smoothed.len()..self.filtered.len())
+                    .map(|i| smoothed(&self.filtered, i, kernel)),
+            );
+        }
+        RorPoints {
+            replace: true,
+            ror: self.ror.clone(),
+            outliers: self.outliers.clone(),
+            filtered: self.filtered.clone(),
+            smoothed: smoothed_all,
+            smoothed_tail: Vec::new(),
+        }
+    }
+}
+
+// RoR of a whole roast, e.g. a loaded file or a ghost. nothing after DROP
+pub fn compute(
+    channel_id: &str,
+    data: &[Point],
+    drop: Option<f64>,
+    settings: &RorSettings,
+) -> RorPoints {
+    let mut channel = ChannelRor::new(settings.smoothing(channel_id).clone());
+    for p in data
+        .iter()
+        .filter(|p| drop.map_or(true, |d| p.timestamp <= d))
+    {
+        channel.push(p.clone(), settings);
+    }
+    channel.whole()
+}
+
+// RoR of every channel of the session, computed as the samples come in
+pub struct RorAnalytics {
+    settings: RorSettings,
+    channels: BTreeMap<String, ChannelRor>,
+    drop: Option<f64>,
+}
+
+impl RorAnalytics {
+    pub fn new() -> Self {
+        Self {
+            settings: RorSettings::new(None),
+            channels: BTreeMap::new(),
+            drop: None,
+        }
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        self.settings = RorSettings::new(config.ror.as_ref());
+    }
+
+    pub fn settings(&self) -> &RorSettings {
+        &self.settings
+    }
+
+    // RoR without its outliers, up to DROP
+    pub fn filtered(&self, channel_id: &str) -> &[Point] {
+        self.channels
+            .get(channel_id)
+            .map(|c| c.filtered.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // final smoothed RoR, up to DROP
+    pub fn smoothed(&self, channel_id: &str) -> &[Point] {
+        self.channels
+            .get(channel_id)
+            .map(|c| c.smoothed.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // latest smoothed RoR of each channel, provisional while a centered window
+    // waits for its neighbours, the end of the RoR curve in the webview
+    pub fn current(&self) -> BTreeMap<String, f64> {
+        self.channels
+            .iter()
+            .filter_map(|(id, c)| {
+                let tail = c.tail();
+                let last = tail.last().or(c.smoothed.last())?;
+                Some((id.clone(), last.value))
+            })
+            .collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.channels.clear();
+        self.drop = None;
+    }
+
+    // the new points of each channel in the sample
+    pub fn sample(
+        &mut self,
+        timestamp: f64,
+        values: &BTreeMap<String, f64>,
+    ) -> BTreeMap<String, RorPoints> {
+        let mut update = BTreeMap::new();
+        for (id, value) in values.iter() {
+            let settings = &self.settings;
+            let channel = self
+                .channels
+                .entry(id.clone())
+                .or_insert_with(|| ChannelRor::new(settings.smoothing(id).clone()));
+            let point = Point {
+                timestamp,
+                value: *value,
+            };
+
+            // kept, in case DROP is moved later
+            if self.drop.map_or(false, |d| timestamp > d) {
+                channel.data.push(point);
+                continue;
+            }
+            update.insert(id.clone(), channel.push(point, &self.settings));
+        }
+        update
+    }
+
+    // marked or moved, the RoR after it is cut off and the end smoothed
+    pub fn set_drop(&mut self, timestamp: f64) -> BTreeMap<String, RorPoints> {
+        self.drop = Some(timestamp);
+        let mut update = BTreeMap::new();
+        for (id, channel) in self.channels.iter_mut() {
+            let data = std::mem::take(&mut channel.data);
+            let mut recomputed = ChannelRor::new(channel.smoothing.clone());
+            for p in data.iter().filter(|p| p.timestamp <= timestamp) {
+                recomputed.push(p.clone(), &self.settings);
+            }
+            update.insert(id.clone(), recomputed.whole());
+
+            recomputed.data = data;
+            *channel = recomputed;
+        }
+        update
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[derive(Deserialize)]
+    struct Roast {
+        data: Vec<Point>,
+        drop: Option<f64>,
+    }
+
+    #[derive(Deserialize)]
+    struct BaselinePoint {
+        timestamp: f64,
+        value: Option<f64>, // null where the webview had no value
+    }
+
+    // what baseline.js gives, the RoR of the webview before the backend
+    #[derive(Deserialize)]
+    struct Baseline {
+        ror: Vec<Point>,
+        outliers: Vec<Point>,
+        filtered: Vec<Point>,
+        smoothed: Vec<BaselinePoint>,
+    }
+
+    fn fixture<T: for<'de> Deserialize<'de>>(name: &str) -> T {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("samples/ror")
+            .join(name);
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn compute_fixture(name: &str) -> (RorPoints, Baseline) {
+        let roast: Roast = fixture(&format!("{}.json", name));
+        let points = compute("BT", &roast.data, roast.drop, &RorSettings::new(None));
+        (points, fixture(&format!("{}.baseline.json", name)))
+    }
+
+    #[test]
+    fn recorded_roast_is_the_ror_of_the_webview() {
+        let (points, baseline) = compute_fixture("ethiopia_guji_bt");
+        assert!(points.ror == baseline.ror);
+        assert!(!baseline.outliers.is_empty());
+        assert!(points.outliers == baseline.outliers);
+        assert!(points.filtered == baseline.filtered);
+
+        assert_eq!(points.smoothed.len(), baseline.smoothed.len());
+        for (p, b) in points.smoothed.iter().zip(baseline.smoothed.iter()) {
+            assert_eq!(p.timestamp, b.timestamp);
+            let value = b.value.unwrap();
+            assert!(
+                (p.value - value).abs() < 1e-9,
+                "{} : {} != {}",
+                p.timestamp,
+                p.value,
+                value
+            );
+        }
+    }
+
+    // sample by sample like a session, the smoothed RoR is final at DROP
+    #[test]
+    fn recorded_roast_sample_by_sample() {
+        let roast: Roast = fixture("ethiopia_guji_bt.json");
+        let baseline: Baseline = fixture("ethiopia_guji_bt.baseline.json");
+        let drop = roast.drop.unwrap();
+
+        let mut analytics = RorAnalytics::new();
+        for p in roast.data.iter().filter(|p| p.timestamp <= drop) {
+            let values = BTreeMap::from([(String::from("BT"), p.value)]);
+            analytics.sample(p.timestamp, &values);
+        }
+        let points = &analytics.set_drop(drop)["BT"];
+        assert!(points.replace);
+        assert_eq!(points.smoothed.len(), baseline.smoothed.len());
+        for (p, b) in points.smoothed.iter().zip(baseline.smoothed.iter()) {
+            assert_eq!(p.timestamp, b.timestamp);
+            assert!((p.value - b.value.unwrap()).abs() < 1e-9);
+        }
+    }
+
+    // shorter than the hann window, the webview smoothed nothing. the ends are
+    // mirrored as often as needed instead, so every point is a weighted mean
+    #[test]
+    fn short_roast_is_smoothed_where_the_webview_was_not() {
+        let (points, baseline) = compute_fixture("short_bt");
+        assert!(points.ror == baseline.ror);
+        assert!(points.outliers == baseline.outliers);
+        assert!(points.filtered == baseline.filtered);
+        assert!(points.filtered.len() < 11);
+        assert!(baseline.smoothed.iter().all(|b| b.value.is_none()));
+
+        let values = points.filtered.iter().map(|p| p.value);
+        let min = values.clone().fold(f64::INFINITY, f64::min);
+        let max = values.fold(f64::NEG_INFINITY, f64::max);
+        assert_eq!(points.smoothed.len(), points.filtered.len());
+        for (p, f) in points.smoothed.iter().zip(points.filtered.iter()) {
+            assert_eq!(p.timestamp, f.timestamp);
+            assert!(p.value >= min - 1e-9 && p.value <= max + 1e-9);
+        }
+    }
+
+    // every 2 s, value + slope * t
+    fn line(value: f64, slope: f64, n: usize) -> Vec<Point> {
+        (0..n)
+            .map(|i| Point {
+                timestamp: i as f64 * 2.0,
+                value: value + slope * i as f64 * 2.0,
+            })
+            .collect()
+    }
+
+    // the smoothing alone, every point taken as filtered
+    fn smooth(smoothing: Smoothing, points: &[Point]) -> Vec<Point> {
+        let mut channel = ChannelRor::new(smoothing);
+        for p in points {
+            channel.filtered.push(p.clone());
+            channel.smooth();
+        }
+        channel.smoothed
+    }
+
+    fn kalman() -> Smoothing {
+        Smoothing::Kalman {
+            process_noise: 0.01,
+            measurement_noise: 4.0,
+        }
+    }
+
+    #[test]
+    fn kernels_are_weighted_means() {
+        let expected = [-3.0, 12.0, 17.0, 12.0, -3.0].map(|w| w / 35.0);
+        for (w, e) in savitzky_golay_kernel(5, 2).iter().zip(expected.iter()) {
+            assert!((w - e).abs() < 1e-12);
+        }
+
+        for len in (3..=21).step_by(2) {
+            let mut kernels = vec![hann_kernel(len)];
+            kernels.extend((0..=4).map(|order| savitzky_golay_kernel(len, order)));
+            for kernel in kernels {
+                assert_eq!(kernel.len(), len);
+                assert!((kernel.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+                // symmetric, so a line away from the ends is kept as it is
+                let points = line(10.0, 0.5, len + 10);
+                for i in len / 2..points.len() - len / 2 {
+                    let p = smoothed(&points, i, &kernel);
+                    assert!((p.value - points[i].value).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_constant_ror_is_kept() {
+        let points = line(12.0, 0.0, 60);
+        for smoothing in [Smoothing::Ema(0.3), kalman(), Smoothing::Regression(15.0)] {
+            let smoothed = smooth(smoothing, &points);
+            assert_eq!(smoothed.len(), points.len());
+            for (s, p) in smoothed.iter().zip(points.iter()) {
+                assert_eq!(s.timestamp, p.timestamp);
+                assert!((s.value - 12.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn a_falling_ror_is_followed() {
+        let points = line(20.0, -0.05, 200);
+        let last = points.last().unwrap();
+
+        // a straight line is fitted exactly
+        let smoothed = smooth(Smoothing::Regression(15.0), &points);
+        for (s, p) in smoothed.iter().zip(points.iter()) {
+            assert!((s.value - p.value).abs() < 1e-9);
+        }
+
+        // ema lags a constant step behind, slope * dt * (1 - alpha) / alpha
+        let smoothed = smooth(Smoothing::Ema(0.25), &points);
+        let lag = -0.05 * 2.0 * 0.75 / 0.25;
+        assert!((smoothed.last().unwrap().value - (last.value - lag)).abs() < 1e-6);
+
+        // kalman learns the slope and catches up
+        let smoothed = smooth(kalman(), &points);
+        assert!((smoothed.last().unwrap().value - last.value).abs() < 0.01);
+        assert!((smoothed[1].value - points[1].value).abs() > 0.01);
+    }
+}
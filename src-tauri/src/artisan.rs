@@ -0,0 +1,1023 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use serde_json::{json, Map, Number, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::csv;
+use crate::roastfile::{ChannelData, RoastFile};
+use crate::session::{Point, RoastEvent};
+
+// timeindex of an .alog, index into timex of each event
+// default special event types of artisan, the fifth is a plain event
+const ETYPES: [&str; 5] = ["Air", "Drum", "Damper", "Burner", "--"];
+
+const TIMEINDEX_EVENTS: [&str; 7] = [
+    "CHARGE", "DRY_END", "FC_START", "FC_END", "SC_START", "SC_END", "DROP",
+];
+
+// parser of the python literal an .alog is written as, str() of a dict
+struct PyParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    pos: usize,
+}
+
+impl<'a> PyParser<'a> {
+    fn new(content: &'a str) -> Self {
+        Self {
+            chars: content.chars().peekable(),
+            pos: 0,
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        self.pos += 1;
+        self.chars.next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("{} at character {}", msg, self.pos)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', found end", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some('{') => self.dict(),
+            Some('[') => self.sequence('[', ']'),
+            Some('(') => self.sequence('(', ')'),
+            Some('\'') | Some('"') => Ok(Value::String(self.string()?)),
+            Some(c) if c == '-' || c == '+' || c == '.' || c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.word(),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn dict(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut map = Map::new();
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek() == Some(&'}') {
+                self.next();
+                return Ok(Value::Object(map));
+            }
+
+            let key = match self.value()? {
+                Value::String(s) => s,
+                v => v.to_string(),
+            };
+            self.expect(':')?;
+            let value = self.value()?;
+            map.insert(key, value);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => (),
+                Some('}') => return Ok(Value::Object(map)),
+                _ => return Err(self.error("expected ',' or '}' in dict")),
+            }
+        }
+    }
+
+    // lists and tuples, both become arrays
+    fn sequence(&mut self, open: char, close: char) -> Result<Value, String> {
+        self.expect(open)?;
+        let mut arr = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek() == Some(&close) {
+                self.next();
+                return Ok(Value::Array(arr));
+            }
+
+            arr.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => (),
+                Some(c) if c == close => return Ok(Value::Array(arr)),
+                _ => return Err(self.error(&format!("expected ',' or '{}'", close))),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.next().ok_or_else(|| self.error("unexpected end"))?;
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') => {
+                    let c = self
+                        .next()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    match c {
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        '0' => s.push('\0'),
+                        'x' => s.push(self.hex_char(2)?),
+                        'u' => s.push(self.hex_char(4)?),
+                        'U' => s.push(self.hex_char(8)?),
+                        '\n' => (),     // line continuation
+                        c => s.push(c), // \\ \' \"
+                    }
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn hex_char(&mut self, digits: usize) -> Result<char, String> {
+        let hex: String = (0..digits).filter_map(|_| self.next()).collect();
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(&format!("invalid escape {}", hex)))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut text = String::new();
+        while let Some(c) = self.chars.peek() {
+            if c.is_ascii_alphanumeric() || *c == '.' || *c == '-' || *c == '+' {
+                text.push(*c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+
+        if let Ok(i) = text.parse::<i64>() {
+            return Ok(Value::Number(Number::from(i)));
+        }
+        match text.parse::<f64>() {
+            // nan and inf have no json, they are missing values
+            Ok(f) => Ok(Number::from_f64(f)
+                .map(Value::Number)
+                .unwrap_or(Value::Null)),
+            Err(_) => Err(self.error(&format!("invalid number {}", text))),
+        }
+    }
+
+    // True, False, None, nan, inf and string prefixes like u'...'
+    fn word(&mut self) -> Result<Value, String> {
+        let mut word = String::new();
+        while let Some(c) = self.chars.peek() {
+            if c.is_ascii_alphanumeric() || *c == '_' {
+                word.push(*c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+
+        match word.as_str() {
+            "True" => Ok(Value::Bool(true)),
+            "False" => Ok(Value::Bool(false)),
+            "None" | "nan" | "inf" => Ok(Value::Null),
+            "u" | "b" | "r" | "ur" | "br" | "rb"
+                if matches!(self.chars.peek(), Some('\'') | Some('"')) =>
+            {
+                Ok(Value::String(self.string()?))
+            }
+            w => Err(self.error(&format!("unexpected {}", w))),
+        }
+    }
+}
+
+// the dict of an .alog as json
+pub fn parse_python_literal(content: &str) -> Result<Value, String> {
+    let mut parser = PyParser::new(content);
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.chars.peek().is_some() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+fn floats(alog: &Value, key: &str) -> Vec<Option<f64>> {
+    alog.get(key)
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().map(|v| v.as_f64()).collect())
+        .unwrap_or_default()
+}
+
+fn text(alog: &Value, key: &str) -> String {
+    alog.get(key)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string()
+}
+
+fn number(v: Option<&Value>) -> f64 {
+    v.and_then(|v| match v {
+        Value::String(s) => s.trim().parse().ok(),
+        v => v.as_f64(),
+    })
+    .unwrap_or(0.0)
+}
+
+fn grams(unit: &str) -> f64 {
+    match unit.to_lowercase().as_str() {
+        "kg" => 1000.0,
+        "lb" => 453.59237,
+        "oz" => 28.349523,
+        _ => 1.0,
+    }
+}
+
+fn millilitres(unit: &str) -> f64 {
+    match unit.to_lowercase().as_str() {
+        "l" => 1000.0,
+        "gal" => 3785.41,
+        "qt" => 946.353,
+        "cup" => 236.588,
+        _ => 1.0,
+    }
+}
+
+// [weight, weight unit, volume, volume unit], to g/l
+fn density(v: Option<&Value>) -> f64 {
+    let d = match v.and_then(|v| v.as_array()) {
+        Some(d) => d,
+        None => return 0.0,
+    };
+    let unit = |i: usize, default: &str| {
+        d.get(i)
+            .and_then(|v| v.as_str())
+            .unwrap_or(default)
+            .to_string()
+    };
+    let weight = number(d.first()) * grams(&unit(1, "g"));
+    let volume = number(d.get(2)) * millilitres(&unit(3, "l"));
+    if volume > 0.0 {
+        weight / volume * 1000.0
+    } else {
+        0.0
+    }
+}
+
+// artisan writes -1 for a reading it does not have
+fn points(
+    timex: &[Option<f64>],
+    temps: &[Option<f64>],
+    convert: &dyn Fn(f64) -> f64,
+) -> Vec<Point> {
+    timex
+        .iter()
+        .zip(temps)
+        .filter_map(|(t, v)| match (t, v) {
+            (Some(t), Some(v)) if *v != -1.0 => Some(Point {
+                timestamp: *t,
+                value: convert(*v),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// artisan keeps slider values of special events as v / 10 + 1
+fn external_event_value(v: f64) -> f64 {
+    if v > -1.1 && v < 1.1 {
+        0.0
+    } else if v >= 1.1 {
+        (v * 10.0).round() - 10.0
+    } else {
+        (v * 10.0).round() + 10.0
+    }
+}
+
+fn internal_event_value(v: f64) -> f64 {
+    if v == 0.0 {
+        1.0
+    } else if v > 0.0 {
+        v / 10.0 + 1.0
+    } else {
+        v / 10.0 - 1.0
+    }
+}
+
+// special event type names of artisan, to our manual channel ids
+fn manual_channel_id(etype: &str) -> String {
+    match etype.to_lowercase().as_str() {
+        "burner" => String::from("gas"),
+        "air" => String::from("airflow"),
+        s => s.to_string(),
+    }
+}
+
+// an artisan roast in the roast file model, temperatures in temperature_unit
+pub fn import(content: &str, temperature_unit: &str) -> Result<RoastFile, String> {
+    let alog = parse_python_literal(content)?;
+    if !alog.is_object() {
+        return Err(String::from("alog is not a dict"));
+    }
+
+    let mode = text(&alog, "mode");
+    let convert: Box<dyn Fn(f64) -> f64> = match (mode.as_str(), temperature_unit) {
+        ("F", "C") => Box::new(|v| (v - 32.0) * 5.0 / 9.0),
+        ("C", "F") => Box::new(|v| v * 9.0 / 5.0 + 32.0),
+        _ => Box::new(|v| v),
+    };
+
+    let timex = floats(&alog, "timex");
+    let et = floats(&alog, "temp1");
+    let bt = floats(&alog, "temp2");
+
+    let mut roast = RoastFile::new();
+    roast.channel_arr.push(ChannelData {
+        id: String::from("BT"),
+        data_arr: points(&timex, &bt, &convert),
+    });
+    roast.channel_arr.push(ChannelData {
+        id: String::from("ET"),
+        data_arr: points(&timex, &et, &convert),
+    });
+
+    // extra devices, two curves each
+    let extratimex = alog.get("extratimex").and_then(|v| v.as_array());
+    for (i, t) in extratimex.into_iter().flatten().enumerate() {
+        let t: Vec<Option<f64>> = t
+            .as_array()
+            .map(|arr| arr.iter().map(|v| v.as_f64()).collect())
+            .unwrap_or_default();
+        for n in ["1", "2"] {
+            let name = alog
+                .get(format!("extraname{}", n))
+                .and_then(|v| v.get(i))
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let temps: Vec<Option<f64>> = alog
+                .get(format!("extratemp{}", n))
+                .and_then(|v| v.get(i))
+                .and_then(|v| v.as_array())
+                .map(|arr| arr.iter().map(|v| v.as_f64()).collect())
+                .unwrap_or_default();
+            let data_arr = points(&t, &temps, &convert);
+            if !name.is_empty() && !data_arr.is_empty() && roast.channel(name).is_none() {
+                roast.channel_arr.push(ChannelData {
+                    id: name.to_string(),
+                    data_arr,
+                });
+            }
+        }
+    }
+
+    // roast events, 0 is unset except for CHARGE which uses -1
+    let timeindex: Vec<i64> = alog
+        .get("timeindex")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().map(|v| v.as_i64().unwrap_or(0)).collect())
+        .unwrap_or_default();
+    for (i, id) in TIMEINDEX_EVENTS.iter().enumerate() {
+        let index = match timeindex.get(i) {
+            Some(index) if *index > 0 || (i == 0 && *index == 0) => *index as usize,
+            _ => continue,
+        };
+        if let (Some(Some(timestamp)), Some(Some(value))) = (timex.get(index), bt.get(index)) {
+            roast.roast_events.insert(
+                id.to_string(),
+                RoastEvent {
+                    id: id.to_string(),
+                    timestamp: *timestamp,
+                    value: convert(*value),
+                },
+            );
+        }
+    }
+
+    // TP is computed by artisan, relative to CHARGE
+    let computed = alog.get("computed");
+    let tp_time = computed
+        .and_then(|c| c.get("TP_time"))
+        .and_then(|v| v.as_f64());
+    let tp_bt = computed
+        .and_then(|c| c.get("TP_BT"))
+        .and_then(|v| v.as_f64());
+    if let (Some(charge), Some(tp_time), Some(tp_bt)) =
+        (roast.roast_events.get("CHARGE"), tp_time, tp_bt)
+    {
+        let tp = RoastEvent {
+            id: String::from("TP"),
+            timestamp: charge.timestamp + tp_time,
+            value: convert(tp_bt),
+        };
+        roast.roast_events.insert(String::from("TP"), tp);
+    }
+
+    // special events, e.g. burner and air sliders
+    let etypes: Vec<String> = alog
+        .get("etypes")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .map(|v| v.as_str().unwrap_or("").to_string())
+                .collect()
+        })
+        .unwrap_or(ETYPES.iter().map(|e| e.to_string()).collect());
+    let specialevents = floats(&alog, "specialevents");
+    let specialeventstype = floats(&alog, "specialeventstype");
+    let specialeventsvalue = floats(&alog, "specialeventsvalue");
+
+    let mut manual_channels: BTreeMap<String, Vec<Point>> = BTreeMap::new();
+    for ((index, etype), value) in specialevents
+        .iter()
+        .zip(specialeventstype.iter())
+        .zip(specialeventsvalue.iter())
+    {
+        let (index, etype, value) = match (index, etype, value) {
+            (Some(i), Some(e), Some(v)) => (*i as usize, *e as usize, *v),
+            _ => continue,
+        };
+        let name = match etypes.get(etype) {
+            Some(name) if !name.is_empty() => name,
+            _ => continue, // "--", a plain event without a value
+        };
+        if let Some(Some(timestamp)) = timex.get(index) {
+            manual_channels
+                .entry(manual_channel_id(name))
+                .or_default()
+                .push(Point {
+                    timestamp: *timestamp,
+                    value: external_event_value(value),
+                });
+        }
+    }
+    roast.manual_channel_arr = manual_channels
+        .into_iter()
+        .map(|(id, mut data_arr)| {
+            data_arr.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+            ChannelData { id, data_arr }
+        })
+        .collect();
+
+    // bean and roast metadata
+    roast.title = text(&alog, "title");
+    let notes: Vec<String> = [text(&alog, "beans"), text(&alog, "roastingnotes")]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect();
+    roast.notes = notes.join("\n");
+
+    if let Some(weight) = alog.get("weight").and_then(|v| v.as_array()) {
+        let unit = grams(weight.get(2).and_then(|v| v.as_str()).unwrap_or("g"));
+        roast.weight_green = number(weight.first()) * unit;
+        roast.weight_roasted = number(weight.get(1)) * unit;
+    }
+    if let Some(volume) = alog.get("volume").and_then(|v| v.as_array()) {
+        let unit = millilitres(volume.get(2).and_then(|v| v.as_str()).unwrap_or("l"));
+        roast.volume_green = number(volume.first()) * unit;
+        roast.volume_roasted = number(volume.get(1)) * unit;
+    }
+    roast.density_green = density(alog.get("density"));
+    roast.density_roasted = density(alog.get("density_roasted"));
+    roast.moisture_green = number(alog.get("moisture_greens"));
+    roast.moisture_roasted = number(alog.get("moisture_roasted"));
+    roast.color_whole = number(alog.get("whole_color"));
+    roast.color_ground = number(alog.get("ground_color"));
+    roast.roast_epoch = alog
+        .get("roastepoch")
+        .and_then(|v| v.as_u64())
+        .map(|e| e * 1000);
+    roast.batch = match alog.get("roastbatchnr").and_then(|v| v.as_u64()) {
+        Some(nr) if nr > 0 => Some(format!("{}{}", text(&alog, "roastbatchprefix"), nr)),
+        _ => None,
+    };
+
+    roast.validate()?;
+    Ok(roast)
+}
+
+pub fn load(path: &Path, temperature_unit: &str) -> Result<RoastFile, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let roast =
+        import(&content, temperature_unit).map_err(|e| format!("{} : {}", path.display(), e))?;
+    info!("artisan roast imported : {}", path.display());
+    Ok(roast)
+}
+
+// str() of a python dict, the way artisan writes an .alog
+fn write_python_literal(v: &Value, out: &mut String) {
+    match v {
+        Value::Null => out.push_str("None"),
+        Value::Bool(true) => out.push_str("True"),
+        Value::Bool(false) => out.push_str("False"),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => {
+            out.push('\'');
+            for c in s.chars() {
+                match c {
+                    '\\' => out.push_str("\\\\"),
+                    '\'' => out.push_str("\\'"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+            out.push('\'');
+        }
+        Value::Array(arr) => {
+            out.push('[');
+            for (i, v) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_python_literal(v, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            out.push('{');
+            for (i, (k, v)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_python_literal(&Value::String(k.clone()), out);
+                out.push_str(": ");
+                write_python_literal(v, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+// first index of timex at or after timestamp
+fn index_of(timex: &[f64], timestamp: f64) -> usize {
+    timex
+        .partition_point(|t| *t < timestamp)
+        .min(timex.len().saturating_sub(1))
+}
+
+// our manual channel ids, to slots of the artisan special event types
+fn etype_slots(manual_channel_arr: &[ChannelData]) -> (Vec<String>, BTreeMap<String, usize>) {
+    let mut etypes: Vec<String> = ETYPES.iter().map(|e| e.to_string()).collect();
+    let mut slots = BTreeMap::new();
+
+    for mc in manual_channel_arr {
+        if let Some(slot) = etypes
+            .iter()
+            .take(4)
+            .position(|e| manual_channel_id(e) == mc.id)
+        {
+            slots.insert(mc.id.clone(), slot);
+        }
+    }
+    for mc in manual_channel_arr {
+        if slots.contains_key(&mc.id) {
+            continue;
+        }
+        let free = (0..4).find(|slot| !slots.values().any(|s| s == slot));
+        match free {
+            Some(slot) => {
+                etypes[slot] = mc.id.clone();
+                slots.insert(mc.id.clone(), slot);
+            }
+            None => warn!(
+                "artisan has 4 special event types, {} is not exported",
+                mc.id
+            ),
+        }
+    }
+    (etypes, slots)
+}
+
+// a date as QDate.toString() in artisan, e.g. "Tue Mar 12 2024". iso_date is
+// the local date of unix_millis
+fn text_date(iso_date: &str, unix_millis: u64, utc_offset: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    // 1970-01-01 was a thursday
+    let days = (unix_millis as i64 / 1000 + utc_offset * 60).div_euclid(86400);
+    let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+    let month = iso_date[5..7].parse::<usize>().unwrap_or(1);
+    let day = iso_date[8..10].parse::<u32>().unwrap_or(1);
+    format!(
+        "{} {} {} {}",
+        weekday,
+        MONTHS[month - 1],
+        day,
+        &iso_date[..4]
+    )
+}
+
+// a roast as an .alog. the temperatures are written as they are, in the unit
+// of the roast's config, or temperature_unit of this machine for a roast without.
+// utc_offset in minutes, for the local date and time of the roast
+pub fn export(
+    roast: &RoastFile,
+    temperature_unit: &str,
+    utc_offset: i64,
+) -> Result<String, String> {
+    let mode = roast
+        .config
+        .as_ref()
+        .map(|c| c.temperature_unit.as_str())
+        .filter(|u| *u == "C" || *u == "F")
+        .unwrap_or(temperature_unit);
+
+    let bt = roast.channel("BT").ok_or("no BT channel")?;
+    if bt.data_arr.is_empty() {
+        return Err(String::from("no BT data"));
+    }
+
+    // artisan has one time axis, every reading of every channel is on it
+    let timex = roast.timeline();
+    let curve = |id: &str| -> Vec<f64> {
+        match roast.channel(id) {
+            Some(c) => timex
+                .iter()
+                .map(|t| c.value_at(*t).unwrap_or(-1.0))
+                .collect(),
+            None => vec![-1.0; timex.len()],
+        }
+    };
+    let temp1 = curve("ET");
+    let temp2 = curve("BT");
+
+    // extra channels, two per artisan extra device
+    let extra: Vec<&ChannelData> = roast
+        .channel_arr
+        .iter()
+        .filter(|c| c.id != "BT" && c.id != "ET")
+        .collect();
+    let devices: Vec<(&ChannelData, Option<&ChannelData>)> = extra
+        .chunks(2)
+        .map(|pair| (pair[0], pair.get(1).copied()))
+        .collect();
+    let extraname1: Vec<String> = devices.iter().map(|(c, _)| c.id.clone()).collect();
+    let extraname2: Vec<String> = devices
+        .iter()
+        .map(|(_, c)| c.map(|c| c.id.clone()).unwrap_or_default())
+        .collect();
+    let extratemp1: Vec<Vec<f64>> = devices.iter().map(|(c, _)| curve(&c.id)).collect();
+    let extratemp2: Vec<Vec<f64>> = devices
+        .iter()
+        .map(|(_, c)| match c {
+            Some(c) => curve(&c.id),
+            None => vec![-1.0; timex.len()],
+        })
+        .collect();
+
+    // roast events, CHARGE unset is -1, the others 0
+    let event_index = |id: &str| {
+        roast
+            .roast_events
+            .get(id)
+            .map(|e| index_of(&timex, e.timestamp) as i64)
+    };
+    let mut timeindex: Vec<i64> = TIMEINDEX_EVENTS
+        .iter()
+        .map(|id| event_index(id).unwrap_or(0))
+        .collect();
+    timeindex[0] = event_index("CHARGE").unwrap_or(-1);
+    timeindex.push(0); // COOL
+
+    let mut computed = Map::new();
+    if let Some(charge) = roast.roast_events.get("CHARGE") {
+        computed.insert(String::from("CHARGE_BT"), json!(charge.value));
+        for (id, key) in [
+            ("DRY_END", "DRY_time"),
+            ("FC_START", "FCs_time"),
+            ("FC_END", "FCe_time"),
+            ("SC_START", "SCs_time"),
+            ("SC_END", "SCe_time"),
+            ("DROP", "DROP_time"),
+        ] {
+            if let Some(e) = roast.roast_events.get(id) {
+                computed.insert(key.to_string(), json!(e.timestamp - charge.timestamp));
+            }
+        }
+        if let Some(tp) = roast.roast_events.get("TP") {
+            computed.insert(
+                String::from("TP_idx"),
+                json!(index_of(&timex, tp.timestamp)),
+            );
+            computed.insert(
+                String::from("TP_time"),
+                json!(tp.timestamp - charge.timestamp),
+            );
+            computed.insert(String::from("TP_BT"), json!(tp.value));
+        }
+        if let Some(drop) = roast.roast_events.get("DROP") {
+            computed.insert(String::from("DROP_BT"), json!(drop.value));
+            computed.insert(
+                String::from("totaltime"),
+                json!(drop.timestamp - charge.timestamp),
+            );
+        }
+    }
+
+    // manual channel changes, as special events on the artisan sliders
+    let (etypes, slots) = etype_slots(&roast.manual_channel_arr);
+    let mut specialevents: Vec<(usize, usize, f64)> = Vec::new();
+    for mc in roast.manual_channel_arr.iter() {
+        if let Some(slot) = slots.get(&mc.id) {
+            for p in mc.data_arr.iter() {
+                specialevents.push((index_of(&timex, p.timestamp), *slot, p.value));
+            }
+        }
+    }
+    specialevents.sort_by_key(|(index, slot, _)| (*index, *slot));
+
+    let roaster_type = roast
+        .config
+        .as_ref()
+        .map(|c| format!("{} {}", c.brand, c.model))
+        .unwrap_or_default();
+    // artisan numbers batches, the prefix is what comes before the digits
+    let batch = roast.batch.clone().unwrap_or_default();
+    let digits = batch.len() - batch.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (batch_prefix, batch_nr) = batch.split_at(batch.len() - digits);
+    // artisan has one line for the beans, imported back into the notes
+    let beans: Vec<&str> = [roast.country.as_str(), roast.process.as_str()]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect();
+    let weight_unit = "g";
+    let mut alog = json!({
+        "roastertype": roaster_type,
+        "mode": mode,
+        "title": roast.title,
+        "roastbatchprefix": batch_prefix,
+        "roastbatchnr": batch_nr.parse::<u64>().unwrap_or(0),
+        "beans": beans.join(", "),
+        "roastingnotes": roast.notes,
+        "weight": [roast.weight_green, roast.weight_roasted, weight_unit],
+        "volume": [roast.volume_green / 1000.0, roast.volume_roasted / 1000.0, "l"],
+        "density": [roast.density_green, weight_unit, 1.0, "l"],
+        "density_roasted": [roast.density_roasted, weight_unit, 1.0, "l"],
+        "moisture_greens": roast.moisture_green,
+        "moisture_roasted": roast.moisture_roasted,
+        "whole_color": roast.color_whole.round() as i64,
+        "ground_color": roast.color_ground.round() as i64,
+        "timex": timex,
+        "temp1": temp1,
+        "temp2": temp2,
+        "timeindex": timeindex,
+        "computed": computed,
+        "etypes": etypes,
+        "specialevents": specialevents.iter().map(|e| e.0).collect::<Vec<_>>(),
+        "specialeventstype": specialevents.iter().map(|e| e.1).collect::<Vec<_>>(),
+        "specialeventsvalue": specialevents.iter().map(|e| internal_event_value(e.2)).collect::<Vec<_>>(),
+        "specialeventsStrings": specialevents.iter().map(|_| "").collect::<Vec<_>>(),
+        "extradevices": devices.iter().map(|_| 25).collect::<Vec<_>>(), // virtual device
+        "extraname1": extraname1,
+        "extraname2": extraname2,
+        "extratimex": devices.iter().map(|_| timex.clone()).collect::<Vec<_>>(),
+        "extratemp1": extratemp1,
+        "extratemp2": extratemp2,
+        "extramathexpression1": devices.iter().map(|_| "").collect::<Vec<_>>(),
+        "extramathexpression2": devices.iter().map(|_| "").collect::<Vec<_>>(),
+        "extradevicecolor1": devices.iter().map(|_| "black").collect::<Vec<_>>(),
+        "extradevicecolor2": devices.iter().map(|_| "black").collect::<Vec<_>>(),
+    });
+
+    // artisan dates the roast by roastepoch, the rest is what it shows
+    if let (Some(epoch), Some(alog)) = (roast.roast_epoch, alog.as_object_mut()) {
+        let clock = csv::clock(epoch, utc_offset);
+        let (date, time) = clock.split_at(10);
+        alog.insert(String::from("roastepoch"), json!(epoch / 1000));
+        alog.insert(String::from("roasttzoffset"), json!(-utc_offset * 60));
+        alog.insert(String::from("roastisodate"), json!(date));
+        alog.insert(String::from("roasttime"), json!(time.trim()));
+        alog.insert(
+            String::from("roastdate"),
+            json!(text_date(date, epoch, utc_offset)),
+        );
+    }
+
+    let mut out = String::new();
+    write_python_literal(&alog, &mut out);
+    Ok(out)
+}
+
+pub fn save(
+    path: &Path,
+    roast: &RoastFile,
+    temperature_unit: &str,
+    utc_offset: i64,
+) -> Result<(), String> {
+    let content = export(roast, temperature_unit, utc_offset)?;
+    fs::write(path, content).map_err(|e| e.to_string())?;
+    info!("artisan roast exported : {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn sample(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("samples/artisan")
+            .join(name)
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    fn assert_event(roast: &RoastFile, id: &str, timestamp: f64, value: f64) {
+        let e = roast.roast_events.get(id).expect(id);
+        assert_close(e.timestamp, timestamp);
+        assert_close(e.value, value);
+    }
+
+    #[test]
+    fn imports_celsius_sample() {
+        let roast = load(&sample("ethiopia_guji_celsius.alog"), "C").unwrap();
+
+        let ids: Vec<&str> = roast.channel_arr.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["BT", "ET", "Inlet", "Exhaust"]);
+        // a -1 of a dropped BT reading is left out
+        assert_eq!(roast.channel("BT").unwrap().data_arr.len(), 600);
+        assert_eq!(roast.channel("ET").unwrap().data_arr.len(), 601);
+        assert_close(roast.channel("Inlet").unwrap().data_arr[1].value, 260.1);
+        assert_close(roast.channel("Exhaust").unwrap().data_arr[2].value, 150.2);
+
+        assert_event(&roast, "CHARGE", 30.0, 200.0);
+        assert_event(&roast, "TP", 105.0, 92.0);
+        assert_event(&roast, "DRY_END", 300.0, 138.8);
+        assert_event(&roast, "FC_START", 470.0, 176.6);
+        assert_event(&roast, "FC_END", 520.0, 187.1);
+        assert_event(&roast, "DROP", 560.0, 195.4);
+        assert_eq!(roast.roast_events.len(), 6);
+
+        // burner and air sliders
+        let gas = &roast.manual_channel_arr[1];
+        assert_eq!(gas.id, "gas");
+        let values: Vec<(f64, f64)> = gas
+            .data_arr
+            .iter()
+            .map(|p| (p.timestamp, p.value))
+            .collect();
+        assert_eq!(
+            values,
+            [(32.0, 80.0), (200.0, 70.0), (400.0, 55.0), (480.0, 30.0)]
+        );
+        let airflow = &roast.manual_channel_arr[0];
+        assert_eq!(airflow.id, "airflow");
+        assert_eq!(airflow.data_arr.len(), 2);
+        assert_close(airflow.data_arr[1].value, 50.0);
+
+        assert_eq!(roast.title, "Ethiopia Guji");
+        assert!(roast
+            .notes
+            .starts_with("Ethiopia Guji Hambela natural, G1\n"));
+        assert_close(roast.weight_green, 250.0);
+        assert_close(roast.weight_roasted, 212.0);
+        assert_eq!(roast.batch.as_deref(), Some("12"));
+        assert_eq!(roast.roast_epoch, Some(1_710_238_502_000));
+    }
+
+    #[test]
+    fn imports_fahrenheit_sample_in_celsius() {
+        let roast = load(&sample("colombia_huila_fahrenheit.alog"), "C").unwrap();
+
+        let bt = &roast.channel("BT").unwrap().data_arr;
+        assert_eq!(bt.len(), 721);
+        assert_close(bt[0].value, (400.0 - 32.0) * 5.0 / 9.0);
+        assert_close(bt[2].value, (383.9 - 32.0) * 5.0 / 9.0);
+        assert_close(
+            roast.channel("ET").unwrap().data_arr[720].value,
+            (434.4 - 32.0) * 5.0 / 9.0,
+        );
+
+        // CHARGE at index 0 is set, the others at 0 are not
+        assert_event(&roast, "CHARGE", 0.0, (400.0 - 32.0) * 5.0 / 9.0);
+        assert_event(&roast, "DRY_END", 360.0, (292.7 - 32.0) * 5.0 / 9.0);
+        assert_event(&roast, "FC_START", 560.0, (357.9 - 32.0) * 5.0 / 9.0);
+        assert_event(&roast, "DROP", 690.0, (396.0 - 32.0) * 5.0 / 9.0);
+        assert_eq!(roast.roast_events.len(), 4);
+        assert!(roast.manual_channel_arr.is_empty());
+
+        assert_close(roast.weight_green, 453.59237);
+        assert_close(roast.weight_roasted, 0.86 * 453.59237);
+        assert_eq!(roast.notes, "Huila café, washed");
+        assert_eq!(roast.batch, None);
+    }
+
+    #[test]
+    fn imports_fahrenheit_sample_as_is() {
+        let roast = load(&sample("colombia_huila_fahrenheit.alog"), "F").unwrap();
+        assert_close(roast.channel("BT").unwrap().data_arr[1].value, 391.8);
+        assert_event(&roast, "DROP", 690.0, 396.0);
+    }
+
+    #[test]
+    fn export_then_import_keeps_the_roast() {
+        let mut roast = load(&sample("ethiopia_guji_celsius.alog"), "C").unwrap();
+        roast.country = String::from("Ethiopia");
+        roast.process = String::from("Natural");
+        roast.notes = String::from("sweet");
+
+        let exported = export(&roast, "C", 60).unwrap();
+        let alog = parse_python_literal(&exported).unwrap();
+        assert_eq!(alog["mode"], "C");
+        assert_eq!(alog["beans"], "Ethiopia, Natural");
+        assert_eq!(alog["roasttzoffset"], -3600);
+
+        let back = import(&exported, "C").unwrap();
+        for c in roast.channel_arr.iter() {
+            let b = back.channel(&c.id).expect(&c.id);
+            assert_eq!(b.data_arr.len(), c.data_arr.len(), "{}", c.id);
+            for (p, q) in c.data_arr.iter().zip(b.data_arr.iter()) {
+                assert_close(p.timestamp, q.timestamp);
+                assert_close(p.value, q.value);
+            }
+        }
+        assert_eq!(back.roast_events.len(), roast.roast_events.len());
+        for (id, e) in roast.roast_events.iter() {
+            assert_event(&back, id, e.timestamp, e.value);
+        }
+        let ids = |r: &RoastFile| -> Vec<(String, usize)> {
+            r.manual_channel_arr
+                .iter()
+                .map(|c| (c.id.clone(), c.data_arr.len()))
+                .collect()
+        };
+        assert_eq!(ids(&back), ids(&roast));
+        assert_close(back.manual_channel_arr[1].data_arr[2].value, 55.0);
+
+        assert_eq!(back.title, roast.title);
+        assert_eq!(back.notes, "Ethiopia, Natural\nsweet");
+        assert_close(back.weight_green, roast.weight_green);
+        assert_close(back.weight_roasted, roast.weight_roasted);
+        assert_close(back.volume_green, roast.volume_green);
+        assert_eq!(back.batch, roast.batch);
+        assert!(roast.roast_epoch.is_some());
+        assert_eq!(back.roast_epoch, roast.roast_epoch);
+    }
+
+    #[test]
+    fn export_dates_the_roast_in_local_time() {
+        let mut roast = load(&sample("ethiopia_guji_celsius.alog"), "C").unwrap();
+        // 2024-03-12 23:30:05 UTC
+        roast.roast_epoch = Some(1710286205000);
+
+        let alog = parse_python_literal(&export(&roast, "C", 0).unwrap()).unwrap();
+        assert_eq!(alog["roastepoch"], 1710286205);
+        assert_eq!(alog["roastisodate"], "2024-03-12");
+        assert_eq!(alog["roasttime"], "23:30:05");
+        assert_eq!(alog["roastdate"], "Tue Mar 12 2024");
+
+        // past midnight an hour east
+        let alog = parse_python_literal(&export(&roast, "C", 60).unwrap()).unwrap();
+        assert_eq!(alog["roastepoch"], 1710286205);
+        assert_eq!(alog["roastisodate"], "2024-03-13");
+        assert_eq!(alog["roasttime"], "00:30:05");
+        assert_eq!(alog["roastdate"], "Wed Mar 13 2024");
+
+        roast.roast_epoch = None;
+        let alog = parse_python_literal(&export(&roast, "C", 0).unwrap()).unwrap();
+        assert!(alog.get("roastepoch").is_none());
+    }
+
+    #[test]
+    fn export_mode_is_the_unit_of_the_roast() {
+        let mut roast = load(&sample("colombia_huila_fahrenheit.alog"), "F").unwrap();
+        let mut config = crate::config::Config::new();
+        config.temperature_unit = String::from("F");
+        roast.config = Some(config);
+
+        // exported on a machine in celsius, the values stay fahrenheit
+        let exported = export(&roast, "C", 0).unwrap();
+        let alog = parse_python_literal(&exported).unwrap();
+        assert_eq!(alog["mode"], "F");
+        let back = import(&exported, "F").unwrap();
+        assert_close(back.channel("BT").unwrap().data_arr[1].value, 391.8);
+
+        // a roast without its config is in the unit of the machine
+        roast.config = None;
+        let alog = parse_python_literal(&export(&roast, "F", 0).unwrap()).unwrap();
+        assert_eq!(alog["mode"], "F");
+    }
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[derive(Deserialize, Clone)]
+pub struct ProfilePoint {
+    pub timestamp: f64, // seconds from CHARGE
+    pub value: f64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ProfileManualChannel {
+    pub channel_id: String,
+    pub data: Vec<ProfilePoint>,
+}
+
+// the ghost roast, sent by the webview with timestamps aligned to its CHARGE
+#[derive(Deserialize, Clone)]
+pub struct Profile {
+    pub bt: Vec<ProfilePoint>,
+    pub ror: Vec<ProfilePoint>,
+    pub manual_channels: Vec<ProfileManualChannel>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AutopilotState {
+    Off,
+    Running,
+    Paused,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AutopilotStatus {
+    pub state: AutopilotState,
+    pub time: f64,
+    pub set_point: Option<f64>,
+    pub replay: Vec<String>,
+}
+
+// value of a curve at time t, linear between points, None past the end
+fn interpolate(curve: &[ProfilePoint], t: f64) -> Option<f64> {
+    let first = curve.first()?;
+    if t <= first.timestamp {
+        return Some(first.value);
+    }
+
+    let i = curve.iter().position(|p| p.timestamp >= t)?;
+    let (p0, p1) = (&curve[i - 1], &curve[i]);
+    if p1.timestamp <= p0.timestamp {
+        return Some(p1.value);
+    }
+    Some(p0.value + (p1.value - p0.value) * (t - p0.timestamp) / (p1.timestamp - p0.timestamp))
+}
+
+// follows a saved profile, its BT or RoR curve becomes the pid set point,
+// and its manual channel changes can be replayed at the same times
+pub struct Autopilot {
+    profile: Option<Profile>,
+    state: AutopilotState,
+    resumed: Instant,
+    time_at_resume: f64,            // profile time when started or last resumed
+    replay: HashMap<String, usize>, // channel_id, index of the next change
+    set_point: Option<f64>,
+}
+
+impl Autopilot {
+    pub fn new() -> Self {
+        Self {
+            profile: None,
+            state: AutopilotState::Off,
+            resumed: Instant::now(),
+            time_at_resume: 0.0,
+            replay: HashMap::new(),
+            set_point: None,
+        }
+    }
+
+    // time is the current roast time from CHARGE, in seconds.
+    // replay channels start at the value the profile had at that time
+    pub fn start(&mut self, profile: Profile, time: f64, replay: &[String]) -> Result<(), String> {
+        if profile.bt.is_empty() {
+            return Err(String::from("profile has no BT curve"));
+        }
+
+        self.replay.clear();
+        for id in replay {
+            if let Some(mc) = profile
+                .manual_channels
+                .iter()
+                .find(|mc| &mc.channel_id == id)
+            {
+                let passed = mc.data.iter().filter(|p| p.timestamp <= time).count();
+                self.replay.insert(id.clone(), passed.saturating_sub(1));
+            }
+        }
+
+        self.profile = Some(profile);
+        self.time_at_resume = time;
+        self.resumed = Instant::now();
+        self.set_point = None;
+        self.state = AutopilotState::Running;
+        info!("autopilot started at {:.0} s", time);
+        Ok(())
+    }
+
+    pub fn pause(&mut self) -> Result<(), String> {
+        match self.state {
+            AutopilotState::Running => {
+                self.time_at_resume = self.time();
+                self.state = AutopilotState::Paused;
+                info!("autopilot paused at {:.0} s", self.time_at_resume);
+                Ok(())
+            }
+            _ => Err(String::from("autopilot is not running")),
+        }
+    }
+
+    pub fn resume(&mut self) -> Result<(), String> {
+        match self.state {
+            AutopilotState::Paused => {
+                self.resumed = Instant::now();
+                self.state = AutopilotState::Running;
+                info!("autopilot resumed at {:.0} s", self.time_at_resume);
+                Ok(())
+            }
+            _ => Err(String::from("autopilot is not paused")),
+        }
+    }
+
+    // back to manual, outputs stay where they are
+    pub fn hand_over(&mut self) {
+        if self.state != AutopilotState::Off {
+            info!("autopilot handed over to manual at {:.0} s", self.time());
+        }
+        self.state = AutopilotState::Off;
+        self.replay.clear();
+        self.set_point = None;
+    }
+
+    // stop replaying one channel, after the roaster touched it
+    pub fn release(&mut self, channel_id: &str) -> bool {
+        self.replay.remove(channel_id).is_some()
+    }
+
+    pub fn state(&self) -> AutopilotState {
+        self.state
+    }
+
+    pub fn time(&self) -> f64 {
+        match self.state {
+            AutopilotState::Running => self.time_at_resume + self.resumed.elapsed().as_secs_f64(),
+            _ => self.time_at_resume,
+        }
+    }
+
+    // set point for now, from the RoR curve if ror is true else from BT.
+    // None once the profile has ended
+    pub fn set_point(&mut self, ror: bool) -> Option<f64> {
+        let t = self.time();
+        let profile = self.profile.as_ref()?;
+        let curve = if ror { &profile.ror } else { &profile.bt };
+
+        self.set_point = interpolate(curve, t);
+        self.set_point
+    }
+
+    // manual channel changes of the profile which are due, latest one per channel
+    pub fn due_changes(&mut self) -> Vec<(String, f64)> {
+        let t = self.time();
+        let mut changes = Vec::new();
+
+        let profile = match &self.profile {
+            Some(p) => p,
+            None => return changes,
+        };
+
+        for (id, next) in self.replay.iter_mut() {
+            let data = match profile
+                .manual_channels
+                .iter()
+                .find(|mc| &mc.channel_id == id)
+            {
+                Some(mc) => &mc.data,
+                None => continue,
+            };
+
+            let mut due = None;
+            while *next < data.len() && data[*next].timestamp <= t {
+                due = Some(data[*next].value);
+                *next += 1;
+            }
+            if let Some(value) = due {
+                changes.push((id.clone(), value));
+            }
+        }
+        changes
+    }
+
+    pub fn status(&self) -> AutopilotStatus {
+        AutopilotStatus {
+            state: self.state,
+            time: self.time(),
+            set_point: self.set_point,
+            replay: self.replay.keys().cloned().collect(),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::actuators::unix_millis;
+use crate::config;
+use crate::csv;
+use crate::roastfile::RoastFile;
+
+const DEFAULT_TEMPLATE: &str = "{date}_{batch}_{title}";
+const DEFAULT_DELAY: u64 = 30;
+
+// what the webview knows of the roast beside the recording, field names as in the roast file
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RoastNotes {
+    pub title: String,
+    pub country: String,
+    pub process: String,
+    pub notes: String,
+    pub weight_green: f64,
+    pub weight_roasted: f64,
+    pub volume_green: f64,
+    pub volume_roasted: f64,
+    pub density_green: f64,
+    pub density_roasted: f64,
+    pub moisture_green: f64,
+    pub moisture_roasted: f64,
+    pub color_whole: f64,
+    pub color_ground: f64,
+    pub flavor_list: Vec<String>,
+    pub green_lot: Option<u64>,
+}
+
+impl RoastNotes {
+    pub fn apply(&self, roast: &mut RoastFile) {
+        roast.title = self.title.clone();
+        roast.country = self.country.clone();
+        roast.process = self.process.clone();
+        roast.notes = self.notes.clone();
+        roast.weight_green = self.weight_green;
+        roast.weight_roasted = self.weight_roasted;
+        roast.volume_green = self.volume_green;
+        roast.volume_roasted = self.volume_roasted;
+        roast.density_green = self.density_green;
+        roast.density_roasted = self.density_roasted;
+        roast.moisture_green = self.moisture_green;
+        roast.moisture_roasted = self.moisture_roasted;
+        roast.color_whole = self.color_whole;
+        roast.color_ground = self.color_ground;
+        roast.flavor_list = self.flavor_list.clone();
+        roast.green_lot = self.green_lot;
+    }
+}
+
+// snapshots are saved apart from the state, one at a time, and never over
+// a later one saved first
+#[derive(Default)]
+pub struct SnapshotOrder {
+    taken: u64,
+    saved: u64,
+}
+
+impl SnapshotOrder {
+    pub fn take(&mut self) -> u64 {
+        self.taken += 1;
+        self.taken
+    }
+
+    // false when a later snapshot was saved already
+    pub fn save(&mut self, snapshot: u64) -> bool {
+        if snapshot < self.saved {
+            return false;
+        }
+        self.saved = snapshot;
+        true
+    }
+}
+
+// when and where the roast of the session is saved without asking
+pub struct Autosave {
+    notes: RoastNotes,
+    utc_offset: i64, // minutes, for the date in the file name
+    due: Option<Instant>,
+    path: Option<PathBuf>, // fixed by the first save, later saves update it
+}
+
+impl Autosave {
+    pub fn new() -> Self {
+        Self {
+            notes: RoastNotes::default(),
+            utc_offset: 0,
+            due: None,
+            path: None,
+        }
+    }
+
+    // a new roast, notes are kept since the webview only sends changes
+    pub fn start(&mut self, utc_offset: i64) {
+        self.utc_offset = utc_offset;
+        self.due = None;
+        self.path = None;
+    }
+
+    pub fn reset(&mut self) {
+        self.due = None;
+        self.path = None;
+    }
+
+    pub fn notes(&self) -> &RoastNotes {
+        &self.notes
+    }
+
+    // returns true when the roast was saved already and its file needs the change
+    pub fn set_notes(&mut self, notes: RoastNotes) -> bool {
+        self.notes = notes;
+        self.path.is_some()
+    }
+
+    // at DROP, leaving time to mark the last events
+    pub fn schedule(&mut self, config: &config::Autosave) {
+        let delay = config.delay.unwrap_or(DEFAULT_DELAY);
+        self.due = Some(Instant::now() + Duration::from_secs(delay));
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.due.map_or(false, |due| Instant::now() >= due)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.due.is_some()
+    }
+
+    // where to save, named by the template on the first save
+    pub fn path(&mut self, config: &config::Autosave, roast: &RoastFile) -> PathBuf {
+        self.due = None;
+        if let Some(path) = &self.path {
+            return path.clone();
+        }
+
+        let template = config.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+        let epoch = roast.roast_epoch.unwrap_or_else(|| unix_millis() as u64);
+        let name = file_name(template, roast, &csv::clock(epoch, self.utc_offset));
+        let path = unique_path(Path::new(&config.dir), &name);
+        self.path = Some(path.clone());
+        path
+    }
+}
+
+// clock is YYYY-MM-DD HH:MM:SS
+fn file_name(template: &str, roast: &RoastFile, clock: &str) -> String {
+    let name = template
+        .replace("{date}", &clock[..10])
+        .replace("{time}", &clock[11..16].replace(':', ""))
+        .replace("{batch}", roast.batch.as_deref().unwrap_or(""))
+        .replace("{title}", &roast.title);
+
+    // characters windows does not allow, and separators left by empty fields
+    let mut name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    while name.contains("__") {
+        name = name.replace("__", "_");
+    }
+    let name = name.trim_matches(|c: char| c == '_' || c == '-' || c.is_whitespace());
+    if name.is_empty() {
+        String::from("roast")
+    } else {
+        name.to_string()
+    }
+}
+
+// never overwrite a roast saved before
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.join(format!("{}.json", name));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{}_{}.json", name, n));
+        n += 1;
+    }
+    path
+}
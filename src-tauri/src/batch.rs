@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::actuators::unix_millis;
+use crate::config::Batch;
+use crate::roastfile::write_json_atomic;
+
+// a roast planned for the production day
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlannedBatch {
+    #[serde(default)]
+    pub id: u64, // assigned by the queue
+    pub title: String,
+    pub green_lot: Option<u64>,  // id in the green coffee inventory
+    pub target_weight: f64,      // green, grams
+    pub profile: Option<String>, // roast file loaded as the ghost
+}
+
+// what a started roast is, returned to the webview
+#[derive(Serialize, Clone)]
+pub struct BatchStart {
+    pub batch: String,
+    pub planned: Option<PlannedBatch>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Stored {
+    last_number: u64,
+    last_day: i64, // local days since the unix epoch
+    planned: Vec<PlannedBatch>,
+    next_id: u64,
+}
+
+// the batch counter and the roast queue, kept as json next to the app data
+pub struct BatchQueue {
+    path: Option<PathBuf>,
+    stored: Stored,
+    current: Option<u64>, // planned batch being roasted, taken off the queue at DROP
+}
+
+impl BatchQueue {
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            stored: Stored::default(),
+            current: None,
+        }
+    }
+
+    pub fn open(&mut self, dir: &Path) {
+        let path = dir.join("batch.json");
+        self.stored = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("batch queue {} unreadable : {}", path.display(), e);
+                Stored::default()
+            }),
+            Err(_) => Stored::default(),
+        };
+        info!(
+            "batch queue opened : last batch {}, {} planned",
+            self.stored.last_number,
+            self.stored.planned.len()
+        );
+        self.path = Some(path);
+    }
+
+    fn store(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("batch queue is not open")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        write_json_atomic(path, &self.stored)
+    }
+
+    // number the roast being started, utc_offset in minutes gives the local day.
+    // planned is the queued batch loaded in the webview, None for a roast off the queue
+    pub fn start(
+        &mut self,
+        config: Option<&Batch>,
+        utc_offset: i64,
+        planned: Option<u64>,
+    ) -> BatchStart {
+        let day = (unix_millis() as i64 / 1000 + utc_offset * 60).div_euclid(86400);
+        let daily_reset = config.and_then(|b| b.daily_reset).unwrap_or(false);
+        if daily_reset && day != self.stored.last_day {
+            self.stored.last_number = 0;
+        }
+        self.stored.last_number += 1;
+        self.stored.last_day = day;
+
+        let prefix = config.and_then(|b| b.prefix.clone()).unwrap_or_default();
+        let digits = config.and_then(|b| b.digits).unwrap_or(3);
+        let batch = format!(
+            "{}{:0width$}",
+            prefix,
+            self.stored.last_number,
+            width = digits
+        );
+
+        let planned = planned.and_then(|id| {
+            let planned = self.stored.planned.iter().find(|p| p.id == id).cloned();
+            if planned.is_none() {
+                warn!("planned batch {} is no longer in the queue", id);
+            }
+            planned
+        });
+        self.current = planned.as_ref().map(|p| p.id);
+        if let Err(e) = self.store() {
+            warn!("failed to store batch counter : {}", e);
+        }
+        info!("batch {} started", batch);
+        BatchStart { batch, planned }
+    }
+
+    // the planned batch is done, returns the next one. None when no planned
+    // batch was being roasted, or DROP was only moved
+    pub fn dropped(&mut self) -> Option<Option<PlannedBatch>> {
+        let id = self.current.take()?;
+        self.stored.planned.retain(|p| p.id != id);
+        if let Err(e) = self.store() {
+            warn!("failed to store batch queue : {}", e);
+        }
+        info!("planned batch {} done", id);
+        Some(self.stored.planned.first().cloned())
+    }
+
+    pub fn list(&self) -> Vec<PlannedBatch> {
+        self.stored.planned.clone()
+    }
+
+    pub fn add(&mut self, mut planned: PlannedBatch) -> Result<PlannedBatch, String> {
+        self.stored.next_id = self.stored.next_id.max(1);
+        planned.id = self.stored.next_id;
+        self.stored.next_id += 1;
+        self.stored.planned.push(planned.clone());
+        self.store()?;
+        Ok(planned)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Result<(), String> {
+        let len = self.stored.planned.len();
+        self.stored.planned.retain(|p| p.id != id);
+        if self.stored.planned.len() == len {
+            return Err(format!("no planned batch {}", id));
+        }
+        if self.current == Some(id) {
+            self.current = None;
+        }
+        self.store()
+    }
+
+    // reorder, index is the new position in the queue
+    pub fn move_to(&mut self, id: u64, index: usize) -> Result<(), String> {
+        let i = self
+            .stored
+            .planned
+            .iter()
+            .position(|p| p.id == id)
+            .ok_or(format!("no planned batch {}", id))?;
+        let planned = self.stored.planned.remove(i);
+        let index = index.min(self.stored.planned.len());
+        self.stored.planned.insert(index, planned);
+        self.store()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planned(title: &str) -> PlannedBatch {
+        PlannedBatch {
+            id: 0,
+            title: title.to_string(),
+            green_lot: None,
+            target_weight: 500.0,
+            profile: None,
+        }
+    }
+
+    #[test]
+    fn only_the_loaded_batch_leaves_the_queue() {
+        let dir = std::env::temp_dir().join(format!("roastcraft-batch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut queue = BatchQueue::new();
+        queue.open(&dir);
+        let first = queue.add(planned("first")).unwrap();
+        let second = queue.add(planned("second")).unwrap();
+
+        // a roast off the queue does not take its head
+        let start = queue.start(None, 0, None);
+        assert_eq!(start.batch, "001");
+        assert!(start.planned.is_none());
+        assert!(queue.dropped().is_none());
+        assert_eq!(queue.list().len(), 2);
+
+        let start = queue.start(None, 0, Some(second.id));
+        assert_eq!(start.planned.map(|p| p.title), Some(String::from("second")));
+        let next = queue.dropped().unwrap().unwrap();
+        assert_eq!(next.id, first.id);
+
+        // removed from the queue after it was loaded
+        let start = queue.start(None, 0, Some(second.id));
+        assert_eq!(start.batch, "003");
+        assert!(start.planned.is_none());
+        assert_eq!(queue.list().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
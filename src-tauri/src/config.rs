@@ -8,10 +8,20 @@ pub struct Config {
     pub brand: String,
     pub model: String,
     pub temperature_unit: String,
-    pub alarms: Vec<i32>,
+    pub alarms: Vec<i32>, // BT temperatures after TP, shorthand for [[alarm]]
+    pub alarm: Option<Vec<AlarmRule>>,
     pub serial: Option<Serial>,
     pub tcp: Option<Tcp>,
     pub manual_channel: Option<Vec<ManualChannel>>,
+    pub pid: Option<Pid>,
+    pub safety: Option<Safety>,
+    pub batch: Option<Batch>,
+    pub autosave: Option<Autosave>,
+    pub ror: Option<Ror>,
+    pub detection: Option<Detection>,
+    pub prediction: Option<Prediction>,
+    pub drop_target: Option<DropTarget>,
+    pub phases: Option<Phases>,
 }
 
 impl Config {
@@ -22,11 +32,71 @@ impl Config {
             model: String::new(),
             temperature_unit: String::new(),
             alarms: Vec::new(),
+            alarm: None,
             serial: None,
             tcp: None,
             manual_channel: None,
+            pid: None,
+            safety: None,
+            batch: None,
+            autosave: None,
+            ror: None,
+            detection: None,
+            prediction: None,
+            drop_target: None,
+            phases: None,
         }
     }
+
+    pub fn manual_channel(&self, channel_id: &str) -> Option<&ManualChannel> {
+        self.manual_channel
+            .iter()
+            .flatten()
+            .find(|mc| mc.channel_id == channel_id)
+    }
+
+    // what parses but cannot work, e.g. outputs are clamped to min and max
+    pub fn validate(&self) -> Result<(), String> {
+        let errors: Vec<String> = self
+            .manual_channel
+            .iter()
+            .flatten()
+            .filter(|mc| mc.min > mc.max)
+            .map(|mc| {
+                format!(
+                    "manual channel {} has min {} above max {}",
+                    mc.channel_id, mc.min, mc.max
+                )
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AlarmRule {
+    pub label: Option<String>, // default source and value, e.g. BT 200
+    pub source: String, // channel_id, <channel_id>_ror, time, dry_percent, mai_percent, dev_percent
+    pub condition: String, // above or below
+    pub value: f64,
+    pub hysteresis: Option<f64>, // distance back past value to re-arm, default 0
+    pub after: Option<String>,   // roast event which must have happened, e.g. TP
+    pub repeat: Option<bool>,    // default false, once per roast
+    pub sound: Option<bool>,     // speak the label, default false
+    pub set_output: Option<AlarmOutput>,
+    pub roast_event: Option<String>, // mark a roast event, e.g. DRY_END
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AlarmOutput {
+    pub channel_id: String, // manual channel_id
+    pub value: f64,
 }
 
 // LEVEL 1
@@ -60,6 +130,7 @@ pub struct Ta612c {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Modbus {
     pub protocol: String,
+    pub retry: Option<u16>, // retries per request, default 0
     pub slave: Vec<Slave>,
 }
 
@@ -103,4 +174,177 @@ pub struct ManualChannel {
     pub max: u16,
     pub step: u16,
     pub default_value: u16,
+    pub rate_limit: Option<u64>, // minimum milliseconds between two outputs, default 0
+    pub modbus: Option<ModbusTarget>,
+    pub http: Option<HttpTarget>,
+    pub tc4: Option<Tc4Target>,
+    pub mqtt: Option<MqttTarget>,
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ModbusTarget {
+    pub id: u16,
+    pub function: u16, // 5 coil, 6 single register, 16 multiple registers
+    pub registry: u16,
+    pub multiplier: Option<f64>, // written value = value * multiplier, default 1
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HttpTarget {
+    pub url: String, // POST {"channel_id": "gas", "value": 40}
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Tc4Target {
+    pub port: String,
+    pub baud_rate: u16,
+    pub command: Option<String>, // OT1, OT2, IO3, default OT1
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MqttTarget {
+    pub ip: String,
+    pub port: u16,
+    pub topic: String,
+    pub client_id: Option<String>, // default roastcraft
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Pid {
+    pub input: String,        // channel_id, e.g. BT
+    pub mode: Option<String>, // temperature or ror, default temperature
+    pub output: String,       // manual channel_id, e.g. gas
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub output_min: Option<f64>, // default manual channel min
+    pub output_max: Option<f64>, // default manual channel max
+    pub set_point: Option<f64>,
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Safety {
+    pub limit: Option<Vec<SafetyLimit>>,
+    pub sensor_min: Option<f64>, // readings outside are an open sensor, default -50
+    pub sensor_max: Option<f64>, // default 1000
+    pub stale_timeout: Option<u64>, // seconds without a sample, default 10
+    pub heartbeat_timeout: Option<u64>, // seconds without the webview, unchecked if absent
+    pub output: Vec<SafeOutput>,
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SafetyLimit {
+    pub channel_id: String, // e.g. BT, ET
+    pub max: f64,
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SafeOutput {
+    pub channel_id: String, // manual channel_id
+    pub value: f64,
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Batch {
+    pub prefix: Option<String>,
+    pub digits: Option<usize>,     // zero padded, default 3
+    pub daily_reset: Option<bool>, // default false
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Autosave {
+    pub dir: String,
+    pub template: Option<String>, // {date} {time} {batch} {title}, default {date}_{batch}_{title}
+    pub delay: Option<u64>,       // seconds after DROP, default 30
+    pub on_stop: Option<bool>,    // also save when the roast is stopped, default true
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Ror {
+    pub window: Option<usize>,           // samples the raw RoR spans, default 5
+    pub outlier_window: Option<usize>,   // RoR before a point for its z-score, default 5
+    pub outlier_z_score: Option<f64>,    // default 3
+    pub smoothing_window: Option<usize>, // odd, samples of the Hann window, default 11
+    pub smoothing: Option<RorSmoothing>, // default the Hann window of smoothing_window
+    pub channel: Option<Vec<RorChannel>>,
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RorChannel {
+    pub channel_id: String,
+    pub smoothing: RorSmoothing,
+}
+
+// LEVEL 2
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RorSmoothing {
+    pub method: String,             // hann, savitzky_golay, ema, kalman or regression
+    pub window: Option<usize>,      // hann and savitzky_golay, odd, default smoothing_window
+    pub order: Option<usize>,       // savitzky_golay, polynomial order, default 2
+    pub alpha: Option<f64>,         // ema, weight of the newest point, default 0.3
+    pub process_noise: Option<f64>, // kalman, variance of the RoR acceleration, default 0.01
+    pub measurement_noise: Option<f64>, // kalman, variance of the filtered RoR, default 4
+    pub span: Option<f64>,          // regression, seconds the line is fitted to, default 15
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Detection {
+    pub enabled: Option<bool>, // mark CHARGE, TP, DRY_END and DROP from BT, default true
+    pub break_ratio: Option<f64>, // CHARGE and DROP, BT RoR fall over the rise before, default 2
+    pub charge_drop: Option<f64>, // CHARGE and DROP, BT RoR fall per minute, default 20C or 36F
+    pub tp_drop: Option<f64>,  // TP, BT fall from its high, default 50C or 90F
+    pub dry_end: Option<f64>,  // DRY_END, BT, default 150C or 302F
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Prediction {
+    pub fc_temperature: Option<f64>, // BT, default the average of the lot, else 196C or 385F
+    pub lot_roasts: Option<usize>,   // last roasts of the lot averaged, default 5
+    pub window: Option<f64>,         // seconds of BT RoR the trend is fitted to, default 90
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DropTarget {
+    pub dtr: Option<f64>, // development time ratio, %, unless the lot or the roast has a target
+    pub temperature: Option<f64>, // BT at DROP, projected from the BT RoR trend
+    pub countdown: Option<Vec<u64>>, // seconds before the projected DROP to alarm, default none
+    pub sound: Option<bool>, // speak the countdown alarms, default true
+}
+
+// LEVEL 1
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Phases {
+    pub auc_base: Option<f64>, // BT the area under the curve is taken above, default 100C or 212F
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_channel_range_is_validated() {
+        let shipped: Config = toml::from_str(include_str!("../roastcraft.toml")).unwrap();
+        assert!(shipped.validate().is_ok());
+
+        let mut config = shipped.clone();
+        let gas = &mut config.manual_channel.as_mut().unwrap()[0];
+        (gas.min, gas.max) = (100, 0);
+        let error = config.validate().err().unwrap();
+        assert!(error.contains("gas"), "{}", error);
+    }
 }
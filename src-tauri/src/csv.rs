@@ -0,0 +1,594 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::info;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::analytics::{self as ror_analytics, RorSettings};
+use crate::roastfile::{ChannelData, RoastFile};
+use crate::session::{Point, RoastEvent};
+
+// metadata rows of the header block, "# key,value"
+const METADATA: [&str; 15] = [
+    "batch",
+    "title",
+    "country",
+    "process",
+    "notes",
+    "weight_green",
+    "weight_roasted",
+    "volume_green",
+    "volume_roasted",
+    "density_green",
+    "density_roasted",
+    "moisture_green",
+    "moisture_roasted",
+    "color_whole",
+    "color_ground",
+];
+
+fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn row(fields: &[String]) -> String {
+    let fields: Vec<String> = fields.iter().map(|f| field(f)).collect();
+    fields.join(",") + "\r\n"
+}
+
+fn number(v: f64) -> String {
+    format!("{}", (v * 100.0).round() / 100.0)
+}
+
+// unix time in milliseconds to "2024-03-12 10:15:02", utc_offset in minutes
+pub fn clock(unix_millis: u64, utc_offset: i64) -> String {
+    let secs = unix_millis as i64 / 1000 + utc_offset * 60;
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+// "2024-03-12 10:15:02" back to unix time in milliseconds, utc_offset in minutes
+fn unix_millis(clock: &str, utc_offset: i64) -> Option<u64> {
+    let (date, time) = clock.trim().split_once(' ')?;
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<i64> = time
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let (&[year, month, day], &[h, m, sec]) = (date.as_slice(), time.as_slice()) else {
+        return None;
+    };
+
+    // days from civil, the inverse of clock
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + h * 3600 + m * 60 + sec - utc_offset * 60;
+    u64::try_from(secs * 1000).ok()
+}
+
+// utc offset in minutes as "+01:00"
+fn offset(utc_offset: i64) -> String {
+    let sign = if utc_offset < 0 { '-' } else { '+' };
+    let minutes = utc_offset.abs();
+    format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+fn parse_offset(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(t) => (-1, t),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (h, m) = text.split_once(':')?;
+    Some(sign * (h.parse::<i64>().ok()? * 60 + m.parse::<i64>().ok()?))
+}
+
+fn metadata(roast: &RoastFile, key: &str) -> String {
+    match key {
+        "batch" => roast.batch.clone().unwrap_or_default(),
+        "title" => roast.title.clone(),
+        "country" => roast.country.clone(),
+        "process" => roast.process.clone(),
+        "notes" => roast.notes.clone(),
+        "weight_green" => number(roast.weight_green),
+        "weight_roasted" => number(roast.weight_roasted),
+        "volume_green" => number(roast.volume_green),
+        "volume_roasted" => number(roast.volume_roasted),
+        "density_green" => number(roast.density_green),
+        "density_roasted" => number(roast.density_roasted),
+        "moisture_green" => number(roast.moisture_green),
+        "moisture_roasted" => number(roast.moisture_roasted),
+        "color_whole" => number(roast.color_whole),
+        "color_ground" => number(roast.color_ground),
+        _ => String::new(),
+    }
+}
+
+fn set_metadata(roast: &mut RoastFile, key: &str, value: &str) {
+    let n = || decimal(value).unwrap_or(0.0);
+    match key {
+        "batch" => roast.batch = Some(value.to_string()).filter(|b| !b.is_empty()),
+        "title" => roast.title = value.to_string(),
+        "country" => roast.country = value.to_string(),
+        "process" => roast.process = value.to_string(),
+        "notes" => roast.notes = value.to_string(),
+        "weight_green" => roast.weight_green = n(),
+        "weight_roasted" => roast.weight_roasted = n(),
+        "volume_green" => roast.volume_green = n(),
+        "volume_roasted" => roast.volume_roasted = n(),
+        "density_green" => roast.density_green = n(),
+        "density_roasted" => roast.density_roasted = n(),
+        "moisture_green" => roast.moisture_green = n(),
+        "moisture_roasted" => roast.moisture_roasted = n(),
+        "color_whole" => roast.color_whole = n(),
+        "color_ground" => roast.color_ground = n(),
+        _ => (),
+    }
+}
+
+// one row per sample, time relative to CHARGE, utc_offset in minutes for the clock column.
+// the RoR columns are the smoothed RoR of the chart, empty at outliers and after DROP
+pub fn export(roast: &RoastFile, utc_offset: i64, settings: &RorSettings) -> String {
+    // a BOM, so excel reads the file as utf-8
+    let mut out = String::from("\u{feff}");
+
+    out += &row(&[String::from("# RoastCraft roast")]);
+    for key in METADATA {
+        out += &row(&[format!("# {}", key), metadata(roast, key)]);
+    }
+    if let Some(epoch) = roast.roast_epoch {
+        out += &row(&[
+            String::from("# start"),
+            clock(epoch, utc_offset),
+            offset(utc_offset),
+        ]);
+    }
+    let mut manual: Vec<String> = vec![String::from("# manual_channels")];
+    manual.extend(roast.manual_channel_arr.iter().map(|mc| mc.id.clone()));
+    out += &row(&manual);
+
+    let mut header = vec![String::from("time"), String::from("clock")];
+    header.extend(roast.channel_arr.iter().map(|c| c.id.clone()));
+    header.extend(roast.channel_arr.iter().map(|c| format!("{}_ror", c.id)));
+    header.extend(roast.manual_channel_arr.iter().map(|mc| mc.id.clone()));
+    header.push(String::from("event"));
+    out += &row(&header);
+
+    let charge = roast
+        .roast_events
+        .get("CHARGE")
+        .map(|e| e.timestamp)
+        .unwrap_or(0.0);
+    let timeline = roast.timeline();
+    let drop = roast.roast_events.get("DROP").map(|e| e.timestamp);
+    let ror: Vec<ChannelData> = roast
+        .channel_arr
+        .iter()
+        .map(|c| ChannelData {
+            id: c.id.clone(),
+            data_arr: ror_analytics::compute(&c.id, &c.data_arr, drop, settings).smoothed,
+        })
+        .collect();
+
+    // events on the first row at or after them
+    let mut events: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for e in roast.roast_events.values() {
+        let i = timeline
+            .partition_point(|t| *t < e.timestamp)
+            .min(timeline.len().saturating_sub(1));
+        events.entry(i).or_default().push(e.id.clone());
+    }
+
+    let cell = |v: Option<f64>| v.map(number).unwrap_or_default();
+    for (i, t) in timeline.iter().enumerate() {
+        let mut fields = vec![
+            number(t - charge),
+            roast
+                .roast_epoch
+                .map(|epoch| clock(epoch + (t * 1000.0) as u64, utc_offset))
+                .unwrap_or_default(),
+        ];
+        fields.extend(roast.channel_arr.iter().map(|c| cell(c.value_at(*t))));
+        fields.extend(ror.iter().map(|c| cell(c.value_at(*t))));
+        fields.extend(
+            roast
+                .manual_channel_arr
+                .iter()
+                .map(|mc| cell(mc.value_before(*t))),
+        );
+        fields.push(events.get(&i).map(|e| e.join(" ")).unwrap_or_default());
+        out += &row(&fields);
+    }
+    out
+}
+
+// split one line, quoted fields may hold the delimiter
+fn parse_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+// lines of the file, a newline inside quotes does not end one
+fn records(content: &str) -> Vec<String> {
+    let mut records = Vec::new();
+    let mut record = String::new();
+    let mut quoted = false;
+
+    for c in content.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                record.push(c);
+            }
+            '\r' if !quoted => (),
+            '\n' if !quoted => records.push(std::mem::take(&mut record)),
+            c => record.push(c),
+        }
+    }
+    if !record.is_empty() {
+        records.push(record);
+    }
+    records
+}
+
+// a decimal comma is read as a point
+fn decimal(text: &str) -> Option<f64> {
+    text.trim().replace(',', ".").parse().ok()
+}
+
+// seconds, or m:ss and h:mm:ss like other loggers write
+fn seconds(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if text.contains(':') {
+        let (sign, text) = match text.strip_prefix('-') {
+            Some(t) => (-1.0, t),
+            None => (1.0, text),
+        };
+        text.split(':')
+            .try_fold(0.0, |acc, part| {
+                Some(acc * 60.0 + part.parse::<f64>().ok()?)
+            })
+            .map(|s| sign * s)
+    } else {
+        decimal(text)
+    }
+}
+
+// a roast from our own export, or from another logger with at least time and BT columns
+pub fn import(content: &str) -> Result<RoastFile, String> {
+    let content = content.trim_start_matches('\u{feff}');
+    let records = records(content);
+
+    // the header line tells how values are separated, excel uses ; in some locales
+    let header = records
+        .iter()
+        .find(|r| !r.starts_with('#') && !r.trim().is_empty())
+        .ok_or("no header line")?;
+    let delimiter = [',', ';', '\t']
+        .into_iter()
+        .max_by_key(|d| header.matches(*d).count())
+        .unwrap_or(',');
+
+    let mut roast = RoastFile::new();
+    let mut manual_ids: Vec<String> = Vec::new();
+    let mut columns: Vec<String> = Vec::new();
+    let mut rows: Vec<Vec<String>> = Vec::new();
+
+    for record in records.iter() {
+        if record.trim().is_empty() {
+            continue;
+        }
+        let fields = parse_line(record, delimiter);
+        if let Some(key) = fields[0].strip_prefix('#') {
+            let key = key.trim();
+            let value = fields.get(1).map(|v| v.as_str()).unwrap_or("");
+            if key == "start" {
+                // the clock of START, in utc without an offset
+                let utc_offset = fields.get(2).and_then(|o| parse_offset(o)).unwrap_or(0);
+                roast.roast_epoch = unix_millis(value, utc_offset);
+            } else if key == "manual_channels" {
+                manual_ids = fields[1..]
+                    .iter()
+                    .filter(|id| !id.is_empty())
+                    .cloned()
+                    .collect();
+            } else {
+                set_metadata(&mut roast, key, value);
+            }
+        } else if columns.is_empty() {
+            columns = fields.iter().map(|f| f.trim().to_string()).collect();
+        } else {
+            rows.push(fields);
+        }
+    }
+
+    let time_column = columns
+        .iter()
+        .position(|c| c.eq_ignore_ascii_case("time"))
+        .unwrap_or(0);
+    let times: Vec<Option<f64>> = rows
+        .iter()
+        .map(|r| r.get(time_column).and_then(|t| seconds(t)))
+        .collect();
+    // our time column is relative to CHARGE, points are relative to the first row
+    let start = times.iter().flatten().copied().next().ok_or("no samples")?;
+
+    let mut channels: BTreeMap<usize, ChannelData> = BTreeMap::new();
+    let mut manual_channels: BTreeMap<usize, ChannelData> = BTreeMap::new();
+    let mut event_column = None;
+    for (i, name) in columns.iter().enumerate() {
+        let lower = name.to_lowercase();
+        if i == time_column || lower == "clock" || lower.ends_with("_ror") || name.is_empty() {
+            continue;
+        }
+        if lower == "event" {
+            event_column = Some(i);
+            continue;
+        }
+        let id = match lower.as_str() {
+            "bt" => String::from("BT"),
+            "et" => String::from("ET"),
+            _ => name.clone(),
+        };
+        let data = ChannelData {
+            id: id.clone(),
+            data_arr: Vec::new(),
+        };
+        if manual_ids.contains(&id) {
+            manual_channels.insert(i, data);
+        } else {
+            channels.insert(i, data);
+        }
+    }
+
+    for (r, time) in rows.iter().zip(times.iter()) {
+        let timestamp = match time {
+            Some(t) => t - start,
+            None => continue,
+        };
+        for (i, c) in channels.iter_mut() {
+            if let Some(value) = r.get(*i).and_then(|v| decimal(v)) {
+                c.data_arr.push(Point { timestamp, value });
+            }
+        }
+        // manual channels are carried forward, keep the changes only
+        for (i, mc) in manual_channels.iter_mut() {
+            if let Some(value) = r.get(*i).and_then(|v| decimal(v)) {
+                if mc.data_arr.last().map(|p| p.value) != Some(value) {
+                    mc.data_arr.push(Point { timestamp, value });
+                }
+            }
+        }
+    }
+    // text columns of other loggers end up without points
+    roast.channel_arr = channels
+        .into_values()
+        .filter(|c| !c.data_arr.is_empty())
+        .collect();
+    roast.manual_channel_arr = manual_channels.into_values().collect();
+
+    if let Some(event_column) = event_column {
+        for (r, time) in rows.iter().zip(times.iter()) {
+            let (ids, timestamp) = match (r.get(event_column), time) {
+                (Some(ids), Some(t)) => (ids, t - start),
+                _ => continue,
+            };
+            for id in ids.split_whitespace() {
+                let value = roast
+                    .channel("BT")
+                    .and_then(|bt| bt.value_at(timestamp))
+                    .unwrap_or(0.0);
+                roast.roast_events.insert(
+                    id.to_string(),
+                    RoastEvent {
+                        id: id.to_string(),
+                        timestamp,
+                        value,
+                    },
+                );
+            }
+        }
+    }
+
+    roast.validate()?;
+    Ok(roast)
+}
+
+pub fn load(path: &Path) -> Result<RoastFile, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let roast = import(&content).map_err(|e| format!("{} : {}", path.display(), e))?;
+    info!("csv roast imported : {}", path.display());
+    Ok(roast)
+}
+
+pub fn save(
+    path: &Path,
+    roast: &RoastFile,
+    utc_offset: i64,
+    settings: &RorSettings,
+) -> Result<(), String> {
+    fs::write(path, export(roast, utc_offset, settings)).map_err(|e| e.to_string())?;
+    info!("csv roast exported : {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(id: &str, values: &[f64]) -> ChannelData {
+        ChannelData {
+            id: String::from(id),
+            data_arr: values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| Point {
+                    timestamp: i as f64 * 2.0,
+                    value: *v,
+                })
+                .collect(),
+        }
+    }
+
+    fn event(id: &str, timestamp: f64, value: f64) -> (String, RoastEvent) {
+        (
+            String::from(id),
+            RoastEvent {
+                id: String::from(id),
+                timestamp,
+                value,
+            },
+        )
+    }
+
+    #[test]
+    fn clock_reads_back() {
+        for (epoch, utc_offset) in [
+            (0, 0),
+            (1710286205000, 0),
+            (1710286205000, 60),
+            (951782400000, -330),
+        ] {
+            assert_eq!(
+                unix_millis(&clock(epoch, utc_offset), utc_offset),
+                Some(epoch)
+            );
+        }
+        assert_eq!(clock(1710286205000, 60), "2024-03-13 00:30:05");
+        assert_eq!(parse_offset(&offset(-330)), Some(-330));
+        assert_eq!(parse_offset(&offset(60)), Some(60));
+    }
+
+    #[test]
+    fn export_then_import_keeps_the_roast() {
+        let mut roast = RoastFile::new();
+        roast.channel_arr = vec![
+            channel("BT", &[180.0, 150.0, 120.5, 110.25, 115.0, 130.0]),
+            channel("ET", &[230.0, 225.0, 220.0, 222.0, 228.0, 235.0]),
+        ];
+        roast.manual_channel_arr = vec![ChannelData {
+            id: String::from("gas"),
+            data_arr: vec![
+                Point {
+                    timestamp: 0.0,
+                    value: 40.0,
+                },
+                Point {
+                    timestamp: 6.0,
+                    value: 60.0,
+                },
+            ],
+        }];
+        roast.roast_events =
+            BTreeMap::from([event("CHARGE", 0.0, 180.0), event("TP", 6.0, 110.25)]);
+        roast.title = String::from("Guji, \"natural\"");
+        roast.batch = Some(String::from("B-7"));
+        roast.weight_green = 250.0;
+        // whole seconds, the clock column has no milliseconds
+        roast.roast_epoch = Some(1710286205000);
+
+        let exported = export(&roast, 60, &RorSettings::new(None));
+        let back = import(&exported).unwrap();
+
+        assert_eq!(back.roast_epoch, roast.roast_epoch);
+        assert_eq!(back.title, roast.title);
+        assert_eq!(back.batch, roast.batch);
+        assert_eq!(back.weight_green, 250.0);
+        for id in ["BT", "ET"] {
+            let points = |r: &RoastFile| {
+                r.channel(id)
+                    .unwrap()
+                    .data_arr
+                    .iter()
+                    .map(|p| (p.timestamp, p.value))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(points(&back), points(&roast));
+        }
+        assert_eq!(back.manual_channel_arr.len(), 1);
+        let gas: Vec<(f64, f64)> = back.manual_channel_arr[0]
+            .data_arr
+            .iter()
+            .map(|p| (p.timestamp, p.value))
+            .collect();
+        assert_eq!(gas, vec![(0.0, 40.0), (6.0, 60.0)]);
+        assert_eq!(
+            back.roast_events.keys().collect::<Vec<_>>(),
+            ["CHARGE", "TP"]
+        );
+        assert_eq!(back.roast_events["TP"].timestamp, 6.0);
+        assert_eq!(back.roast_events["TP"].value, 110.25);
+    }
+
+    #[test]
+    fn imports_semicolons_and_decimal_commas() {
+        let content = "# title;Kenya AA\r\n\
+                       Time;ET;BT;Event\r\n\
+                       0:00;220,5;185,25;CHARGE\r\n\
+                       0:02;218,0;160,5;\r\n\
+                       0:04;216,75;140,0;\r\n";
+        let roast = import(content).unwrap();
+
+        assert_eq!(roast.title, "Kenya AA");
+        assert_eq!(roast.roast_epoch, None);
+        let bt: Vec<(f64, f64)> = roast
+            .channel("BT")
+            .unwrap()
+            .data_arr
+            .iter()
+            .map(|p| (p.timestamp, p.value))
+            .collect();
+        assert_eq!(bt, vec![(0.0, 185.25), (2.0, 160.5), (4.0, 140.0)]);
+        assert_eq!(roast.channel("ET").unwrap().data_arr[2].value, 216.75);
+        assert_eq!(roast.roast_events["CHARGE"].value, 185.25);
+    }
+}
@@ -0,0 +1,326 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::info;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::analytics::raw_ror;
+use crate::config::Config;
+use crate::session::{Point, RoastEvent};
+
+// BT RoR around a CHARGE or DROP break, the break is the middle one
+const BREAK_WINDOW: usize = 5;
+
+// a roast event found in the BT curve, emitted as "roast_event_detected"
+#[derive(Serialize, Clone)]
+pub struct DetectedEvent {
+    pub event: RoastEvent,
+    pub confidence: f64, // 0.5 when the threshold is just passed, 1 when passed twice over
+}
+
+fn confidence(value: f64, threshold: f64) -> f64 {
+    if threshold <= 0.0 {
+        return 1.0;
+    }
+    (value / threshold / 2.0).clamp(0.0, 1.0)
+}
+
+// finds CHARGE, TP, DRY_END and DROP in the BT of the session as it is recorded
+pub struct EventDetector {
+    enabled: bool,
+    break_ratio: f64, // BT RoR after a break over the one before
+    charge_drop: f64, // BT RoR fall after a break, degrees per minute
+    tp_drop: f64,     // BT fall from its high before TP
+    dry_end: f64,     // BT
+}
+
+impl EventDetector {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            break_ratio: 2.0,
+            charge_drop: 20.0,
+            tp_drop: 50.0,
+            dry_end: 150.0,
+        }
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        // defaults in the temperature unit of the machine
+        let (scale, dry_end) = if config.temperature_unit == "F" {
+            (1.8, 302.0)
+        } else {
+            (1.0, 150.0)
+        };
+
+        let detection = config.detection.as_ref();
+        self.enabled = detection.and_then(|d| d.enabled).unwrap_or(true);
+        self.break_ratio = detection.and_then(|d| d.break_ratio).unwrap_or(2.0);
+        self.charge_drop = detection
+            .and_then(|d| d.charge_drop)
+            .unwrap_or(20.0 * scale);
+        self.tp_drop = detection.and_then(|d| d.tp_drop).unwrap_or(50.0 * scale);
+        self.dry_end = detection.and_then(|d| d.dry_end).unwrap_or(dry_end);
+    }
+
+    // events found with the latest BT sample. events are those of the session,
+    // marked by hand or found before, which are not looked for again
+    pub fn detect(
+        &self,
+        bt: &[Point],
+        ror_window: usize,
+        events: &BTreeMap<String, RoastEvent>,
+    ) -> Vec<DetectedEvent> {
+        let mut detected = Vec::new();
+        if !self.enabled || events.contains_key("DROP") {
+            return detected;
+        }
+        let found = |id: &str, detected: &Vec<DetectedEvent>| {
+            events.contains_key(id) || detected.iter().any(|d| d.event.id == id)
+        };
+
+        if let Some(d) = self.find_break(bt, ror_window) {
+            if !found("CHARGE", &detected) {
+                detected.push(d.with_id("CHARGE"));
+            } else if found("TP", &detected) {
+                detected.push(d.with_id("DROP"));
+            }
+        }
+        if found("CHARGE", &detected) && !found("TP", &detected) {
+            let charge = events
+                .get("CHARGE")
+                .map(|e| e.timestamp)
+                .or(detected.first().map(|d| d.event.timestamp))
+                .unwrap_or(0.0);
+            if let Some(d) = self.find_turning_point(bt, charge) {
+                detected.push(d);
+            }
+        }
+        if found("TP", &detected) && !found("DRY_END", &detected) {
+            if let Some(d) = self.find_dry_end(bt) {
+                detected.push(d);
+            }
+        }
+
+        for d in detected.iter() {
+            info!(
+                "detected {} at {} s, confidence {:.2}",
+                d.event.id, d.event.timestamp, d.confidence
+            );
+        }
+        detected
+    }
+
+    // reference: artisan/src/artisanlib/main.py  BTbreak()
+    // . BT RoR before the break is not negative on average, after it negative.
+    //   artisan wants it rising, but a BT settled in the preheated drum is flat
+    // . the fall after is break_ratio times the rise before, and charge_drop at least
+    fn find_break(&self, bt: &[Point], ror_window: usize) -> Option<DetectedEvent> {
+        if bt.len() < BREAK_WINDOW {
+            return None;
+        }
+        let start = bt.len() - BREAK_WINDOW;
+        let ror: Vec<f64> = (start..bt.len())
+            .map(|i| raw_ror(bt, i, ror_window))
+            .collect();
+
+        let pre = (ror[0] + ror[1]) / 2.0;
+        let post = (ror[3] + ror[4]) / 2.0;
+        if pre >= 0.0
+            && ror[3] < 0.0
+            && ror[4] < 0.0
+            && post.abs() > pre.abs() * self.break_ratio
+            && post.abs() >= self.charge_drop
+        {
+            let point = &bt[start + 2];
+            return Some(DetectedEvent {
+                event: RoastEvent {
+                    id: String::new(),
+                    timestamp: point.timestamp,
+                    value: point.value,
+                },
+                confidence: confidence(post.abs() / pre.abs(), self.break_ratio)
+                    .min(confidence(post.abs(), self.charge_drop)),
+            });
+        }
+        None
+    }
+
+    // the lowest BT after CHARGE, once the last two readings are above it
+    fn find_turning_point(&self, bt: &[Point], charge: f64) -> Option<DetectedEvent> {
+        if bt.len() < 3 {
+            return None;
+        }
+        let after_charge = bt.iter().position(|p| p.timestamp >= charge)?;
+        let (low, low_point) = bt[after_charge..]
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.value.total_cmp(&b.1.value))?;
+        let low = after_charge + low;
+        let high = bt[..=low].iter().map(|p| p.value).fold(f64::MIN, f64::max);
+
+        let fall = high - low_point.value;
+        let n = bt.len();
+        if low <= n - 3
+            && bt[n - 1].value > low_point.value
+            && bt[n - 2].value > low_point.value
+            && fall > self.tp_drop
+        {
+            return Some(DetectedEvent {
+                event: RoastEvent {
+                    id: String::from("TP"),
+                    timestamp: low_point.timestamp,
+                    value: low_point.value,
+                },
+                confidence: confidence(fall, self.tp_drop),
+            });
+        }
+        None
+    }
+
+    // the last two readings are above dry_end. a BT still rising past it is
+    // certain, a flat one may be noise around it
+    fn find_dry_end(&self, bt: &[Point]) -> Option<DetectedEvent> {
+        if bt.len() < 2 {
+            return None;
+        }
+        let last = &bt[bt.len() - 1];
+        let point = &bt[bt.len() - 2];
+        if last.value > self.dry_end && point.value > self.dry_end {
+            return Some(DetectedEvent {
+                event: RoastEvent {
+                    id: String::from("DRY_END"),
+                    timestamp: point.timestamp,
+                    value: point.value,
+                },
+                confidence: if last.value > point.value { 1.0 } else { 0.5 },
+            });
+        }
+        None
+    }
+}
+
+impl DetectedEvent {
+    fn with_id(mut self, id: &str) -> Self {
+        self.event.id = id.to_string();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[derive(Deserialize)]
+    struct Roast {
+        data: Vec<Point>,
+    }
+
+    // BT of the celsius sample alog, the roast the RoR tests use too
+    fn recorded_bt() -> Vec<Point> {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("samples/ror/ethiopia_guji_bt.json");
+        let roast: Roast = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        roast.data
+    }
+
+    // fed sample by sample like a session, detected events are marked.
+    // returns when each was detected, the event and its confidence
+    fn run(bt: &[Point]) -> Vec<(f64, RoastEvent, f64)> {
+        let detector = EventDetector::new();
+        let mut events = BTreeMap::new();
+        let mut found = Vec::new();
+        for i in 0..bt.len() {
+            for d in detector.detect(&bt[..=i], 5, &events) {
+                events.insert(d.event.id.clone(), d.event.clone());
+                found.push((bt[i].timestamp, d.event, d.confidence));
+            }
+        }
+        found
+    }
+
+    // the drum is emptied at the DROP of the recording, BT reads the air and falls
+    fn with_simulated_drop(bt: &[Point], drop: f64) -> Vec<Point> {
+        let mut bt: Vec<Point> = bt.iter().filter(|p| p.timestamp <= drop).cloned().collect();
+        let last = bt[bt.len() - 1].value;
+        bt.extend((1..=20).map(|k| Point {
+            timestamp: drop + k as f64,
+            value: last - 2.5 * k as f64,
+        }));
+        bt
+    }
+
+    fn assert_detected(
+        found: &(f64, RoastEvent, f64),
+        at: f64,
+        id: &str,
+        timestamp: f64,
+        value: f64,
+        confidence: f64,
+    ) {
+        let (detected_at, event, c) = found;
+        assert_eq!(event.id, id);
+        assert_eq!(*detected_at, at, "{} detected at", id);
+        assert_eq!(event.timestamp, timestamp, "{} timestamp", id);
+        assert_eq!(event.value, value, "{} value", id);
+        assert!((c - confidence).abs() < 1e-9, "{} confidence {}", id, c);
+    }
+
+    #[test]
+    fn recorded_roast() {
+        let found = run(&recorded_bt());
+        assert_eq!(found.len(), 3);
+        // BT is flat before CHARGE, a RoR of 0 before the break
+        assert_detected(&found[0], 32.0, "CHARGE", 30.0, 200.0, 1.0);
+        assert_detected(&found[1], 106.0, "TP", 104.0, 89.0, 1.0);
+        // BT rises past dry_end, which is certain
+        assert_detected(&found[2], 351.0, "DRY_END", 350.0, 150.2, 1.0);
+    }
+
+    #[test]
+    fn recorded_roast_with_simulated_drop() {
+        let found = run(&with_simulated_drop(&recorded_bt(), 560.0));
+        assert_eq!(found.len(), 4);
+        let (at, drop, confidence) = &found[3];
+        assert_eq!(drop.id, "DROP");
+        assert_eq!(*at, 562.0);
+        assert_eq!((drop.timestamp, drop.value), (560.0, 195.4));
+        assert!(*confidence > 0.5 && *confidence <= 1.0);
+    }
+
+    // artisan and the webview wanted a rising BT before the break. a probe
+    // settled in the preheated drum reads flat, so not falling is enough, and
+    // charge_drop keeps a slow fall of a flat BT from being a break
+    #[test]
+    fn break_after_a_flat_or_falling_bt() {
+        let detector = EventDetector::new();
+        let events = BTreeMap::new();
+        let bt = |values: &[f64]| -> Vec<Point> {
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| Point {
+                    timestamp: i as f64,
+                    value: *v,
+                })
+                .collect()
+        };
+        let detect = |values: &[f64]| detector.detect(&bt(values), 5, &events);
+
+        let flat = [200.0; 10];
+        let charge = [&flat[..], &[195.0, 190.0]].concat();
+        assert_eq!(detect(&charge)[0].event.timestamp, 9.0);
+
+        // 12 degrees a minute is not a charge
+        let slow = [&flat[..], &[199.8, 199.6]].concat();
+        assert!(detect(&slow).is_empty());
+
+        // BT already falling, e.g. the drum cooling, has no break
+        let falling: Vec<f64> = (0..10).map(|i| 200.0 - i as f64).collect();
+        let falling = [&falling[..], &[185.0, 180.0]].concat();
+        assert!(detect(&falling).is_empty());
+    }
+}
@@ -1,13 +1,33 @@
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 
 use async_trait::async_trait;
 use serde_json::Value;
+use tokio::sync::oneshot;
+
+use crate::config::ManualChannel;
 
 pub mod http;
 pub mod modbus;
+pub mod scanner;
+pub mod serial;
 pub mod ta612c;
 
 #[async_trait]
 pub trait Device {
     async fn read(self: &mut Self) -> Result<Value, Error>;
+
+    // send a manual channel value to the machine, Ok once the device confirmed it
+    async fn write(self: &mut Self, _channel: &ManualChannel, _value: f64) -> Result<(), Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "device does not support writing",
+        ))
+    }
+}
+
+// handed to the reader loop, so writes share the connection with reads
+pub struct WriteRequest {
+    pub channel: ManualChannel,
+    pub value: f64,
+    pub reply: oneshot::Sender<Result<(), Error>>,
 }
@@ -1,147 +1,449 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use async_trait::async_trait;
-use log::error;
-use rmodbus::{
-    client::ModbusRequest, generate_ascii_frame, guess_response_frame_len, parse_ascii_frame,
-    ModbusProto,
-};
+use log::{error, info, warn};
+use rmodbus::{client::ModbusRequest, generate_ascii_frame, ModbusProto};
+use serde::Serialize;
 use serde_json::{to_value, Map, Value};
-use serialport::{DataBits, Parity, SerialPort, StopBits};
+use serialport::SerialPort;
 use std::{
+    collections::HashMap,
+    fmt,
     io::{Error, ErrorKind},
+    thread,
     time::Duration,
 };
 use tauri::{AppHandle, Manager};
-use tokio::time;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
 
+use super::serial::SerialWorker;
 use super::Device;
-use crate::config::{Config, Slave};
+use crate::config::{Config, ManualChannel, ModbusTarget, Serial, Slave};
 
 pub struct ModbusDevice {
-    stream: Box<dyn SerialPort>,
+    worker: SerialWorker,
     config: Config,
+    app: AppHandle,
+    inter_frame_delay: Duration,
+    errors: HashMap<u16, u32>, // slave id => error count
 }
 
 impl ModbusDevice {
     pub fn new(config: Config, app: &AppHandle) -> ModbusDevice {
         let serial = config.serial.as_ref().unwrap();
 
-        let timeout = Duration::from_secs(1);
+        match SerialWorker::open(serial) {
+            Ok(worker) => ModbusDevice {
+                worker,
+                inter_frame_delay: inter_frame_delay(serial),
+                config,
+                app: app.clone(),
+                errors: HashMap::new(),
+            },
+            Err(err) => {
+                app.emit_all(
+                    "log_event",
+                    format!("{}{}", "Failed to open serial port: ", err.to_string()),
+                )
+                .unwrap();
+                panic!("Failed to open serial port");
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModbusError {
+    Io(Error),
+    Exception { function: u8, code: u8 },
+    Crc { expected: u16, received: u16 },
+    Lrc { expected: u8, received: u8 },
+    Frame(String),
+}
 
-        let mut data_bits = DataBits::Eight;
-        if serial.data_bits == 7 {
-            data_bits = DataBits::Seven;
-        } else if serial.data_bits == 6 {
-            data_bits = DataBits::Six;
-        } else if serial.data_bits == 5 {
-            data_bits = DataBits::Five;
+impl ModbusError {
+    // exceptions other than "acknowledge" and "slave busy" will answer the same again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ModbusError::Exception { code, .. } => *code == 0x05 || *code == 0x06,
+            _ => true,
         }
+    }
+}
 
-        let mut parity = Parity::None;
-        let parity_lowercase = serial.parity.to_lowercase();
-        if parity_lowercase == "even" {
-            parity = Parity::Even;
-        } else if parity_lowercase == "odd" {
-            parity = Parity::Odd;
+fn exception_name(code: u8) -> &'static str {
+    match code {
+        0x01 => "illegal function",
+        0x02 => "illegal data address",
+        0x03 => "illegal data value",
+        0x04 => "slave device failure",
+        0x05 => "acknowledge",
+        0x06 => "slave device busy",
+        0x08 => "memory parity error",
+        0x0A => "gateway path unavailable",
+        0x0B => "gateway target device failed to respond",
+        _ => "unknown exception",
+    }
+}
+
+impl fmt::Display for ModbusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModbusError::Io(e) => write!(f, "{}", e),
+            ModbusError::Exception { function, code } => write!(
+                f,
+                "exception 0x{:02X} ({}) on function {}",
+                code,
+                exception_name(*code),
+                function
+            ),
+            ModbusError::Crc { expected, received } => write!(
+                f,
+                "CRC mismatch, expected 0x{:04X} received 0x{:04X}",
+                expected, received
+            ),
+            ModbusError::Lrc { expected, received } => write!(
+                f,
+                "LRC mismatch, expected 0x{:02X} received 0x{:02X}",
+                expected, received
+            ),
+            ModbusError::Frame(msg) => write!(f, "malformed frame, {}", msg),
         }
+    }
+}
+
+impl From<Error> for ModbusError {
+    fn from(e: Error) -> Self {
+        ModbusError::Io(e)
+    }
+}
 
-        let mut stop_bits = StopBits::One;
-        if serial.stop_bits == 2 {
-            stop_bits = StopBits::Two;
+impl From<ModbusError> for Error {
+    fn from(e: ModbusError) -> Self {
+        match e {
+            ModbusError::Io(e) => e,
+            other => Error::new(ErrorKind::Other, other.to_string()),
         }
+    }
+}
 
-        let open_result = serialport::new(&serial.port, serial.baud_rate as u32)
-            .data_bits(data_bits)
-            .parity(parity)
-            .stop_bits(stop_bits)
-            .timeout(timeout)
-            .open();
+#[derive(Serialize, Clone)]
+struct ModbusErrorEvent {
+    slave_id: u16,
+    channel_id: String,
+    error_count: u32,
+    message: String,
+}
 
-        match open_result {
-            Ok(stream) => ModbusDevice { stream, config },
-            Err(err) => {
-                app.emit_all(
-                    "log_event",
-                    format!("{}{}", "Failed to open serial port: ", err.to_string()),
-                )
-                .unwrap();
-                panic!("Failed to open serial port");
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for b in data {
+        crc ^= *b as u16;
+        for _ in 0..8 {
+            if crc & 0x0001 != 0 {
+                crc = (crc >> 1) ^ 0xA001;
+            } else {
+                crc >>= 1;
             }
         }
     }
+    crc
+}
+
+pub fn lrc(data: &[u8]) -> u8 {
+    data.iter()
+        .fold(0u8, |acc, b| acc.wrapping_add(*b))
+        .wrapping_neg()
+}
+
+// modbus-rtu frames are delimited by 3.5 character times of silence,
+// fixed to 1750us above 19200 baud
+pub fn inter_frame_delay(serial: &Serial) -> Duration {
+    if serial.baud_rate as u32 > 19200 {
+        return Duration::from_micros(1750);
+    }
+
+    let parity_bits = if serial.parity.to_lowercase() == "none" {
+        0
+    } else {
+        1
+    };
+    let bits_per_char = 1 + serial.data_bits as u64 + parity_bits + serial.stop_bits as u64;
+
+    Duration::from_micros(bits_per_char * 3_500_000 / serial.baud_rate.max(1) as u64)
+}
+
+fn read_rtu_frame(stream: &mut Box<dyn SerialPort>) -> Result<Vec<u8>, ModbusError> {
+    // slave id, function, byte count or exception code
+    let mut frame = vec![0u8; 3];
+    stream.read_exact(&mut frame)?;
+
+    let exception = frame[1] & 0x80 != 0;
+    let rest = if exception {
+        2
+    } else {
+        match frame[1] {
+            1..=4 => frame[2] as usize + 2,
+            5 | 6 | 15 | 16 => 5,
+            f => return Err(ModbusError::Frame(format!("unexpected function {}", f))),
+        }
+    };
+
+    frame.resize(3 + rest, 0);
+    stream.read_exact(&mut frame[3..])?;
+
+    let (payload, crc_bytes) = frame.split_at(frame.len() - 2);
+    let expected = crc16(payload);
+    let received = u16::from_le_bytes([crc_bytes[0], crc_bytes[1]]);
+    if expected != received {
+        return Err(ModbusError::Crc { expected, received });
+    }
+
+    if exception {
+        return Err(ModbusError::Exception {
+            function: frame[1] & 0x7F,
+            code: frame[2],
+        });
+    }
+
+    Ok(frame)
+}
+
+// returns the decoded binary frame, LRC included
+fn read_ascii_frame(stream: &mut Box<dyn SerialPort>) -> Result<Vec<u8>, ModbusError> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte)?;
+        line.push(byte[0]);
+        if byte[0] == b'\n' {
+            break;
+        }
+        if line.len() > 513 {
+            return Err(ModbusError::Frame(String::from("no line feed")));
+        }
+    }
+
+    let start = line
+        .iter()
+        .position(|b| *b == b':')
+        .ok_or(ModbusError::Frame(String::from("no start colon")))?;
+    let hex = line[start + 1..]
+        .strip_suffix(b"\r\n")
+        .ok_or(ModbusError::Frame(String::from("no carriage return")))?;
+
+    if hex.len() < 6 || hex.len() % 2 != 0 {
+        return Err(ModbusError::Frame(format!("odd length {}", hex.len())));
+    }
+
+    let mut frame = Vec::with_capacity(hex.len() / 2);
+    for pair in hex.chunks(2) {
+        let b = std::str::from_utf8(pair)
+            .ok()
+            .and_then(|s| u8::from_str_radix(s, 16).ok())
+            .ok_or(ModbusError::Frame(String::from("invalid hex digit")))?;
+        frame.push(b);
+    }
+
+    let (payload, lrc_byte) = frame.split_at(frame.len() - 1);
+    let expected = lrc(payload);
+    let received = lrc_byte[0];
+    if expected != received {
+        return Err(ModbusError::Lrc { expected, received });
+    }
+
+    if frame[1] & 0x80 != 0 {
+        return Err(ModbusError::Exception {
+            function: frame[1] & 0x7F,
+            code: frame[2],
+        });
+    }
+
+    Ok(frame)
+}
+
+// send a request generated for `proto` and return the validated response frame
+pub fn exchange(
+    proto: ModbusProto,
+    inter_frame_delay: Duration,
+    request: &[u8],
+    stream: &mut Box<dyn SerialPort>,
+) -> Result<Vec<u8>, ModbusError> {
+    match proto {
+        ModbusProto::Rtu => {
+            thread::sleep(inter_frame_delay);
+            stream.write_all(request)?;
+            read_rtu_frame(stream)
+        }
+        _ => {
+            let mut request_ascii = Vec::new();
+            generate_ascii_frame(request, &mut request_ascii)
+                .map_err(|e| ModbusError::Frame(format!("{:?}", e)))?;
+            stream.write_all(&request_ascii)?;
+            read_ascii_frame(stream)
+        }
+    }
+}
+
+// modbus-tcp frame: 7 bytes MBAP header followed by function and data, no checksum
+pub async fn exchange_tcp(stream: &mut TcpStream, request: &[u8]) -> Result<Vec<u8>, ModbusError> {
+    stream.write_all(request).await?;
+
+    let mut frame = vec![0u8; 7];
+    stream.read_exact(&mut frame).await?;
+
+    let len = u16::from_be_bytes([frame[4], frame[5]]) as usize;
+    if !(3..=254).contains(&len) {
+        return Err(ModbusError::Frame(format!("invalid length {}", len)));
+    }
+
+    frame.resize(6 + len, 0);
+    stream.read_exact(&mut frame[7..]).await?;
+
+    if frame[7] & 0x80 != 0 {
+        return Err(ModbusError::Exception {
+            function: frame[7] & 0x7F,
+            code: frame[8],
+        });
+    }
+
+    Ok(frame)
 }
 
-async fn ascii(slave: &Slave, stream: &mut Box<dyn SerialPort>) -> f64 {
+fn read_holding(
+    slave: &Slave,
+    proto: ModbusProto,
+    inter_frame_delay: Duration,
+    stream: &mut Box<dyn SerialPort>,
+) -> Result<f64, ModbusError> {
     // create request object
-    let mut mreq = ModbusRequest::new(slave.id as u8, ModbusProto::Ascii);
+    let mut mreq = ModbusRequest::new(slave.id as u8, proto);
     let mut request = Vec::new();
 
     // get holding registers
     mreq.generate_get_holdings(slave.registry, 1, &mut request)
-        .unwrap();
-
-    let mut request_ascii = Vec::new();
-    generate_ascii_frame(&request, &mut request_ascii).unwrap();
-    stream.write(&request_ascii).unwrap();
-
-    let mut buf = [0u8; 7];
-    stream.read_exact(&mut buf).unwrap();
-    let mut response_ascii = Vec::new();
-    response_ascii.extend_from_slice(&buf);
-    let len = guess_response_frame_len(&buf, ModbusProto::Ascii).unwrap();
-    if len > 7 {
-        let mut rest = vec![0u8; (len - 7) as usize];
-        stream.read_exact(&mut rest).unwrap();
-        response_ascii.extend(rest);
-    }
-
-    let mut response = vec![0; (len as usize - 3) / 2];
-    parse_ascii_frame(&response_ascii, len as usize, &mut response, 0).unwrap();
-    // println!("response {:02X?}", response);
+        .map_err(|e| ModbusError::Frame(format!("{:?}", e)))?;
+
+    let response = exchange(proto, inter_frame_delay, &request, stream)?;
+
     let mut data = Vec::new();
 
-    mreq.parse_u16(&response, &mut data).unwrap();
+    // parse response bools into data vec, the frame is already checked
+    mreq.parse_u16(&response, &mut data)
+        .map_err(|e| ModbusError::Frame(format!("{:?}", e)))?;
 
     let rounded_number = (data[0] as f64 * 10.0).round() / 100.0;
-
-    rounded_number
+    Ok(rounded_number)
 }
 
-async fn rtu(slave: &Slave, stream: &mut Box<dyn SerialPort>) -> f64 {
-    // create request object
-    let mut mreq = ModbusRequest::new(slave.id as u8, ModbusProto::Rtu);
+fn write_target(
+    target: &ModbusTarget,
+    value: f64,
+    proto: ModbusProto,
+    inter_frame_delay: Duration,
+    stream: &mut Box<dyn SerialPort>,
+) -> Result<(), ModbusError> {
+    let mut mreq = ModbusRequest::new(target.id as u8, proto);
     let mut request = Vec::new();
 
-    // get holding registers
-    mreq.generate_get_holdings(slave.registry, 1, &mut request)
-        .unwrap();
+    let raw = (value * target.multiplier.unwrap_or(1.0))
+        .round()
+        .clamp(0.0, u16::MAX as f64) as u16;
+
+    match target.function {
+        5 => mreq.generate_set_coil(target.registry, raw != 0, &mut request),
+        6 => mreq.generate_set_holding(target.registry, raw, &mut request),
+        16 => mreq.generate_set_holdings_bulk(target.registry, &[raw], &mut request),
+        f => return Err(ModbusError::Frame(format!("unsupported function {}", f))),
+    }
+    .map_err(|e| ModbusError::Frame(format!("{:?}", e)))?;
 
-    stream.write(&request).unwrap();
+    let response = exchange(proto, inter_frame_delay, &request, stream)?;
 
-    let mut buf = [0u8; 7];
-    stream.read_exact(&mut buf).unwrap();
-    let mut response = Vec::new();
-    response.extend_from_slice(&buf);
-    let len = guess_response_frame_len(&buf, ModbusProto::Rtu).unwrap();
+    // the slave echoes the written address and value on success
+    mreq.parse_ok(&response)
+        .map_err(|e| ModbusError::Frame(format!("{:?}", e)))
+}
 
-    if len > 7 {
-        let mut rest = vec![0u8; (len - 7) as usize];
-        stream.read_exact(&mut rest).unwrap();
-        response.extend(rest);
+impl ModbusDevice {
+    fn proto(&self) -> ModbusProto {
+        let modbus = self
+            .config
+            .serial
+            .as_ref()
+            .unwrap()
+            .modbus
+            .as_ref()
+            .unwrap();
+        if modbus.protocol == "modbus-rtu" {
+            ModbusProto::Rtu
+        } else {
+            ModbusProto::Ascii
+        }
     }
 
-    let mut data = Vec::new();
+    // run job on the serial thread, retrying as configured and counting errors per slave
+    async fn request<R, F>(
+        &mut self,
+        slave_id: u16,
+        channel_id: &str,
+        job: F,
+    ) -> Result<R, ModbusError>
+    where
+        F: FnOnce(&mut Box<dyn SerialPort>) -> Result<R, ModbusError> + Clone + Send + 'static,
+        R: Send + 'static,
+    {
+        let modbus = self
+            .config
+            .serial
+            .as_ref()
+            .unwrap()
+            .modbus
+            .as_ref()
+            .unwrap();
+        let retry = modbus.retry.unwrap_or(0);
 
-    // check if frame has no Modbus error inside and parse response bools into data vec
-    mreq.parse_u16(&response, &mut data).unwrap();
-    // for i in 0..data.len() {
-    //     println!("{} {}", i, data[i]);
-    // }
+        let mut attempt = 0;
+        loop {
+            match self
+                .worker
+                .transact(Duration::from_secs(3), job.clone())
+                .await
+            {
+                Ok(r) => return Ok(r),
+                Err(e) => {
+                    let error_count = self.errors.entry(slave_id).or_insert(0);
+                    *error_count += 1;
 
-    let rounded_number = (data[0] as f64 * 10.0).round() / 100.0;
-    // println!("{} : {}", slave.channel_id, rounded_number);
-    rounded_number
+                    let message = format!("modbus slave {} ({}) : {}", slave_id, channel_id, e);
+                    warn!("{}, attempt {}", message, attempt + 1);
+
+                    self.app
+                        .emit_all(
+                            "modbus_error",
+                            ModbusErrorEvent {
+                                slave_id,
+                                channel_id: channel_id.to_string(),
+                                error_count: *error_count,
+                                message: message.clone(),
+                            },
+                        )
+                        .unwrap();
+
+                    if attempt < retry && e.is_retryable() {
+                        attempt += 1;
+                        continue;
+                    }
+
+                    error!("{}", message);
+                    return Err(e);
+                }
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -149,40 +451,55 @@ impl Device for ModbusDevice {
     async fn read(self: &mut Self) -> Result<Value, Error> {
         let mut map = Map::new();
 
-        // 10 seconds timeout
-        let res = tokio::time::timeout(time::Duration::from_secs(10), async {
-            // read registers
-            let config = &self.config;
-            let serial = config.serial.as_ref().unwrap();
-            let modbus = serial.modbus.as_ref().unwrap();
-            let slaves = &modbus.slave;
-
-            for slave in slaves {
-                let rounded_number: f64;
-                if modbus.protocol == "modbus-rtu" {
-                    rounded_number = rtu(slave, &mut self.stream).await;
-                } else {
-                    rounded_number = ascii(slave, &mut self.stream).await;
-                }
+        // read registers
+        let slaves = self
+            .config
+            .serial
+            .as_ref()
+            .unwrap()
+            .modbus
+            .as_ref()
+            .unwrap()
+            .slave
+            .clone();
+        let proto = self.proto();
+        let delay = self.inter_frame_delay;
 
-                map.insert(
-                    slave.channel_id.clone(),
-                    to_value(rounded_number).expect("Conversion failed"),
-                );
-            }
-        });
+        for slave in slaves {
+            let s = slave.clone();
+            let rounded_number = self
+                .request(slave.id, &slave.channel_id, move |stream| {
+                    read_holding(&s, proto, delay, stream)
+                })
+                .await?;
 
-        match res.await {
-            Ok(_) => (),
-            Err(_) => {
-                error!("read_holding_registers timeout");
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "read_holding_registers timeout",
-                ));
-            }
+            map.insert(
+                slave.channel_id.clone(),
+                to_value(rounded_number).expect("Conversion failed"),
+            );
         }
         // println!("result map : {:?} ", map);
         Ok(Value::Object(map))
     }
+
+    async fn write(self: &mut Self, channel: &ManualChannel, value: f64) -> Result<(), Error> {
+        let target = match &channel.modbus {
+            Some(target) => target.clone(),
+            None => return Ok(()), // not bound to the machine, only logged
+        };
+        let proto = self.proto();
+        let delay = self.inter_frame_delay;
+
+        let t = target.clone();
+        self.request(target.id, &channel.channel_id, move |stream| {
+            write_target(&t, value, proto, delay, stream)
+        })
+        .await?;
+
+        info!(
+            "modbus slave {} registry {} : {} set to {}",
+            target.id, target.registry, channel.channel_id, value
+        );
+        Ok(())
+    }
 }
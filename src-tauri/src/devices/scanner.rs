@@ -0,0 +1,331 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{debug, info};
+use rmodbus::{client::ModbusRequest, ModbusProto};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+use tauri::api::cli::Matches;
+use tauri::{AppHandle, Manager};
+use tokio::net::TcpStream;
+
+use super::modbus::{exchange, exchange_tcp, inter_frame_delay, ModbusError};
+use super::serial::SerialWorker;
+use crate::config::{Serial, Tcp};
+
+// what to scan, serial has priority over tcp like in Config
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ScanRequest {
+    pub serial: Option<Serial>,
+    pub tcp: Option<Tcp>,
+    pub protocol: String, // modbus-ascii, modbus-rtu, modbus-tcp
+    pub function: u8,     // 1, 2, 3, 4
+    pub slave_from: u8,
+    pub slave_to: u8,
+    pub registry_from: u16,
+    pub registry_to: u16,
+    pub timeout_ms: Option<u64>,
+}
+
+// one register, or coil, of a slave which answered
+#[derive(Serialize, Clone)]
+pub struct ScanResult {
+    pub slave_id: u8,
+    pub registry: u16,
+    pub raw: Option<u16>,
+    pub decoded: Option<Decoded>,
+    pub error: Option<String>,
+}
+
+// raw value read as every decode_type supported in machine files,
+// 32 bit types combine the register with the next one, high word first
+#[derive(Serialize, Clone)]
+pub struct Decoded {
+    pub u16: u16,
+    pub i16: i16,
+    pub u32: Option<u32>,
+    pub i32: Option<i32>,
+    pub f32: Option<f32>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ScanReport {
+    pub responding_slaves: Vec<u8>,
+    pub results: Vec<ScanResult>,
+}
+
+#[derive(Serialize, Clone)]
+struct ScanProgress {
+    slave_id: u8,
+    registry: u16,
+}
+
+enum Transport {
+    Serial {
+        worker: SerialWorker,
+        proto: ModbusProto,
+        delay: Duration,
+    },
+    Tcp(TcpStream),
+}
+
+impl Transport {
+    async fn open(req: &ScanRequest) -> Result<Transport, Error> {
+        match (&req.serial, &req.tcp) {
+            (Some(serial), _) => Ok(Transport::Serial {
+                worker: SerialWorker::open(serial)?,
+                proto: if req.protocol == "modbus-rtu" {
+                    ModbusProto::Rtu
+                } else {
+                    ModbusProto::Ascii
+                },
+                delay: inter_frame_delay(serial),
+            }),
+            (None, Some(tcp)) => Ok(Transport::Tcp(
+                TcpStream::connect((tcp.ip.as_str(), tcp.port)).await?,
+            )),
+            (None, None) => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "neither serial nor tcp given",
+            )),
+        }
+    }
+
+    fn proto(&self) -> ModbusProto {
+        match self {
+            Transport::Serial { proto, .. } => *proto,
+            Transport::Tcp(_) => ModbusProto::TcpUdp,
+        }
+    }
+
+    async fn exchange(
+        &mut self,
+        request: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, ModbusError> {
+        match self {
+            Transport::Serial {
+                worker,
+                proto,
+                delay,
+            } => {
+                let (proto, delay) = (*proto, *delay);
+                worker
+                    .transact(timeout, move |stream| {
+                        exchange(proto, delay, &request, stream)
+                    })
+                    .await
+            }
+            Transport::Tcp(stream) => {
+                match tokio::time::timeout(timeout, exchange_tcp(stream, &request)).await {
+                    Ok(res) => res,
+                    Err(_) => Err(ModbusError::Io(Error::new(
+                        ErrorKind::TimedOut,
+                        "tcp transaction timeout",
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+async fn read_one(
+    transport: &mut Transport,
+    slave_id: u8,
+    function: u8,
+    registry: u16,
+    timeout: Duration,
+) -> Result<u16, ModbusError> {
+    let mut mreq = ModbusRequest::new(slave_id, transport.proto());
+    let mut request = Vec::new();
+
+    match function {
+        1 => mreq.generate_get_coils(registry, 1, &mut request),
+        2 => mreq.generate_get_discretes(registry, 1, &mut request),
+        3 => mreq.generate_get_holdings(registry, 1, &mut request),
+        4 => mreq.generate_get_inputs(registry, 1, &mut request),
+        f => return Err(ModbusError::Frame(format!("unsupported function {}", f))),
+    }
+    .map_err(|e| ModbusError::Frame(format!("{:?}", e)))?;
+
+    let response = transport.exchange(request, timeout).await?;
+
+    if function <= 2 {
+        let mut data = Vec::new();
+        mreq.parse_bool(&response, &mut data)
+            .map_err(|e| ModbusError::Frame(format!("{:?}", e)))?;
+        Ok(data.first().copied().unwrap_or(false) as u16)
+    } else {
+        let mut data = Vec::new();
+        mreq.parse_u16(&response, &mut data)
+            .map_err(|e| ModbusError::Frame(format!("{:?}", e)))?;
+        data.first()
+            .copied()
+            .ok_or(ModbusError::Frame(String::from("no register in response")))
+    }
+}
+
+fn decode(high: u16, low: Option<u16>) -> Decoded {
+    let long = low.map(|low| ((high as u32) << 16) | low as u32);
+    Decoded {
+        u16: high,
+        i16: high as i16,
+        u32: long,
+        i32: long.map(|l| l as i32),
+        f32: long.map(f32::from_bits),
+    }
+}
+
+pub async fn scan(req: &ScanRequest, app: &AppHandle) -> Result<ScanReport, Error> {
+    let timeout = Duration::from_millis(req.timeout_ms.unwrap_or(1000));
+    let mut transport = Transport::open(req).await?;
+
+    let mut report = ScanReport {
+        responding_slaves: Vec::new(),
+        results: Vec::new(),
+    };
+
+    for slave_id in req.slave_from..=req.slave_to {
+        let mut responding = false;
+
+        for registry in req.registry_from..=req.registry_to {
+            app.emit_all("modbus_scan_progress", ScanProgress { slave_id, registry })
+                .unwrap();
+
+            match read_one(&mut transport, slave_id, req.function, registry, timeout).await {
+                Ok(raw) => {
+                    responding = true;
+                    report.results.push(ScanResult {
+                        slave_id,
+                        registry,
+                        raw: Some(raw),
+                        decoded: None,
+                        error: None,
+                    });
+                }
+                Err(ModbusError::Io(e)) => {
+                    debug!("slave {} registry {} : {}", slave_id, registry, e);
+                    // silence on the first register means nobody is there
+                    if !responding {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    // an exception, or a garbled frame, still tells the slave exists
+                    responding = true;
+                    report.results.push(ScanResult {
+                        slave_id,
+                        registry,
+                        raw: None,
+                        decoded: None,
+                        error: Some(e.to_string()),
+                    });
+                }
+            }
+        }
+
+        if responding {
+            info!("modbus scan found slave {}", slave_id);
+            report.responding_slaves.push(slave_id);
+        }
+    }
+
+    // decode once all registers are known, so 32 bit values can span two of them
+    if req.function >= 3 {
+        let raws: Vec<(u8, u16, Option<u16>)> = report
+            .results
+            .iter()
+            .map(|r| (r.slave_id, r.registry, r.raw))
+            .collect();
+
+        for r in report.results.iter_mut() {
+            if let Some(raw) = r.raw {
+                let low = raws
+                    .iter()
+                    .find(|(s, reg, _)| *s == r.slave_id && *reg == r.registry.wrapping_add(1))
+                    .and_then(|(_, _, raw)| *raw);
+                r.decoded = Some(decode(raw, low));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+// "1-10" or "5"
+fn parse_range<T: std::str::FromStr + Copy>(s: &str) -> Option<(T, T)> {
+    match s.split_once('-') {
+        Some((from, to)) => Some((from.trim().parse().ok()?, to.trim().parse().ok()?)),
+        None => {
+            let v = s.trim().parse().ok()?;
+            Some((v, v))
+        }
+    }
+}
+
+// build a ScanRequest from the arguments of the "scan" cli subcommand
+pub fn request_from_cli(matches: &Matches) -> Result<ScanRequest, String> {
+    let arg = |name: &str| -> Option<String> {
+        matches
+            .args
+            .get(name)
+            .and_then(|a| a.value.as_str())
+            .map(|s| s.to_string())
+    };
+    let num = |name: &str, default: u16| -> Result<u16, String> {
+        match arg(name) {
+            Some(v) => v.parse().map_err(|_| format!("invalid --{} {}", name, v)),
+            None => Ok(default),
+        }
+    };
+
+    let protocol = arg("protocol").unwrap_or(String::from("modbus-rtu"));
+
+    let serial = arg("port").map(|port| -> Result<Serial, String> {
+        Ok(Serial {
+            port,
+            baud_rate: num("baud-rate", 9600)?,
+            data_bits: num("data-bits", 8)?,
+            parity: arg("parity").unwrap_or(String::from("none")),
+            stop_bits: num("stop-bits", 1)?,
+            modbus: None,
+            ta612c: None,
+        })
+    });
+    let serial = match serial {
+        Some(s) => Some(s?),
+        None => None,
+    };
+
+    let tcp = match arg("ip") {
+        Some(ip) => Some(Tcp {
+            ip,
+            port: num("tcp-port", 502)?,
+            modbus: None,
+            http: None,
+        }),
+        None => None,
+    };
+
+    let slaves = arg("slaves").unwrap_or(String::from("1-10"));
+    let (slave_from, slave_to) =
+        parse_range::<u8>(&slaves).ok_or(format!("invalid --slaves {}", slaves))?;
+
+    let registers = arg("registers").unwrap_or(String::from("0-9"));
+    let (registry_from, registry_to) =
+        parse_range::<u16>(&registers).ok_or(format!("invalid --registers {}", registers))?;
+
+    Ok(ScanRequest {
+        serial,
+        tcp,
+        protocol,
+        function: num("function", 3)? as u8,
+        slave_from,
+        slave_to,
+        registry_from,
+        registry_to,
+        timeout_ms: Some(num("timeout", 1000)? as u64),
+    })
+}
@@ -0,0 +1,98 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{debug, warn};
+use serialport::{ClearBuffer, DataBits, Parity, SerialPort, StopBits};
+use std::{
+    io::{Error, ErrorKind},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+use tokio::sync::oneshot;
+
+use crate::config::Serial;
+
+type Job = Box<dyn FnOnce(&mut Box<dyn SerialPort>) + Send>;
+
+// serialport only offers blocking reads, so the port lives on its own thread
+// and async callers exchange jobs with it through channels. a stuck read can
+// then be abandoned by the caller without blocking a tokio worker thread
+pub struct SerialWorker {
+    sender: mpsc::Sender<Job>,
+}
+
+impl SerialWorker {
+    pub fn open(serial: &Serial) -> Result<SerialWorker, Error> {
+        let mut data_bits = DataBits::Eight;
+        if serial.data_bits == 7 {
+            data_bits = DataBits::Seven;
+        } else if serial.data_bits == 6 {
+            data_bits = DataBits::Six;
+        } else if serial.data_bits == 5 {
+            data_bits = DataBits::Five;
+        }
+
+        let mut parity = Parity::None;
+        let parity_lowercase = serial.parity.to_lowercase();
+        if parity_lowercase == "even" {
+            parity = Parity::Even;
+        } else if parity_lowercase == "odd" {
+            parity = Parity::Odd;
+        }
+
+        let mut stop_bits = StopBits::One;
+        if serial.stop_bits == 2 {
+            stop_bits = StopBits::Two;
+        }
+
+        let mut stream = serialport::new(&serial.port, serial.baud_rate as u32)
+            .data_bits(data_bits)
+            .parity(parity)
+            .stop_bits(stop_bits)
+            .timeout(Duration::from_secs(1))
+            .open()?;
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let port = serial.port.clone();
+
+        thread::Builder::new()
+            .name(format!("serial {}", port))
+            .spawn(move || {
+                // loop ends when the SerialWorker, and with it the sender, is dropped
+                for job in receiver {
+                    // drop leftovers of a transaction the caller gave up on
+                    if let Err(e) = stream.clear(ClearBuffer::Input) {
+                        warn!("failed to clear serial input buffer: {}", e);
+                    }
+                    job(&mut stream);
+                }
+                debug!("serial thread for {} stopped", port);
+            })?;
+
+        Ok(SerialWorker { sender })
+    }
+
+    // run f on the serial thread, waiting at most `timeout` for its result.
+    // on timeout the job keeps running until the port's own read timeout
+    // expires, but the caller is released immediately
+    pub async fn transact<F, R, E>(&self, timeout: Duration, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut Box<dyn SerialPort>) -> Result<R, E> + Send + 'static,
+        R: Send + 'static,
+        E: From<Error> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+
+        self.sender
+            .send(Box::new(move |stream| {
+                let _ = tx.send(f(stream));
+            }))
+            .map_err(|_| Error::new(ErrorKind::BrokenPipe, "serial thread stopped"))?;
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(Error::new(ErrorKind::BrokenPipe, "serial thread stopped").into()),
+            Err(_) => Err(Error::new(ErrorKind::TimedOut, "serial transaction timeout").into()),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::actuators::unix_millis;
+use crate::roastfile::{write_json_atomic, RoastFile};
+
+// a purchase of green coffee, weights in grams
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GreenLot {
+    #[serde(default)]
+    pub id: u64, // assigned by the inventory
+    pub name: String,
+    pub origin: String,
+    pub process: String,
+    pub supplier: String,
+    pub purchase_date: String, // YYYY-MM-DD
+    pub price: f64,            // of the whole lot
+    pub initial_weight: f64,
+    pub density: f64,                         // g/l
+    pub moisture: f64,                        // %
+    pub target_dtr: Option<f64>,              // development time ratio aimed at, %
+    pub target_drop_temperature: Option<f64>, // BT aimed at for DROP
+}
+
+// every change of stock after the purchase
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StockMovement {
+    pub lot_id: u64,
+    pub timestamp: u64, // unix time in milliseconds
+    pub delta: f64,     // grams, negative when taken out
+    pub reason: String, // roast, adjustment, ...
+    pub roast_path: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct LotStock {
+    pub lot: GreenLot,
+    pub stock: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Stored {
+    lots: Vec<GreenLot>,
+    movements: Vec<StockMovement>,
+    #[serde(default)]
+    next_id: u64, // never given again, roast files keep naming removed lots
+}
+
+// green lots and their stock, kept as json next to the app data
+pub struct Inventory {
+    path: Option<PathBuf>,
+    lots: Vec<GreenLot>,
+    movements: Vec<StockMovement>,
+    next_id: u64,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            lots: Vec::new(),
+            movements: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn open(&mut self, dir: &Path) {
+        let path = dir.join("inventory.json");
+        let stored: Stored = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("inventory {} unreadable : {}", path.display(), e);
+                Stored::default()
+            }),
+            Err(_) => Stored::default(),
+        };
+        // inventories stored before next_id start after their highest lot
+        self.next_id = stored
+            .lots
+            .iter()
+            .map(|l| l.id + 1)
+            .max()
+            .unwrap_or(1)
+            .max(stored.next_id);
+        self.lots = stored.lots;
+        self.movements = stored.movements;
+        info!(
+            "inventory opened : {} lots in {}",
+            self.lots.len(),
+            path.display()
+        );
+        self.path = Some(path);
+    }
+
+    fn store(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("inventory is not open")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let stored = Stored {
+            lots: self.lots.clone(),
+            movements: self.movements.clone(),
+            next_id: self.next_id,
+        };
+        write_json_atomic(path, &stored)
+    }
+
+    fn lot_index(&self, id: u64) -> Result<usize, String> {
+        self.lots
+            .iter()
+            .position(|l| l.id == id)
+            .ok_or(format!("no green lot {}", id))
+    }
+
+    pub fn lot(&self, id: u64) -> Option<&GreenLot> {
+        self.lots.iter().find(|l| l.id == id)
+    }
+
+    pub fn stock(&self, lot: &GreenLot) -> f64 {
+        lot.initial_weight
+            + self
+                .movements
+                .iter()
+                .filter(|m| m.lot_id == lot.id)
+                .map(|m| m.delta)
+                .sum::<f64>()
+    }
+
+    pub fn list(&self) -> Vec<LotStock> {
+        self.lots
+            .iter()
+            .map(|lot| LotStock {
+                lot: lot.clone(),
+                stock: self.stock(lot),
+            })
+            .collect()
+    }
+
+    pub fn add_lot(&mut self, mut lot: GreenLot) -> Result<GreenLot, String> {
+        lot.id = self.next_id;
+        self.next_id += 1;
+        self.lots.push(lot.clone());
+        self.store()?;
+        info!("green lot {} added : {}", lot.id, lot.name);
+        Ok(lot)
+    }
+
+    pub fn update_lot(&mut self, lot: GreenLot) -> Result<GreenLot, String> {
+        let i = self.lot_index(lot.id)?;
+        self.lots[i] = lot.clone();
+        self.store()?;
+        Ok(lot)
+    }
+
+    // the lot and its movements, roast files keep naming its id
+    pub fn remove_lot(&mut self, id: u64) -> Result<(), String> {
+        let i = self.lot_index(id)?;
+        self.lots.remove(i);
+        self.movements.retain(|m| m.lot_id != id);
+        self.store()?;
+        info!("green lot {} removed", id);
+        Ok(())
+    }
+
+    // e.g. a recount, or beans thrown away
+    pub fn adjust(&mut self, lot_id: u64, delta: f64, reason: &str) -> Result<f64, String> {
+        let i = self.lot_index(lot_id)?;
+        self.movements.push(StockMovement {
+            lot_id,
+            timestamp: unix_millis() as u64,
+            delta,
+            reason: reason.to_string(),
+            roast_path: None,
+        });
+        self.store()?;
+        Ok(self.stock(&self.lots[i]))
+    }
+
+    pub fn movements(&self, lot_id: u64) -> Vec<StockMovement> {
+        self.movements
+            .iter()
+            .filter(|m| m.lot_id == lot_id)
+            .cloned()
+            .collect()
+    }
+
+    // take the green weight of a saved roast out of its lot. saving the same
+    // roast again replaces its movement, so it is only deducted once
+    pub fn record_roast(&mut self, path: &Path, roast: &RoastFile) -> Result<(), String> {
+        let path = path.to_string_lossy().to_string();
+        let previous = self
+            .movements
+            .iter()
+            .position(|m| m.roast_path.as_deref() == Some(path.as_str()));
+
+        let lot_id = match roast.green_lot {
+            Some(id) => id,
+            None => {
+                // the lot was taken off the roast
+                if let Some(i) = previous {
+                    self.movements.remove(i);
+                    self.store()?;
+                }
+                return Ok(());
+            }
+        };
+        self.lot_index(lot_id)?;
+
+        let movement = StockMovement {
+            lot_id,
+            timestamp: unix_millis() as u64,
+            delta: -roast.weight_green,
+            reason: String::from("roast"),
+            roast_path: Some(path),
+        };
+        match previous {
+            Some(i) => self.movements[i] = movement,
+            None => self.movements.push(movement),
+        }
+        self.store()?;
+        info!("{} g taken from green lot {}", roast.weight_green, lot_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "roastcraft-inventory-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn lot(name: &str) -> GreenLot {
+        GreenLot {
+            id: 0,
+            name: name.to_string(),
+            origin: String::from("Kenya"),
+            process: String::from("washed"),
+            supplier: String::new(),
+            purchase_date: String::from("2024-03-01"),
+            price: 120.0,
+            initial_weight: 10000.0,
+            density: 720.0,
+            moisture: 10.5,
+            target_dtr: None,
+            target_drop_temperature: None,
+        }
+    }
+
+    #[test]
+    fn removed_lot_ids_are_not_given_again() {
+        let dir = temp_dir("ids");
+        let mut inventory = Inventory::new();
+        inventory.open(&dir);
+        assert_eq!(inventory.add_lot(lot("first")).unwrap().id, 1);
+        assert_eq!(inventory.add_lot(lot("second")).unwrap().id, 2);
+        inventory.remove_lot(2).unwrap();
+
+        let mut inventory = Inventory::new();
+        inventory.open(&dir);
+        assert_eq!(inventory.add_lot(lot("third")).unwrap().id, 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn next_id_of_an_inventory_stored_before_it() {
+        let dir = temp_dir("legacy");
+        let mut stored = lot("first");
+        stored.id = 4;
+        let content = serde_json::json!({ "lots": [stored], "movements": [] });
+        fs::write(dir.join("inventory.json"), content.to_string()).unwrap();
+
+        let mut inventory = Inventory::new();
+        inventory.open(&dir);
+        assert_eq!(inventory.list().len(), 1);
+        assert_eq!(inventory.add_lot(lot("second")).unwrap().id, 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,385 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::actuators::unix_millis;
+use crate::roastfile::{self, ChannelData, RoastFile};
+use crate::session::{Point, RoastEvent, SessionUpdate};
+
+// append-only record of a recording session, one SessionUpdate as json per line.
+// written as updates arrive, so an app crash loses nothing,
+// and synced to disk on a schedule, so a machine crash loses at most sync_interval
+pub struct Journal {
+    file: File,
+    path: PathBuf,
+    last_sync: Instant,
+    sync_interval: Duration,
+}
+
+impl Journal {
+    pub fn create(dir: &Path, sync_interval: Duration) -> Result<Journal, Error> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("roast-{}.jsonl", unix_millis()));
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(&path)?;
+        info!("journal created : {}", path.display());
+
+        Ok(Journal {
+            file,
+            path,
+            last_sync: Instant::now(),
+            sync_interval,
+        })
+    }
+
+    pub fn append(&mut self, update: &SessionUpdate) -> Result<(), Error> {
+        let mut line = serde_json::to_string(update)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+
+        // status changes are rare and worth keeping right away
+        let status = matches!(update, SessionUpdate::Status { .. });
+        if status || self.last_sync.elapsed() >= self.sync_interval {
+            self.file.sync_data()?;
+            self.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // the roast was saved or thrown away on purpose
+    pub fn discard(self) {
+        drop(self.file);
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("failed to remove journal {} : {}", self.path.display(), e);
+        }
+    }
+}
+
+// a journal left behind by a crash, or an unsaved roast
+#[derive(Serialize, Clone)]
+pub struct UnfinishedJournal {
+    pub path: String,
+    pub created: u128, // unix time in milliseconds
+    pub samples: usize,
+    pub last_timestamp: f64,
+}
+
+#[derive(Default)]
+struct Recovered {
+    channels: BTreeMap<String, Vec<Point>>,
+    manual_channels: BTreeMap<String, Vec<Point>>,
+    roast_events: BTreeMap<String, RoastEvent>,
+    samples: usize,
+    last_timestamp: f64,
+}
+
+// replay the complete lines of a journal. the tail after the last newline,
+// or after the first line which does not parse, is what a crash cut off
+fn replay(bytes: &[u8]) -> Recovered {
+    let mut r = Recovered::default();
+
+    let complete = match bytes.iter().rposition(|b| *b == b'\n') {
+        Some(i) => &bytes[..i],
+        None => return r,
+    };
+
+    for line in complete.split(|b| *b == b'\n') {
+        let update: SessionUpdate = match serde_json::from_slice(line) {
+            Ok(u) => u,
+            Err(_) => break,
+        };
+
+        match update {
+            SessionUpdate::Status { .. } => (),
+            SessionUpdate::Sample { timestamp, values } => {
+                for (id, value) in values {
+                    r.channels
+                        .entry(id)
+                        .or_default()
+                        .push(Point { timestamp, value });
+                }
+                r.samples += 1;
+                r.last_timestamp = timestamp;
+            }
+            SessionUpdate::ManualChange { channel_id, point } => {
+                r.manual_channels.entry(channel_id).or_default().push(point);
+            }
+            SessionUpdate::RoastEvent { event } => {
+                r.roast_events.insert(event.id.clone(), event);
+            }
+        }
+    }
+    r
+}
+
+fn created_of(path: &Path) -> u128 {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| s.strip_prefix("roast-"))
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn find_unfinished(dir: &Path) -> Vec<UnfinishedJournal> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut journals: Vec<UnfinishedJournal> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|x| x == "jsonl").unwrap_or(false))
+        .filter_map(|path| {
+            let r = replay(&fs::read(&path).ok()?);
+            Some(UnfinishedJournal {
+                path: path.to_string_lossy().to_string(),
+                created: created_of(&path),
+                samples: r.samples,
+                last_timestamp: r.last_timestamp,
+            })
+        })
+        .collect();
+
+    journals.sort_by_key(|j| j.created);
+    journals
+}
+
+// write the journal as a roast file, a journal without samples has nothing to recover
+pub fn recover(journal: &Path, roast_file: &Path) -> Result<(), Error> {
+    let r = replay(&fs::read(journal)?);
+    if r.samples == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} holds no samples, discard it", journal.display()),
+        ));
+    }
+
+    let channel_data = |map: BTreeMap<String, Vec<Point>>| {
+        map.into_iter()
+            .map(|(id, data_arr)| ChannelData { id, data_arr })
+            .collect()
+    };
+
+    let mut roast = RoastFile::new();
+    roast.title = String::from("recovered");
+    roast.channel_arr = channel_data(r.channels);
+    roast.manual_channel_arr = channel_data(r.manual_channels);
+    roast.roast_events = r.roast_events;
+    // the journal is created at START
+    roast.roast_epoch = match created_of(journal) {
+        0 => None,
+        created => Some(created as u64),
+    };
+
+    // the journal is kept when the roast would not load back
+    roastfile::save(roast_file, &roast).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    fs::remove_file(journal)?;
+    info!(
+        "journal {} recovered into {}",
+        journal.display(),
+        roast_file.display()
+    );
+    Ok(())
+}
+
+pub fn discard(journal: &Path) -> Result<(), Error> {
+    fs::remove_file(journal)?;
+    info!("journal {} discarded", journal.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal_bytes() -> Vec<u8> {
+        let sample = |timestamp: f64, bt: f64| SessionUpdate::Sample {
+            timestamp,
+            values: BTreeMap::from([(String::from("BT"), bt), (String::from("ET"), bt + 20.0)]),
+        };
+        let updates = vec![
+            SessionUpdate::Status {
+                status: crate::session::SessionStatus::Recording,
+                timer: 0.0,
+            },
+            SessionUpdate::ManualChange {
+                channel_id: String::from("gas"),
+                point: Point {
+                    timestamp: 0.0,
+                    value: 40.0,
+                },
+            },
+            sample(1.0, 180.5),
+            sample(2.0, 170.25),
+            SessionUpdate::RoastEvent {
+                event: RoastEvent {
+                    id: String::from("CHARGE"),
+                    timestamp: 2.0,
+                    value: 170.25,
+                },
+            },
+            sample(3.0, 160.0),
+            SessionUpdate::ManualChange {
+                channel_id: String::from("gas"),
+                point: Point {
+                    timestamp: 3.0,
+                    value: 60.0,
+                },
+            },
+            sample(4.0, 155.125),
+        ];
+
+        let mut bytes = Vec::new();
+        for u in updates {
+            bytes.extend(serde_json::to_string(&u).unwrap().as_bytes());
+            bytes.push(b'\n');
+        }
+        bytes
+    }
+
+    #[test]
+    fn replay_takes_only_complete_lines() {
+        let bytes = journal_bytes();
+        let lines: Vec<&[u8]> = bytes.split(|b| *b == b'\n').collect();
+
+        for cut in 0..=bytes.len() {
+            let r = replay(&bytes[..cut]);
+
+            // what the complete lines before the cut hold
+            let complete = bytes[..cut].iter().filter(|b| **b == b'\n').count();
+            let mut samples = 0;
+            let mut manual = 0;
+            let mut events = 0;
+            let mut last_timestamp = 0.0;
+            for line in &lines[..complete] {
+                match serde_json::from_slice(line).unwrap() {
+                    SessionUpdate::Sample { timestamp, .. } => {
+                        samples += 1;
+                        last_timestamp = timestamp;
+                    }
+                    SessionUpdate::ManualChange { .. } => manual += 1,
+                    SessionUpdate::RoastEvent { .. } => events += 1,
+                    SessionUpdate::Status { .. } => (),
+                }
+            }
+
+            assert_eq!(r.samples, samples, "cut at {}", cut);
+            assert_eq!(r.last_timestamp, last_timestamp, "cut at {}", cut);
+            assert_eq!(
+                r.channels.get("BT").map_or(0, |c| c.len()),
+                samples,
+                "cut at {}",
+                cut
+            );
+            assert_eq!(
+                r.manual_channels.get("gas").map_or(0, |c| c.len()),
+                manual,
+                "cut at {}",
+                cut
+            );
+            assert_eq!(r.roast_events.len(), events, "cut at {}", cut);
+        }
+
+        let r = replay(&bytes);
+        assert_eq!(r.samples, 4);
+        assert_eq!(r.channels["ET"][3].value, 175.125);
+        assert_eq!(r.roast_events["CHARGE"].timestamp, 2.0);
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "roastcraft-journal-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn recovers_a_cut_journal_into_a_roast_file() {
+        let dir = temp_dir("recover");
+        let journal = dir.join("roast-1700000000000.jsonl");
+        let bytes = journal_bytes();
+        // the crash cut the last sample in half
+        fs::write(&journal, &bytes[..bytes.len() - 10]).unwrap();
+
+        let roast_file = dir.join("recovered.json");
+        recover(&journal, &roast_file).unwrap();
+        assert!(!journal.exists());
+
+        let roast = roastfile::load(&roast_file).unwrap();
+        assert_eq!(roast.title, "recovered");
+        assert_eq!(roast.roast_epoch, Some(1700000000000));
+        let bt = &roast.channel("BT").unwrap().data_arr;
+        assert_eq!(bt.len(), 3);
+        assert_eq!(bt[2].value, 160.0);
+        assert_eq!(roast.manual_channel_arr[0].data_arr.len(), 2);
+        assert_eq!(roast.roast_events["CHARGE"].value, 170.25);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_what_would_not_load() {
+        let dir = temp_dir("refuse");
+        let roast_file = dir.join("recovered.json");
+
+        // cut before the first sample, after the status and a manual change
+        let empty = dir.join("roast-1.jsonl");
+        let bytes = journal_bytes();
+        let (cut, _) = bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(1)
+            .unwrap();
+        fs::write(&empty, &bytes[..=cut]).unwrap();
+        let e = recover(&empty, &roast_file).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // samples without BT, the roast file would not be valid
+        let no_bt = dir.join("roast-2.jsonl");
+        let update = SessionUpdate::Sample {
+            timestamp: 1.0,
+            values: BTreeMap::from([(String::from("ET"), 200.0)]),
+        };
+        fs::write(&no_bt, serde_json::to_string(&update).unwrap() + "\n").unwrap();
+        let e = recover(&no_bt, &roast_file).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // both journals are kept, nothing was written
+        assert!(empty.exists() && no_bt.exists());
+        assert!(!roast_file.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_unfinished_journals_oldest_first() {
+        let dir = temp_dir("find");
+        fs::write(dir.join("roast-2000.jsonl"), journal_bytes()).unwrap();
+        fs::write(dir.join("roast-1000.jsonl"), b"").unwrap();
+        fs::write(dir.join("notes.txt"), b"not a journal").unwrap();
+
+        let journals = find_unfinished(&dir);
+        assert_eq!(journals.len(), 2);
+        assert_eq!((journals[0].created, journals[0].samples), (1000, 0));
+        assert_eq!((journals[1].created, journals[1].samples), (2000, 4));
+        assert_eq!(journals[1].last_timestamp, 4.0);
+        assert!(find_unfinished(&dir.join("missing")).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,511 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::actuators::unix_millis;
+use crate::phases::PhaseReport;
+use crate::roastfile::{self, RoastFile};
+
+// one saved roast, what the library lists and searches
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LibraryEntry {
+    pub id: u64,
+    pub path: String,
+    pub date: u64, // unix time in milliseconds, START or the file time
+    pub title: String,
+    pub country: String,
+    pub process: String,
+    pub weight_green: f64,
+    pub weight_roasted: f64,
+    pub development_time: Option<f64>, // FC_START to DROP, seconds
+    pub drop_temperature: Option<f64>,
+    pub fc_temperature: Option<f64>, // BT at FC_START
+    pub green_lot: Option<u64>,
+    pub phases: Option<PhaseReport>, // as saved in the roast file
+    pub flavor_list: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct LibraryFilter {
+    pub text: Option<String>, // part of title, country, process, a flavor or a tag
+    pub tag: Option<String>,
+    pub from: Option<u64>, // date range, unix time in milliseconds
+    pub to: Option<u64>,
+    pub sort: Option<String>, // date, title, country, weight_green, development_time, drop_temperature, development_percent, auc
+    pub descending: Option<bool>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub skipped: Vec<String>, // file and reason
+}
+
+// the index of saved roasts, a sqlite database next to the app data
+pub struct Library {
+    connection: Option<Connection>,
+}
+
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+    CREATE TABLE IF NOT EXISTS roast (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        path TEXT NOT NULL UNIQUE,
+        date INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        country TEXT NOT NULL,
+        process TEXT NOT NULL,
+        weight_green REAL NOT NULL,
+        weight_roasted REAL NOT NULL,
+        development_time REAL,
+        drop_temperature REAL,
+        fc_temperature REAL,
+        green_lot INTEGER,
+        phases TEXT,
+        flavor_list TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS tag (
+        roast_id INTEGER NOT NULL REFERENCES roast (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (roast_id, tag)
+    );
+    CREATE INDEX IF NOT EXISTS roast_green_lot ON roast (green_lot, date);
+";
+
+// the columns of a LibraryEntry, tags as a json array
+const SELECT: &str = "
+    SELECT id, path, date, title, country, process, weight_green, weight_roasted,
+        development_time, drop_temperature, fc_temperature, green_lot, phases, flavor_list,
+        (SELECT json_group_array(tag) FROM (SELECT tag FROM tag WHERE roast_id = roast.id ORDER BY tag))
+    FROM roast";
+
+fn entry(row: &Row) -> rusqlite::Result<LibraryEntry> {
+    let json = |i: usize| -> rusqlite::Result<Vec<String>> {
+        Ok(serde_json::from_str(&row.get::<_, String>(i)?).unwrap_or_default())
+    };
+    Ok(LibraryEntry {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        date: row.get(2)?,
+        title: row.get(3)?,
+        country: row.get(4)?,
+        process: row.get(5)?,
+        weight_green: row.get(6)?,
+        weight_roasted: row.get(7)?,
+        development_time: row.get(8)?,
+        drop_temperature: row.get(9)?,
+        fc_temperature: row.get(10)?,
+        green_lot: row.get(11)?,
+        phases: row
+            .get::<_, Option<String>>(12)?
+            .and_then(|p| serde_json::from_str(&p).ok()),
+        flavor_list: json(13)?,
+        tags: json(14)?,
+    })
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Self { connection: None }
+    }
+
+    pub fn open(&mut self, dir: &Path) {
+        let db_path = dir.join("library.sqlite");
+        let connection = fs::create_dir_all(dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| Connection::open(&db_path).map_err(|e| e.to_string()))
+            .and_then(|c| {
+                c.execute_batch(SCHEMA)
+                    .map(|_| c)
+                    .map_err(|e| e.to_string())
+            });
+        self.connection = match connection {
+            Ok(c) => Some(c),
+            Err(e) => {
+                warn!("library {} unreadable : {}", db_path.display(), e);
+                return;
+            }
+        };
+        info!(
+            "library opened : {} roasts in {}",
+            self.count().unwrap_or(0),
+            db_path.display()
+        );
+    }
+
+    fn connection(&self) -> Result<&Connection, String> {
+        self.connection
+            .as_ref()
+            .ok_or(String::from("library is not open"))
+    }
+
+    fn count(&self) -> Result<u64, String> {
+        self.connection()?
+            .query_row("SELECT count(*) FROM roast", [], |row| row.get(0))
+            .map_err(|e| e.to_string())
+    }
+
+    fn entry(&self, id: u64) -> Result<LibraryEntry, String> {
+        self.connection()?
+            .query_row(&format!("{} WHERE id = ?1", SELECT), [id], entry)
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or(format!("no roast {} in the library", id))
+    }
+
+    // add a saved roast, or refresh it when the file is already indexed
+    pub fn index(&mut self, path: &Path, roast: &RoastFile) -> Result<LibraryEntry, String> {
+        let path = path.to_string_lossy().to_string();
+        let file_time = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64);
+
+        let event = |id: &str| roast.roast_events.get(id);
+        let development_time = match (event("FC_START"), event("DROP")) {
+            (Some(fc), Some(drop)) => Some(drop.timestamp - fc.timestamp),
+            _ => None,
+        };
+        let date = roast
+            .roast_epoch
+            .or(file_time)
+            .unwrap_or_else(|| unix_millis() as u64);
+        let phases = match &roast.phases {
+            Some(p) => Some(serde_json::to_string(p).map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let flavor_list = serde_json::to_string(&roast.flavor_list).map_err(|e| e.to_string())?;
+
+        // the id and tags stay when the file is indexed again
+        let id: u64 = self
+            .connection()?
+            .query_row(
+                "INSERT INTO roast (path, date, title, country, process, weight_green,
+                    weight_roasted, development_time, drop_temperature, fc_temperature,
+                    green_lot, phases, flavor_list)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+                ON CONFLICT (path) DO UPDATE SET date = excluded.date, title = excluded.title,
+                    country = excluded.country, process = excluded.process,
+                    weight_green = excluded.weight_green, weight_roasted = excluded.weight_roasted,
+                    development_time = excluded.development_time,
+                    drop_temperature = excluded.drop_temperature,
+                    fc_temperature = excluded.fc_temperature, green_lot = excluded.green_lot,
+                    phases = excluded.phases, flavor_list = excluded.flavor_list
+                RETURNING id",
+                params![
+                    path,
+                    date,
+                    roast.title,
+                    roast.country,
+                    roast.process,
+                    roast.weight_green,
+                    roast.weight_roasted,
+                    development_time,
+                    event("DROP").map(|e| e.value),
+                    event("FC_START").map(|e| e.value),
+                    roast.green_lot,
+                    phases,
+                    flavor_list,
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        self.entry(id)
+    }
+
+    pub fn list(&self, filter: &LibraryFilter) -> Result<Vec<LibraryEntry>, String> {
+        let mut statement = self
+            .connection()?
+            .prepare(&format!(
+                "{} WHERE (?1 IS NULL OR date >= ?1) AND (?2 IS NULL OR date <= ?2)
+                    AND (?3 IS NULL OR EXISTS
+                        (SELECT 1 FROM tag WHERE roast_id = roast.id AND tag = ?3))",
+                SELECT
+            ))
+            .map_err(|e| e.to_string())?;
+        let entries = statement
+            .query_map(params![filter.from, filter.to, filter.tag], entry)
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<LibraryEntry>>>())
+            .map_err(|e| e.to_string())?;
+
+        // text and sort as in the webview, lowercase beyond ascii and from the phases
+        let text = filter.text.as_ref().map(|t| t.to_lowercase());
+        let matches_text = |e: &LibraryEntry| match &text {
+            Some(t) => [&e.title, &e.country, &e.process]
+                .into_iter()
+                .chain(e.flavor_list.iter())
+                .chain(e.tags.iter())
+                .any(|s| s.to_lowercase().contains(t.as_str())),
+            None => true,
+        };
+        let mut entries: Vec<LibraryEntry> = entries.into_iter().filter(matches_text).collect();
+
+        let number = |v: Option<f64>| v.unwrap_or(f64::NEG_INFINITY);
+        let development_percent = |e: &LibraryEntry| {
+            number(
+                e.phases
+                    .as_ref()
+                    .and_then(|p| p.dev.as_ref())
+                    .map(|d| d.percent),
+            )
+        };
+        let auc = |e: &LibraryEntry| number(e.phases.as_ref().map(|p| p.total.auc));
+        match filter.sort.as_deref().unwrap_or("date") {
+            "title" => entries.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase())),
+            "country" => entries.sort_by(|a, b| a.country.cmp(&b.country)),
+            "weight_green" => entries.sort_by(|a, b| a.weight_green.total_cmp(&b.weight_green)),
+            "development_time" => entries
+                .sort_by(|a, b| number(a.development_time).total_cmp(&number(b.development_time))),
+            "drop_temperature" => entries
+                .sort_by(|a, b| number(a.drop_temperature).total_cmp(&number(b.drop_temperature))),
+            "development_percent" => {
+                entries.sort_by(|a, b| development_percent(a).total_cmp(&development_percent(b)))
+            }
+            "auc" => entries.sort_by(|a, b| auc(a).total_cmp(&auc(b))),
+            _ => entries.sort_by_key(|e| e.date),
+        }
+        if filter.descending.unwrap_or(false) {
+            entries.reverse();
+        }
+        Ok(entries)
+    }
+
+    // average first crack temperature of the last roasts of a green lot
+    pub fn fc_temperature(&self, green_lot: u64, roasts: usize) -> Option<f64> {
+        let average = self.connection().ok()?.query_row(
+            "SELECT avg(fc_temperature) FROM (SELECT fc_temperature FROM roast
+                WHERE green_lot = ?1 AND fc_temperature IS NOT NULL
+                ORDER BY date DESC LIMIT ?2)",
+            params![green_lot, roasts as u64],
+            |row| row.get(0),
+        );
+        match average {
+            Ok(average) => average,
+            Err(e) => {
+                warn!("first crack temperature of lot {} : {}", green_lot, e);
+                None
+            }
+        }
+    }
+
+    pub fn tag(&mut self, id: u64, tags: Vec<String>) -> Result<LibraryEntry, String> {
+        self.entry(id)?;
+        let connection = self.connection.as_mut().ok_or("library is not open")?;
+        let transaction = connection.transaction().map_err(|e| e.to_string())?;
+        transaction
+            .execute("DELETE FROM tag WHERE roast_id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+        for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            transaction
+                .execute(
+                    "INSERT OR IGNORE INTO tag (roast_id, tag) VALUES (?1, ?2)",
+                    params![id, tag],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        transaction.commit().map_err(|e| e.to_string())?;
+        self.entry(id)
+    }
+
+    // the title is changed in the roast file too
+    pub fn rename(&mut self, id: u64, title: &str) -> Result<LibraryEntry, String> {
+        let path = PathBuf::from(&self.entry(id)?.path);
+        let mut roast = roastfile::load(&path)?;
+        roast.title = title.to_string();
+        roastfile::save(&path, &roast)?;
+
+        self.connection()?
+            .execute(
+                "UPDATE roast SET title = ?2 WHERE id = ?1",
+                params![id, title],
+            )
+            .map_err(|e| e.to_string())?;
+        self.entry(id)
+    }
+
+    // forget the roast, and remove its file when asked to
+    pub fn delete(&mut self, id: u64, delete_file: bool) -> Result<(), String> {
+        let path = self.entry(id)?.path;
+        if delete_file {
+            fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+        self.connection()?
+            .execute("DELETE FROM roast WHERE id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+        info!("library entry {} removed : {}", id, path);
+        Ok(())
+    }
+}
+
+// the roast files in a folder and its subfolders
+fn roast_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), String> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("{} : {}", dir.display(), e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            roast_files(&path, paths)?;
+        } else if path.extension().map(|x| x == "json").unwrap_or(false) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+// index every roast file under a folder, those which do not load are reported.
+// the files are read without the library locked, it is only taken to index each
+pub fn import_folder(library: &Mutex<Library>, dir: &Path) -> Result<ImportReport, String> {
+    let mut report = ImportReport {
+        imported: 0,
+        skipped: Vec::new(),
+    };
+
+    let mut paths = Vec::new();
+    roast_files(dir, &mut paths)?;
+    for path in paths {
+        let indexed =
+            roastfile::load(&path).and_then(|roast| library.lock().unwrap().index(&path, &roast));
+        match indexed {
+            Ok(_) => report.imported += 1,
+            Err(e) => report.skipped.push(e),
+        }
+    }
+    info!(
+        "library import of {} : {} imported, {} skipped",
+        dir.display(),
+        report.imported,
+        report.skipped.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roastfile::ChannelData;
+    use crate::session::{Point, RoastEvent};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "roastcraft-library-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn roast(title: &str, epoch: u64, fc_temperature: f64) -> RoastFile {
+        let mut roast = RoastFile::new();
+        roast.title = title.to_string();
+        roast.country = String::from("Ethiopia");
+        roast.roast_epoch = Some(epoch);
+        roast.green_lot = Some(7);
+        roast.channel_arr = vec![ChannelData {
+            id: String::from("BT"),
+            data_arr: vec![Point {
+                timestamp: 0.0,
+                value: 200.0,
+            }],
+        }];
+        for (id, timestamp, value) in [("FC_START", 480.0, fc_temperature), ("DROP", 600.0, 210.0)]
+        {
+            roast.roast_events.insert(
+                id.to_string(),
+                RoastEvent {
+                    id: id.to_string(),
+                    timestamp,
+                    value,
+                },
+            );
+        }
+        roast
+    }
+
+    #[test]
+    fn import_tag_and_index_again() {
+        let dir = temp_dir("import");
+        let roasts = dir.join("roasts");
+        fs::create_dir_all(roasts.join("2024")).unwrap();
+        roastfile::save(&roasts.join("a.json"), &roast("first", 1000, 196.0)).unwrap();
+        roastfile::save(&roasts.join("2024/b.json"), &roast("second", 2000, 198.0)).unwrap();
+        fs::write(roasts.join("2024/broken.json"), "{").unwrap();
+        fs::write(roasts.join("notes.txt"), "not a roast").unwrap();
+
+        let library = Mutex::new(Library::new());
+        library.lock().unwrap().open(&dir);
+        let report = import_folder(&library, &roasts).unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].contains("broken.json"));
+
+        let mut library = library.into_inner().unwrap();
+        let all = library.list(&LibraryFilter::default()).unwrap();
+        assert_eq!(
+            all.iter().map(|e| e.title.as_str()).collect::<Vec<_>>(),
+            ["first", "second"]
+        );
+        assert_eq!(all[0].development_time, Some(120.0));
+        assert_eq!(all[0].drop_temperature, Some(210.0));
+        assert_eq!(library.fc_temperature(7, 1), Some(198.0));
+        assert_eq!(library.fc_temperature(7, 5), Some(197.0));
+        assert_eq!(library.fc_temperature(8, 5), None);
+
+        let first = all[0].id;
+        let tagged = library
+            .tag(
+                first,
+                vec![" light ".into(), "espresso".into(), "light".into()],
+            )
+            .unwrap();
+        assert_eq!(tagged.tags, ["espresso", "light"]);
+
+        // indexing the file again keeps its id and tags
+        let path = roasts.join("a.json");
+        let entry = library
+            .index(&path, &roast("first again", 1000, 196.0))
+            .unwrap();
+        assert_eq!(entry.id, first);
+        assert_eq!(entry.title, "first again");
+        assert_eq!(entry.tags, ["espresso", "light"]);
+
+        let filter = LibraryFilter {
+            tag: Some(String::from("espresso")),
+            ..Default::default()
+        };
+        assert_eq!(library.list(&filter).unwrap().len(), 1);
+        let filter = LibraryFilter {
+            text: Some(String::from("SECOND")),
+            ..Default::default()
+        };
+        assert_eq!(library.list(&filter).unwrap()[0].title, "second");
+
+        // tags go with the roast, the file stays
+        library.delete(first, false).unwrap();
+        assert!(path.exists());
+        assert_eq!(library.list(&filter).unwrap().len(), 1);
+        let count: u64 = library
+            .connection()
+            .unwrap()
+            .query_row("SELECT count(*) FROM tag", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+
+        // a new roast never takes the id of a deleted one
+        let entry = library.index(&path, &roast("first", 1000, 196.0)).unwrap();
+        assert!(entry.id > all[1].id);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,23 +3,79 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use log::{debug, trace, warn, LevelFilter};
-use std::fs::File;
+use log::{debug, info, trace, warn, LevelFilter};
+use std::fs::{self, File};
 use std::io::Read;
-use std::sync::Mutex;
-use tauri::async_runtime::{spawn, JoinHandle};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::async_runtime::{spawn, spawn_blocking, JoinHandle};
 use tauri::{CustomMenuItem, Manager, Menu, MenuItem, Submenu};
 use tauri_plugin_log::{fern::colors::ColoredLevelConfig, LogTarget};
-use tokio::time::{interval, Duration};
+use tokio::sync::mpsc;
+use tokio::time::{interval, sleep, Duration};
 
+use crate::actuators::{OutputChange, Outputs};
+use crate::alarms::{AlarmEngine, AlarmState};
+use crate::analytics::{self as ror_analytics, RorAnalytics, RorPoints, RorSettings};
+use crate::autopilot::{Autopilot, AutopilotState, AutopilotStatus, Profile};
+use crate::autosave::{Autosave, RoastNotes, SnapshotOrder};
+use crate::batch::{BatchQueue, BatchStart, PlannedBatch};
 use crate::config::Config;
-use crate::devices::Device;
+use crate::detector::EventDetector;
+use crate::devices::scanner::{ScanReport, ScanRequest};
+use crate::devices::{Device, WriteRequest};
+use crate::inventory::{GreenLot, Inventory, LotStock, StockMovement};
+use crate::journal::UnfinishedJournal;
+use crate::library::{ImportReport, Library, LibraryEntry, LibraryFilter};
+use crate::phases::PhaseReport;
+use crate::pid::{PidLoop, PidStatus};
+use crate::prediction::{FcPrediction, FcPredictor};
+use crate::roastfile::{RoastFile, FORMAT_VERSION};
+use crate::safety::{Interlock, SafetyAlarm};
+use crate::session::{Point, RoastSession, SessionSnapshot, SessionStatus, SessionUpdate};
+use crate::targeting::{DropCountdown, DropTarget, DropTargetRecord, DropTargeter};
 
+mod actuators;
+mod alarms;
+mod analytics;
+mod artisan;
+mod autopilot;
+mod autosave;
+mod batch;
 mod config;
+mod csv;
+mod detector;
 mod devices;
+mod inventory;
+mod journal;
+mod library;
+mod phases;
+mod pid;
+mod prediction;
+mod roastfile;
+mod safety;
+mod session;
+mod targeting;
 
 struct RoastCraftState {
     reader_handle: Option<JoinHandle<()>>,
+    watchdog_handle: Option<JoinHandle<()>>,
+    write_sender: Option<mpsc::Sender<WriteRequest>>,
+    outputs: Arc<tokio::sync::Mutex<Outputs>>,
+    pid: Arc<Mutex<PidLoop>>,
+    autopilot: Arc<Mutex<Autopilot>>,
+    interlock: Arc<Mutex<Interlock>>,
+    alarms: Arc<Mutex<AlarmEngine>>,
+    analytics: Arc<Mutex<RorAnalytics>>,
+    detector: Arc<Mutex<EventDetector>>,
+    predictor: Arc<Mutex<FcPredictor>>,
+    targeter: Arc<Mutex<DropTargeter>>,
+    session: Arc<Mutex<RoastSession>>,
+    library: Arc<Mutex<Library>>,
+    inventory: Arc<Mutex<Inventory>>,
+    batches: Arc<Mutex<BatchQueue>>,
+    autosave: Arc<Mutex<Autosave>>,
+    autosave_order: Arc<Mutex<SnapshotOrder>>,
     config: Config,
 }
 
@@ -27,11 +83,248 @@ impl RoastCraftState {
     fn new() -> Self {
         Self {
             reader_handle: None,
+            watchdog_handle: None,
+            write_sender: None,
+            outputs: Arc::new(tokio::sync::Mutex::new(Outputs::new())),
+            pid: Arc::new(Mutex::new(PidLoop::new())),
+            autopilot: Arc::new(Mutex::new(Autopilot::new())),
+            interlock: Arc::new(Mutex::new(Interlock::new())),
+            alarms: Arc::new(Mutex::new(AlarmEngine::new())),
+            analytics: Arc::new(Mutex::new(RorAnalytics::new())),
+            detector: Arc::new(Mutex::new(EventDetector::new())),
+            predictor: Arc::new(Mutex::new(FcPredictor::new())),
+            targeter: Arc::new(Mutex::new(DropTargeter::new())),
+            session: Arc::new(Mutex::new(RoastSession::new())),
+            library: Arc::new(Mutex::new(Library::new())),
+            inventory: Arc::new(Mutex::new(Inventory::new())),
+            batches: Arc::new(Mutex::new(BatchQueue::new())),
+            autosave: Arc::new(Mutex::new(Autosave::new())),
+            autosave_order: Arc::new(Mutex::new(SnapshotOrder::default())),
             config: Config::new(),
         }
     }
 }
 
+fn emit_session(app: &tauri::AppHandle, update: SessionUpdate) {
+    app.emit_all("session_update", update).unwrap();
+}
+
+// the alarm engine works on the session time base and roast events
+fn sync_alarms(state: &RoastCraftState) {
+    let (timer, events) = {
+        let session = state.session.lock().unwrap();
+        (session.timer(), session.event_times())
+    };
+    state.alarms.lock().unwrap().set_roast_events(timer, events);
+}
+
+// set_output may wait for the reader loop, which is usually our caller,
+// so automated outputs are applied in their own tasks
+fn send_outputs(
+    app: &tauri::AppHandle,
+    state: &RoastCraftState,
+    targets: Vec<(String, f64)>,
+    source: &'static str,
+) {
+    let interlock = state.interlock.lock().unwrap();
+    for (channel_id, value) in targets {
+        // a tripped interlock holds its outputs until acknowledged
+        if source != "safety" && interlock.is_tripped() && interlock.is_safe_output(&channel_id) {
+            continue;
+        }
+        if let Some(channel) = state.config.manual_channel(&channel_id).cloned() {
+            let outputs = state.outputs.clone();
+            let session = state.session.clone();
+            let app = app.clone();
+            spawn(async move {
+                if let Some(change) =
+                    actuators::apply_output(&app, &outputs, &channel, value, source).await
+                {
+                    let update = session
+                        .lock()
+                        .unwrap()
+                        .manual_change(&change.channel_id, change.value);
+                    if let Some(update) = update {
+                        emit_session(&app, update);
+                    }
+                }
+            });
+        }
+    }
+}
+
+// stop every automatic control and force the safe outputs
+fn trip(app: &tauri::AppHandle, state: &RoastCraftState, alarm: SafetyAlarm) {
+    {
+        let mut pid = state.pid.lock().unwrap();
+        let mut autopilot = state.autopilot.lock().unwrap();
+        autopilot.hand_over();
+        pid.disable();
+        app.emit_all("pid_status", pid.status()).unwrap();
+        app.emit_all("autopilot_status", autopilot.status())
+            .unwrap();
+    }
+
+    app.emit_all("log_event", format!("SAFETY : {}", alarm.reason))
+        .unwrap();
+    app.emit_all("safety_alarm", alarm).unwrap();
+
+    force_safe_outputs(app, state);
+}
+
+// every safe output is written again until its actuator confirms it or the
+// alarm is acknowledged, the alarm lists the ones not confirmed meanwhile
+fn force_safe_outputs(app: &tauri::AppHandle, state: &RoastCraftState) {
+    let safe_outputs = state.interlock.lock().unwrap().safe_outputs();
+    for (channel_id, value) in safe_outputs {
+        let channel = state.config.manual_channel(&channel_id).cloned();
+        let outputs = state.outputs.clone();
+        let session = state.session.clone();
+        let interlock = state.interlock.clone();
+        let app = app.clone();
+        spawn(async move {
+            loop {
+                let result = match &channel {
+                    Some(channel) if actuators::is_bound(channel) => {
+                        actuators::set_output(&outputs, channel, value, "safety").await
+                    }
+                    _ => Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "not a manual channel with an output",
+                    )),
+                };
+
+                // superseded by a write that slipped in before the trip, write again
+                let confirmed = match result {
+                    Ok(Some(change)) => {
+                        app.emit_all("output_changed", change.clone()).unwrap();
+                        let update = session
+                            .lock()
+                            .unwrap()
+                            .manual_change(&change.channel_id, change.value);
+                        if let Some(update) = update {
+                            emit_session(&app, update);
+                        }
+                        true
+                    }
+                    Ok(None) => false,
+                    Err(e) => {
+                        let message = format!("safety failed to set {} : {}", channel_id, e);
+                        warn!("{}", message);
+                        app.emit_all("log_event", message).unwrap();
+                        false
+                    }
+                };
+
+                let alarm = {
+                    let mut interlock = interlock.lock().unwrap();
+                    if !interlock.is_tripped() {
+                        return;
+                    }
+                    interlock.confirm_output(&channel_id, confirmed)
+                };
+                if let Some(alarm) = alarm {
+                    app.emit_all("safety_alarm", alarm).unwrap();
+                }
+                if confirmed {
+                    return;
+                }
+                sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+}
+
+// everything the backend does with a fresh sample, after it is sent to the webview
+fn process_sample(app: &tauri::AppHandle, json_value: &serde_json::Value) {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+
+    let update = state.session.lock().unwrap().sample(json_value);
+    if let Some(update) = update {
+        // the RoR goes first, the webview works with it when the sample comes
+        if let SessionUpdate::Sample { timestamp, values } = &update {
+            let ror = state.analytics.lock().unwrap().sample(*timestamp, values);
+            app.emit_all("ror_update", ror).unwrap();
+        }
+        emit_session(app, update);
+        detect_roast_events(app, &state);
+        predict_first_crack(app, &state);
+        target_drop(app, &state);
+        report_phases(app, &state);
+    }
+
+    let alarm = state.interlock.lock().unwrap().check_sample(json_value);
+    if let Some(alarm) = alarm {
+        trip(app, &state, alarm);
+    }
+
+    // one RoR for alarms, PID and the chart
+    let ror = state.analytics.lock().unwrap().current();
+
+    let fired = state.alarms.lock().unwrap().sample(json_value, &ror);
+    for alarm in fired {
+        let output = state.alarms.lock().unwrap().output(alarm.index);
+        if let Some(o) = output {
+            send_outputs(app, &state, vec![(o.channel_id, o.value)], "alarm");
+        }
+        if let Some(id) = &alarm.roast_event {
+            alarm_roast_event(app, &state, id);
+        }
+        app.emit_all("log_event", format!("alarm : {}", alarm.label))
+            .unwrap();
+        app.emit_all("alarm", alarm).unwrap();
+    }
+
+    let (output, status, output_channel, autopilot_status, changes) = {
+        let mut pid = state.pid.lock().unwrap();
+        let mut autopilot = state.autopilot.lock().unwrap();
+        let mut changes = Vec::new();
+
+        // the profile moves the set point, paused holds it
+        if autopilot.state() != AutopilotState::Off {
+            match autopilot.set_point(pid.ror_mode()) {
+                Some(sp) => pid.set_point(sp),
+                None => {
+                    autopilot.hand_over();
+                    pid.disable();
+                    app.emit_all("log_event", "autopilot profile ended, manual control")
+                        .unwrap();
+                }
+            }
+            if autopilot.state() == AutopilotState::Running {
+                changes = autopilot.due_changes();
+            }
+        }
+
+        (
+            pid.sample(json_value, &ror),
+            pid.status(),
+            pid.output_channel(),
+            autopilot.status(),
+            changes,
+        )
+    };
+
+    if output_channel.is_some() {
+        app.emit_all("pid_status", status).unwrap();
+    }
+
+    let source = match autopilot_status.state {
+        AutopilotState::Off => "pid",
+        _ => "autopilot",
+    };
+    if autopilot_status.state != AutopilotState::Off {
+        app.emit_all("autopilot_status", autopilot_status).unwrap();
+    }
+
+    let mut targets: Vec<(String, f64)> = changes;
+    if let (Some(value), Some(channel_id)) = (output, output_channel) {
+        targets.push((channel_id, value));
+    }
+    send_outputs(app, &state, targets, source);
+}
+
 #[tauri::command]
 async fn button_on_clicked(app: tauri::AppHandle) -> () {
     trace!("command called : button_on_clicked");
@@ -45,9 +338,29 @@ async fn button_on_clicked(app: tauri::AppHandle) -> () {
     match &state.reader_handle {
         Some(_handle) => warn!("reader_handle already exist"),
         None => {
+            let update = state.session.lock().unwrap().on(&state.config);
+            emit_session(&app, update);
+            // rules fired while preheating, or in the roast before, fire again
+            state.alarms.lock().unwrap().reset();
+            sync_alarms(&state);
+            state.autosave.lock().unwrap().reset();
+            state.analytics.lock().unwrap().reset();
+            state.predictor.lock().unwrap().reset();
+            state.targeter.lock().unwrap().reset();
+
+            let (write_sender, mut write_receiver) = mpsc::channel::<WriteRequest>(16);
+            state.write_sender = Some(write_sender.clone());
+            let outputs = state.outputs.clone();
+
             state.reader_handle = Some(spawn(async move {
                 let mut interval = interval(Duration::from_secs(2));
 
+                {
+                    let mut outputs = outputs.lock().await;
+                    outputs.reset_log();
+                    outputs.connect(&config, &write_sender);
+                }
+
                 let mut device: Box<dyn Device + Send>;
 
                 // serial has priority over tcp
@@ -66,15 +379,24 @@ async fn button_on_clicked(app: tauri::AppHandle) -> () {
                 }
 
                 loop {
-                    interval.tick().await;
-                    trace!("i am inside async process, 2 sec interval");
+                    tokio::select! {
+                        _ = interval.tick() => {
+                            trace!("i am inside async process, 2 sec interval");
+
+                            match device.read().await {
+                                Ok(json_value) => {
+                                    app2.emit_all("read_channels", &json_value).unwrap();
+                                    trace!("event read_channels emitted : {}", json_value);
 
-                    match device.read().await {
-                        Ok(json_value) => {
-                            app2.emit_all("read_channels", &json_value).unwrap();
-                            trace!("event read_channels emitted : {}", json_value);
+                                    process_sample(&app2, &json_value);
+                                }
+                                Err(_) => {}
+                            }
+                        }
+                        Some(req) = write_receiver.recv() => {
+                            let res = device.write(&req.channel, req.value).await;
+                            let _ = req.reply.send(res);
                         }
-                        Err(_) => {}
                     }
                 }
             }));
@@ -82,7 +404,30 @@ async fn button_on_clicked(app: tauri::AppHandle) -> () {
             debug!(
                 "spawned reader_handle : {:?}",
                 state.reader_handle.as_ref().unwrap()
-            )
+            );
+
+            // stale data and heartbeat are checked apart from the reader loop,
+            // which is exactly what may hang
+            state.interlock.lock().unwrap().start();
+            let app3 = app.clone();
+            state.watchdog_handle = Some(spawn(async move {
+                let mut interval = interval(Duration::from_secs(1));
+                loop {
+                    interval.tick().await;
+
+                    let state_mutex = app3.state::<Mutex<RoastCraftState>>();
+                    let state = state_mutex.lock().unwrap();
+                    let alarm = state.interlock.lock().unwrap().check_timeouts();
+                    if let Some(alarm) = alarm {
+                        trip(&app3, &state, alarm);
+                    }
+
+                    let due = state.autosave.lock().unwrap().is_due();
+                    if due {
+                        autosave(&app3, &state);
+                    }
+                }
+            }));
         }
     }
 }
@@ -102,11 +447,1071 @@ async fn button_off_clicked(app: tauri::AppHandle) -> () {
                 state.reader_handle.as_ref().unwrap()
             );
             state.reader_handle = None;
+            state.write_sender = None;
+
+            if let Some(handle) = state.watchdog_handle.take() {
+                handle.abort();
+            }
+            state.interlock.lock().unwrap().stop();
+
+            let update = state.session.lock().unwrap().off();
+            emit_session(&app, update);
+            autosave_on_stop(&app, &state);
+
+            let outputs = state.outputs.clone();
+            spawn(async move { outputs.lock().await.disconnect() });
         }
         None => warn!("reader_handle is None"),
     }
 }
 
+#[tauri::command]
+async fn set_output(app: tauri::AppHandle, channel_id: String, value: f64) -> Result<(), String> {
+    trace!("command called : set_output");
+
+    let (channel, outputs, session) = {
+        let state_mutex = app.state::<Mutex<RoastCraftState>>();
+        let state = state_mutex.lock().unwrap();
+
+        let channel = state
+            .config
+            .manual_channel(&channel_id)
+            .cloned()
+            .ok_or(format!("unknown manual channel {}", channel_id))?;
+
+        let interlock = state.interlock.lock().unwrap();
+        if interlock.is_tripped() && interlock.is_safe_output(&channel_id) {
+            return Err(format!(
+                "{} is held safe, acknowledge the safety alarm first",
+                channel_id
+            ));
+        }
+        drop(interlock);
+
+        // moving the slider of the pid output takes over from the controller
+        let mut pid = state.pid.lock().unwrap();
+        let mut autopilot = state.autopilot.lock().unwrap();
+        if pid.is_enabled() && pid.output_channel().as_deref() == Some(channel_id.as_str()) {
+            if autopilot.state() != AutopilotState::Off {
+                autopilot.hand_over();
+                app.emit_all("autopilot_status", autopilot.status())
+                    .unwrap();
+                app.emit_all("log_event", "autopilot handed over by manual change")
+                    .unwrap();
+            }
+            pid.disable();
+            app.emit_all("pid_status", pid.status()).unwrap();
+            app.emit_all("log_event", "pid disabled by manual change")
+                .unwrap();
+        } else if autopilot.release(&channel_id) {
+            app.emit_all(
+                "log_event",
+                format!("autopilot stopped replaying {}", channel_id),
+            )
+            .unwrap();
+        }
+
+        (channel, state.outputs.clone(), state.session.clone())
+    };
+
+    // the roast records what reached the roaster, or the value of a channel
+    // without an output, which is only logged
+    let applied = if actuators::is_bound(&channel) {
+        match actuators::set_output(&outputs, &channel, value, "manual").await {
+            Ok(Some(change)) => {
+                let value = change.value;
+                app.emit_all("output_changed", change).unwrap();
+                Some(value)
+            }
+            Ok(None) => None,
+            Err(e) => return Err(format!("failed to set {} : {}", channel_id, e)),
+        }
+    } else {
+        Some(value.clamp(channel.min as f64, channel.max as f64))
+    };
+
+    if let Some(value) = applied {
+        let update = session.lock().unwrap().manual_change(&channel_id, value);
+        if let Some(update) = update {
+            emit_session(&app, update);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_output_log(app: tauri::AppHandle) -> Vec<OutputChange> {
+    let outputs = {
+        let state_mutex = app.state::<Mutex<RoastCraftState>>();
+        let state = state_mutex.lock().unwrap();
+        state.outputs.clone()
+    };
+    let log = outputs.lock().await.log().clone();
+    log
+}
+
+#[tauri::command]
+async fn pid_enable(app: tauri::AppHandle, set_point: Option<f64>) -> Result<PidStatus, String> {
+    trace!("command called : pid_enable");
+
+    let (pid, outputs, channel) = {
+        let state_mutex = app.state::<Mutex<RoastCraftState>>();
+        let state = state_mutex.lock().unwrap();
+        if state.interlock.lock().unwrap().is_tripped() {
+            return Err(String::from("acknowledge the safety alarm first"));
+        }
+        let channel_id = state.pid.lock().unwrap().output_channel();
+        let channel = channel_id.and_then(|id| state.config.manual_channel(&id).cloned());
+        (state.pid.clone(), state.outputs.clone(), channel)
+    };
+
+    let channel = channel.ok_or(String::from("pid output is not a manual channel"))?;
+
+    // continue from the last value sent, bumpless transfer from manual
+    let current_output = outputs.lock().await.last_value(&channel);
+
+    let mut pid = pid.lock().unwrap();
+    if let Some(sp) = set_point {
+        pid.set_point(sp);
+    }
+    pid.enable(current_output)?;
+    app.emit_all("pid_status", pid.status()).unwrap();
+    Ok(pid.status())
+}
+
+#[tauri::command]
+async fn pid_disable(app: tauri::AppHandle) -> PidStatus {
+    trace!("command called : pid_disable");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let mut pid = state.pid.lock().unwrap();
+    pid.disable();
+    app.emit_all("pid_status", pid.status()).unwrap();
+    pid.status()
+}
+
+#[tauri::command]
+async fn pid_set_point(app: tauri::AppHandle, set_point: f64) -> PidStatus {
+    trace!("command called : pid_set_point");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let mut pid = state.pid.lock().unwrap();
+    pid.set_point(set_point);
+    app.emit_all("pid_status", pid.status()).unwrap();
+    pid.status()
+}
+
+#[tauri::command]
+async fn pid_tune(app: tauri::AppHandle, kp: f64, ki: f64, kd: f64) -> PidStatus {
+    trace!("command called : pid_tune");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let mut pid = state.pid.lock().unwrap();
+    pid.tune(kp, ki, kd);
+    pid.status()
+}
+
+#[tauri::command]
+async fn get_pid_status(app: tauri::AppHandle) -> PidStatus {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let status = state.pid.lock().unwrap().status();
+    status
+}
+
+// time is the roast time from CHARGE in seconds, replay lists the manual
+// channels whose recorded changes are played back along with the profile
+#[tauri::command]
+async fn autopilot_start(
+    app: tauri::AppHandle,
+    profile: Profile,
+    time: f64,
+    replay: Vec<String>,
+) -> Result<AutopilotStatus, String> {
+    trace!("command called : autopilot_start");
+
+    let (pid, autopilot, outputs, channel) = {
+        let state_mutex = app.state::<Mutex<RoastCraftState>>();
+        let state = state_mutex.lock().unwrap();
+        if state.interlock.lock().unwrap().is_tripped() {
+            return Err(String::from("acknowledge the safety alarm first"));
+        }
+        let channel_id = state.pid.lock().unwrap().output_channel();
+        let channel = channel_id.and_then(|id| state.config.manual_channel(&id).cloned());
+        (
+            state.pid.clone(),
+            state.autopilot.clone(),
+            state.outputs.clone(),
+            channel,
+        )
+    };
+
+    let channel = channel.ok_or(String::from(
+        "autopilot needs a [pid] driving a manual channel",
+    ))?;
+    let current_output = outputs.lock().await.last_value(&channel);
+
+    // the controller owns the pid output, it is not replayed
+    let replay: Vec<String> = replay
+        .into_iter()
+        .filter(|id| *id != channel.channel_id)
+        .collect();
+
+    let mut pid = pid.lock().unwrap();
+    let mut autopilot = autopilot.lock().unwrap();
+    autopilot.start(profile, time, &replay)?;
+    if let Some(sp) = autopilot.set_point(pid.ror_mode()) {
+        pid.set_point(sp);
+    }
+    if !pid.is_enabled() {
+        pid.enable(current_output)?;
+    }
+
+    app.emit_all("pid_status", pid.status()).unwrap();
+    app.emit_all("autopilot_status", autopilot.status())
+        .unwrap();
+    app.emit_all("log_event", "autopilot started").unwrap();
+    Ok(autopilot.status())
+}
+
+#[tauri::command]
+async fn autopilot_pause(app: tauri::AppHandle) -> Result<AutopilotStatus, String> {
+    trace!("command called : autopilot_pause");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let mut autopilot = state.autopilot.lock().unwrap();
+    autopilot.pause()?;
+    app.emit_all("autopilot_status", autopilot.status())
+        .unwrap();
+    app.emit_all("log_event", "autopilot paused").unwrap();
+    Ok(autopilot.status())
+}
+
+#[tauri::command]
+async fn autopilot_resume(app: tauri::AppHandle) -> Result<AutopilotStatus, String> {
+    trace!("command called : autopilot_resume");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let mut autopilot = state.autopilot.lock().unwrap();
+    autopilot.resume()?;
+    app.emit_all("autopilot_status", autopilot.status())
+        .unwrap();
+    app.emit_all("log_event", "autopilot resumed").unwrap();
+    Ok(autopilot.status())
+}
+
+// stop the autopilot and its pid, leaving every output at its current value
+#[tauri::command]
+async fn autopilot_hand_over(app: tauri::AppHandle) -> AutopilotStatus {
+    trace!("command called : autopilot_hand_over");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let mut pid = state.pid.lock().unwrap();
+    let mut autopilot = state.autopilot.lock().unwrap();
+    autopilot.hand_over();
+    pid.disable();
+    app.emit_all("pid_status", pid.status()).unwrap();
+    app.emit_all("autopilot_status", autopilot.status())
+        .unwrap();
+    app.emit_all("log_event", "autopilot handed over to manual")
+        .unwrap();
+    autopilot.status()
+}
+
+#[tauri::command]
+async fn get_autopilot_status(app: tauri::AppHandle) -> AutopilotStatus {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let status = state.autopilot.lock().unwrap().status();
+    status
+}
+
+// utc_offset in minutes, the batch counter resets on the local day. planned is
+// the id of the queued batch loaded in the webview
+#[tauri::command]
+async fn session_start(
+    app: tauri::AppHandle,
+    utc_offset: i64,
+    planned: Option<u64>,
+) -> Result<BatchStart, String> {
+    trace!("command called : session_start");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let mut session = state.session.lock().unwrap();
+    let update = session.start()?;
+    let start =
+        state
+            .batches
+            .lock()
+            .unwrap()
+            .start(state.config.batch.as_ref(), utc_offset, planned);
+    session.set_batch(start.batch.clone());
+    drop(session);
+    state.autosave.lock().unwrap().start(utc_offset);
+
+    emit_session(&app, update);
+    state.alarms.lock().unwrap().reset();
+    sync_alarms(&state);
+    Ok(start)
+}
+
+#[tauri::command]
+async fn session_stop(app: tauri::AppHandle) -> Result<(), String> {
+    trace!("command called : session_stop");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let update = state.session.lock().unwrap().stop()?;
+    emit_session(&app, update);
+    autosave_on_stop(&app, &state);
+    Ok(())
+}
+
+#[tauri::command]
+async fn session_reset(app: tauri::AppHandle) -> () {
+    trace!("command called : session_reset");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let update = state.session.lock().unwrap().reset(&state.config);
+    emit_session(&app, update);
+    sync_alarms(&state);
+    state.autosave.lock().unwrap().reset();
+    state.analytics.lock().unwrap().reset();
+    state.predictor.lock().unwrap().reset();
+    state.targeter.lock().unwrap().reset();
+}
+
+// a roast event of the session marked or moved, by hand or detected
+fn roast_event_marked(app: &tauri::AppHandle, state: &RoastCraftState, update: SessionUpdate) {
+    let drop = match &update {
+        SessionUpdate::RoastEvent { event } if event.id == "DROP" => Some(event.timestamp),
+        _ => None,
+    };
+    emit_session(app, update);
+    sync_alarms(state);
+    drop_target_changed(app, state);
+
+    // the planned batch is roasted, the queue moves on
+    if let Some(drop) = drop {
+        if let Some(next) = state.batches.lock().unwrap().dropped() {
+            app.emit_all("batch_queue_changed", next).unwrap();
+        }
+        if let Some(config) = &state.config.autosave {
+            state.autosave.lock().unwrap().schedule(config);
+        }
+        let ror = state.analytics.lock().unwrap().set_drop(drop);
+        app.emit_all("ror_update", ror).unwrap();
+    }
+    report_phases(app, state);
+}
+
+// an alarm rule marks its roast event, unless it is marked already
+fn alarm_roast_event(app: &tauri::AppHandle, state: &RoastCraftState, id: &str) {
+    let update = {
+        let mut session = state.session.lock().unwrap();
+        if session.roast_events().contains_key(id) {
+            return;
+        }
+        session.mark_event(id, None, None)
+    };
+    match update {
+        Ok(Some(update)) => roast_event_marked(app, state, update),
+        Ok(None) => (),
+        Err(e) => warn!("alarm failed to mark {} : {}", id, e),
+    }
+}
+
+// roast events found in BT with the latest sample, marked like the webview would
+fn detect_roast_events(app: &tauri::AppHandle, state: &RoastCraftState) {
+    let window = state.analytics.lock().unwrap().settings().window;
+    let detected = {
+        let session = state.session.lock().unwrap();
+        state
+            .detector
+            .lock()
+            .unwrap()
+            .detect(session.channel("BT"), window, session.roast_events())
+    };
+
+    for d in detected {
+        let update = state.session.lock().unwrap().mark_event(
+            &d.event.id,
+            Some(d.event.timestamp),
+            Some(d.event.value),
+        );
+        match update {
+            Ok(Some(update)) => {
+                app.emit_all("roast_event_detected", d).unwrap();
+                roast_event_marked(app, state, update);
+            }
+            Ok(None) => (),
+            Err(e) => warn!("failed to mark detected {} : {}", d.event.id, e),
+        }
+    }
+}
+
+// first crack from the BT RoR trend, against the temperature of the lot's earlier roasts
+fn predict_first_crack(app: &tauri::AppHandle, state: &RoastCraftState) {
+    let lot = state.autosave.lock().unwrap().notes().green_lot;
+    let lot_temperature = lot.and_then(|id| {
+        let roasts = state.predictor.lock().unwrap().lot_roasts();
+        state.library.lock().unwrap().fc_temperature(id, roasts)
+    });
+
+    let update = {
+        let session = state.session.lock().unwrap();
+        let analytics = state.analytics.lock().unwrap();
+        state.predictor.lock().unwrap().update(
+            session.channel("BT"),
+            analytics.filtered("BT"),
+            session.roast_events(),
+            lot_temperature,
+        )
+    };
+    if let Some(prediction) = update {
+        app.emit_all("fc_prediction", prediction).unwrap();
+    }
+}
+
+// DROP projected for the target DTR or BT, of the roast, its green lot or the config
+fn target_drop(app: &tauri::AppHandle, state: &RoastCraftState) {
+    let lot = state.autosave.lock().unwrap().notes().green_lot;
+    let lot_target = lot
+        .and_then(|id| {
+            let inventory = state.inventory.lock().unwrap();
+            inventory.lot(id).map(|l| DropTarget {
+                dtr: l.target_dtr,
+                temperature: l.target_drop_temperature,
+            })
+        })
+        .unwrap_or_default();
+
+    let update = {
+        let session = state.session.lock().unwrap();
+        let analytics = state.analytics.lock().unwrap();
+        let mut targeter = state.targeter.lock().unwrap();
+        targeter.set_lot(lot_target);
+        targeter.update(
+            session.channel("BT"),
+            analytics.filtered("BT"),
+            session.roast_events(),
+        )
+    };
+    if let Some(countdown) = update.countdown {
+        app.emit_all("drop_countdown", countdown).unwrap();
+    }
+    for alarm in update.alarms {
+        app.emit_all("log_event", format!("alarm : {}", alarm.label))
+            .unwrap();
+        app.emit_all("drop_alarm", alarm).unwrap();
+    }
+    if let Some(record) = update.record {
+        app.emit_all("drop_target", record).unwrap();
+    }
+}
+
+// the target and achieved values, after a roast event or a new target
+fn drop_target_changed(app: &tauri::AppHandle, state: &RoastCraftState) {
+    let record = {
+        let session = state.session.lock().unwrap();
+        state
+            .targeter
+            .lock()
+            .unwrap()
+            .update_record(session.roast_events())
+    };
+    if let Some(record) = record {
+        app.emit_all("drop_target", record).unwrap();
+    }
+}
+
+// the target of this roast, none goes back to the green lot or config
+#[tauri::command]
+async fn set_drop_target(
+    app: tauri::AppHandle,
+    target: Option<DropTarget>,
+) -> Option<DropTargetRecord> {
+    trace!("command called : set_drop_target");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    state.targeter.lock().unwrap().set_roast(target);
+    drop_target_changed(&app, &state);
+    let record = state.targeter.lock().unwrap().record();
+    record
+}
+
+#[tauri::command]
+async fn get_drop_target(app: tauri::AppHandle) -> Option<DropTargetRecord> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let record = state.targeter.lock().unwrap().record();
+    record
+}
+
+#[tauri::command]
+async fn get_drop_countdown(app: tauri::AppHandle) -> Option<DropCountdown> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let countdown = state.targeter.lock().unwrap().last();
+    countdown
+}
+
+// the phases of the session so far, with the RoR shown in the webview
+fn session_phases(state: &RoastCraftState) -> Option<PhaseReport> {
+    let session = state.session.lock().unwrap();
+    let analytics = state.analytics.lock().unwrap();
+    phases::report(
+        session.channel("BT"),
+        analytics.smoothed("BT"),
+        session.roast_events(),
+        session.manual_channels(),
+        phases::auc_base(&state.config),
+    )
+}
+
+fn report_phases(app: &tauri::AppHandle, state: &RoastCraftState) {
+    if let Some(report) = session_phases(state) {
+        app.emit_all("phase_report", report).unwrap();
+    }
+}
+
+#[tauri::command]
+async fn get_phase_report(app: tauri::AppHandle) -> Option<PhaseReport> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    session_phases(&state)
+}
+
+// phases of a loaded roast, with the configured RoR settings
+#[tauri::command]
+async fn compute_phases(app: tauri::AppHandle, roast: RoastFile) -> Option<PhaseReport> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let settings = state.analytics.lock().unwrap().settings().clone();
+    phases::of_roast(&roast, &settings, phases::auc_base(&state.config))
+}
+
+// mark, or move, a roast event, timestamp in seconds from START
+#[tauri::command]
+async fn session_mark_event(
+    app: tauri::AppHandle,
+    event_id: String,
+    timestamp: Option<f64>,
+    value: Option<f64>,
+) -> Result<(), String> {
+    trace!("command called : session_mark_event");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let update = state
+        .session
+        .lock()
+        .unwrap()
+        .mark_event(&event_id, timestamp, value)?;
+    if let Some(update) = update {
+        roast_event_marked(&app, &state, update);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn batch_queue_list(app: tauri::AppHandle) -> Vec<PlannedBatch> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let planned = state.batches.lock().unwrap().list();
+    planned
+}
+
+#[tauri::command]
+async fn batch_queue_add(
+    app: tauri::AppHandle,
+    planned: PlannedBatch,
+) -> Result<PlannedBatch, String> {
+    trace!("command called : batch_queue_add");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let planned = state.batches.lock().unwrap().add(planned);
+    planned
+}
+
+#[tauri::command]
+async fn batch_queue_remove(app: tauri::AppHandle, id: u64) -> Result<(), String> {
+    trace!("command called : batch_queue_remove");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let result = state.batches.lock().unwrap().remove(id);
+    result
+}
+
+#[tauri::command]
+async fn batch_queue_move(app: tauri::AppHandle, id: u64, index: usize) -> Result<(), String> {
+    trace!("command called : batch_queue_move");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let result = state.batches.lock().unwrap().move_to(id, index);
+    result
+}
+
+// RoR of a loaded roast or a ghost, with the configured windows
+#[tauri::command]
+async fn compute_ror(
+    app: tauri::AppHandle,
+    channel_id: String,
+    data: Vec<Point>,
+    drop: Option<f64>,
+) -> RorPoints {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let settings = state.analytics.lock().unwrap().settings().clone();
+    ror_analytics::compute(&channel_id, &data, drop, &settings)
+}
+
+#[tauri::command]
+async fn get_fc_prediction(app: tauri::AppHandle) -> Option<FcPrediction> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let prediction = state.predictor.lock().unwrap().last();
+    prediction
+}
+
+#[tauri::command]
+async fn get_session(app: tauri::AppHandle) -> SessionSnapshot {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let snapshot = state.session.lock().unwrap().snapshot();
+    snapshot
+}
+
+#[tauri::command]
+async fn get_alarms(app: tauri::AppHandle) -> Vec<AlarmState> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let alarms = state.alarms.lock().unwrap().states();
+    alarms
+}
+
+#[tauri::command]
+async fn load_roast_file(app: tauri::AppHandle, path: String) -> Result<RoastFile, String> {
+    trace!("command called : load_roast_file");
+
+    let path = PathBuf::from(path);
+    if path.extension().map(|x| x == "alog").unwrap_or(false) {
+        // artisan roasts, in the temperature unit of this machine
+        let temperature_unit = {
+            let state_mutex = app.state::<Mutex<RoastCraftState>>();
+            let state = state_mutex.lock().unwrap();
+            state.config.temperature_unit.clone()
+        };
+        artisan::load(&path, &temperature_unit)
+    } else if path.extension().map(|x| x == "csv").unwrap_or(false) {
+        csv::load(&path)
+    } else {
+        roastfile::load(&path)
+    }
+}
+
+#[tauri::command]
+async fn save_roast_file(
+    app: tauri::AppHandle,
+    path: String,
+    roast: RoastFile,
+) -> Result<(), String> {
+    trace!("command called : save_roast_file");
+
+    let store = {
+        let state_mutex = app.state::<Mutex<RoastCraftState>>();
+        let state = state_mutex.lock().unwrap();
+        RoastStore::of(&state)
+    };
+    store_roast(&app, &store, &PathBuf::from(path), roast)
+}
+
+// what saving a roast takes from the state, so the file is written, indexed
+// and taken from stock without the state locked
+struct RoastStore {
+    config: Config,
+    ror_settings: RorSettings,
+    session: Arc<Mutex<RoastSession>>,
+    library: Arc<Mutex<Library>>,
+    inventory: Arc<Mutex<Inventory>>,
+}
+
+impl RoastStore {
+    fn of(state: &RoastCraftState) -> Self {
+        Self {
+            config: state.config.clone(),
+            ror_settings: state.analytics.lock().unwrap().settings().clone(),
+            session: state.session.clone(),
+            library: state.library.clone(),
+            inventory: state.inventory.clone(),
+        }
+    }
+}
+
+// save with the device config, and keep the library and inventory up to date
+fn store_roast(
+    app: &tauri::AppHandle,
+    store: &RoastStore,
+    path: &Path,
+    mut roast: RoastFile,
+) -> Result<(), String> {
+    roast.format_version = FORMAT_VERSION;
+    roast.config = Some(store.config.clone());
+    roast.phases = phases::of_roast(&roast, &store.ror_settings, phases::auc_base(&store.config));
+    roastfile::save(path, &roast)?;
+    store.session.lock().unwrap().saved(&roast);
+
+    // every saved roast is in the library
+    let indexed = store.library.lock().unwrap().index(path, &roast);
+    match indexed {
+        Ok(_) => app.emit_all("library_changed", ()).unwrap(),
+        Err(e) => warn!("failed to index {} : {}", path.display(), e),
+    }
+
+    // and its green coffee taken from stock
+    let recorded = store.inventory.lock().unwrap().record_roast(path, &roast);
+    match recorded {
+        Ok(_) => app.emit_all("inventory_changed", ()).unwrap(),
+        Err(e) => warn!("failed to deduct stock for {} : {}", path.display(), e),
+    }
+    Ok(())
+}
+
+// the session with the latest notes, into the autosave directory. the
+// snapshot is taken with the state locked and saved in a task of its own
+fn autosave(app: &tauri::AppHandle, state: &RoastCraftState) {
+    let config = match &state.config.autosave {
+        Some(config) => config,
+        None => return,
+    };
+    let mut roast = {
+        let session = state.session.lock().unwrap();
+        match session.status() {
+            SessionStatus::Recording | SessionStatus::Stopped => session.roast_file(),
+            _ => return,
+        }
+    };
+    roast.drop_target = state.targeter.lock().unwrap().record();
+
+    let path = {
+        let mut autosave = state.autosave.lock().unwrap();
+        autosave.notes().apply(&mut roast);
+        autosave.path(config, &roast)
+    };
+    let snapshot = state.autosave_order.lock().unwrap().take();
+    let order = state.autosave_order.clone();
+    let outputs = state.outputs.clone();
+    let store = RoastStore::of(state);
+    let dir = config.dir.clone();
+    let app = app.clone();
+
+    spawn_blocking(move || {
+        let mut order = order.lock().unwrap();
+        if !order.save(snapshot) {
+            debug!("autosave snapshot {} is older than the saved one", snapshot);
+            return;
+        }
+        // waits for an output being written, the log is never left out
+        roast.output_log = outputs.blocking_lock().log().clone();
+        let result = fs::create_dir_all(&dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| store_roast(&app, &store, &path, roast));
+        match result {
+            Ok(_) => {
+                info!("autosaved {}", path.display());
+                app.emit_all("autosaved", path.to_string_lossy()).unwrap();
+            }
+            Err(e) => {
+                warn!("autosave failed : {}", e);
+                app.emit_all("log_event", format!("autosave failed : {}", e))
+                    .unwrap();
+            }
+        }
+    });
+}
+
+// a stopped roast is saved at once, also when DROP is not marked
+fn autosave_on_stop(app: &tauri::AppHandle, state: &RoastCraftState) {
+    let on_stop = match &state.config.autosave {
+        Some(config) => config.on_stop.unwrap_or(true),
+        None => return,
+    };
+    let pending = state.autosave.lock().unwrap().is_pending();
+    if on_stop || pending {
+        autosave(app, state);
+    }
+}
+
+// the notes of the roast, as edited in the webview
+#[tauri::command]
+async fn autosave_set_notes(app: tauri::AppHandle, notes: RoastNotes) -> () {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let saved = state.autosave.lock().unwrap().set_notes(notes);
+    if saved {
+        autosave(&app, &state);
+    }
+}
+
+#[tauri::command]
+async fn inventory_list(app: tauri::AppHandle) -> Vec<LotStock> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let lots = state.inventory.lock().unwrap().list();
+    lots
+}
+
+#[tauri::command]
+async fn inventory_add_lot(app: tauri::AppHandle, lot: GreenLot) -> Result<GreenLot, String> {
+    trace!("command called : inventory_add_lot");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let lot = state.inventory.lock().unwrap().add_lot(lot);
+    lot
+}
+
+#[tauri::command]
+async fn inventory_update_lot(app: tauri::AppHandle, lot: GreenLot) -> Result<GreenLot, String> {
+    trace!("command called : inventory_update_lot");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let lot = state.inventory.lock().unwrap().update_lot(lot);
+    lot
+}
+
+#[tauri::command]
+async fn inventory_remove_lot(app: tauri::AppHandle, id: u64) -> Result<(), String> {
+    trace!("command called : inventory_remove_lot");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let result = state.inventory.lock().unwrap().remove_lot(id);
+    result
+}
+
+// returns the stock after the adjustment
+#[tauri::command]
+async fn inventory_adjust(
+    app: tauri::AppHandle,
+    lot_id: u64,
+    delta: f64,
+    reason: String,
+) -> Result<f64, String> {
+    trace!("command called : inventory_adjust");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let stock = state
+        .inventory
+        .lock()
+        .unwrap()
+        .adjust(lot_id, delta, &reason);
+    stock
+}
+
+#[tauri::command]
+async fn inventory_movements(app: tauri::AppHandle, lot_id: u64) -> Vec<StockMovement> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let movements = state.inventory.lock().unwrap().movements(lot_id);
+    movements
+}
+
+#[tauri::command]
+async fn library_list(
+    app: tauri::AppHandle,
+    filter: LibraryFilter,
+) -> Result<Vec<LibraryEntry>, String> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let entries = state.library.lock().unwrap().list(&filter);
+    entries
+}
+
+#[tauri::command]
+async fn library_tag(
+    app: tauri::AppHandle,
+    id: u64,
+    tags: Vec<String>,
+) -> Result<LibraryEntry, String> {
+    trace!("command called : library_tag");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let entry = state.library.lock().unwrap().tag(id, tags);
+    entry
+}
+
+#[tauri::command]
+async fn library_rename(
+    app: tauri::AppHandle,
+    id: u64,
+    title: String,
+) -> Result<LibraryEntry, String> {
+    trace!("command called : library_rename");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let entry = state.library.lock().unwrap().rename(id, &title);
+    entry
+}
+
+#[tauri::command]
+async fn library_delete(app: tauri::AppHandle, id: u64, delete_file: bool) -> Result<(), String> {
+    trace!("command called : library_delete");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let result = state.library.lock().unwrap().delete(id, delete_file);
+    result
+}
+
+#[tauri::command]
+async fn library_import_folder(app: tauri::AppHandle, dir: String) -> Result<ImportReport, String> {
+    trace!("command called : library_import_folder");
+
+    // the files are read without the state locked, a large folder takes a while
+    let library = {
+        let state_mutex = app.state::<Mutex<RoastCraftState>>();
+        let state = state_mutex.lock().unwrap();
+        state.library.clone()
+    };
+    let report = library::import_folder(&library, &PathBuf::from(dir))?;
+    app.emit_all("library_changed", ()).unwrap();
+    Ok(report)
+}
+
+// utc_offset in minutes, for the date of the roast
+#[tauri::command]
+async fn export_alog(
+    app: tauri::AppHandle,
+    path: String,
+    roast: RoastFile,
+    utc_offset: i64,
+) -> Result<(), String> {
+    trace!("command called : export_alog");
+
+    let temperature_unit = {
+        let state_mutex = app.state::<Mutex<RoastCraftState>>();
+        let state = state_mutex.lock().unwrap();
+        state.config.temperature_unit.clone()
+    };
+    artisan::save(&PathBuf::from(path), &roast, &temperature_unit, utc_offset)
+}
+
+// utc_offset in minutes, for the wall clock column
+#[tauri::command]
+async fn export_csv(
+    app: tauri::AppHandle,
+    path: String,
+    roast: RoastFile,
+    utc_offset: i64,
+) -> Result<(), String> {
+    trace!("command called : export_csv");
+
+    // the RoR of the chart, with the configured settings
+    let settings = {
+        let state_mutex = app.state::<Mutex<RoastCraftState>>();
+        let state = state_mutex.lock().unwrap();
+        let settings = state.analytics.lock().unwrap().settings().clone();
+        settings
+    };
+    csv::save(&PathBuf::from(path), &roast, utc_offset, &settings)
+}
+
+// journals of earlier sessions, left by a crash or an unsaved roast
+#[tauri::command]
+async fn get_unfinished_journals(app: tauri::AppHandle) -> Vec<UnfinishedJournal> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let session = state.session.lock().unwrap();
+
+    let active = session.active_journal();
+    match session.journal_dir() {
+        Some(dir) => journal::find_unfinished(&dir)
+            .into_iter()
+            .filter(|j| Some(PathBuf::from(&j.path)) != active)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// only unfinished journals may be touched, not any file the webview names
+fn unfinished_journal(app: &tauri::AppHandle, journal: &str) -> Result<PathBuf, String> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let session = state.session.lock().unwrap();
+
+    let path = PathBuf::from(journal);
+    let dir = session.journal_dir().ok_or("no journal directory")?;
+    if path.parent() != Some(dir.as_path()) || Some(&path) == session.active_journal().as_ref() {
+        return Err(format!("{} is not an unfinished journal", journal));
+    }
+    Ok(path)
+}
+
+#[tauri::command]
+async fn recover_journal(
+    app: tauri::AppHandle,
+    journal: String,
+    path: String,
+) -> Result<(), String> {
+    trace!("command called : recover_journal");
+
+    let journal = unfinished_journal(&app, &journal)?;
+    journal::recover(&journal, &PathBuf::from(path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn discard_journal(app: tauri::AppHandle, journal: String) -> Result<(), String> {
+    trace!("command called : discard_journal");
+
+    let journal = unfinished_journal(&app, &journal)?;
+    journal::discard(&journal).map_err(|e| e.to_string())
+}
+
+// the webview calls this every second, see safety heartbeat_timeout
+#[tauri::command]
+async fn heartbeat(app: tauri::AppHandle) -> () {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    state.interlock.lock().unwrap().heartbeat();
+}
+
+#[tauri::command]
+async fn safety_acknowledge(app: tauri::AppHandle) -> Result<(), String> {
+    trace!("command called : safety_acknowledge");
+
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    state.interlock.lock().unwrap().acknowledge()?;
+    app.emit_all("log_event", "safety alarm acknowledged")
+        .unwrap();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_safety_alarm(app: tauri::AppHandle) -> Option<SafetyAlarm> {
+    let state_mutex = app.state::<Mutex<RoastCraftState>>();
+    let state = state_mutex.lock().unwrap();
+    let alarm = state.interlock.lock().unwrap().alarm();
+    alarm
+}
+
 #[tauri::command]
 async fn get_config(app: tauri::AppHandle) -> Config {
     let state_mutex = app.state::<Mutex<RoastCraftState>>();
@@ -114,17 +1519,73 @@ async fn get_config(app: tauri::AppHandle) -> Config {
     state.config.clone()
 }
 
+#[tauri::command]
+async fn modbus_scan(app: tauri::AppHandle, request: ScanRequest) -> Result<ScanReport, String> {
+    trace!("command called : modbus_scan");
+
+    devices::scanner::scan(&request, &app)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// pnpm tauri dev -- -- scan --port=COM4 --protocol=modbus-rtu --slaves=1-3 --registers=18176-18177
+fn run_scan_cli(matches: &tauri::api::cli::Matches, app: tauri::AppHandle) {
+    let request = match devices::scanner::request_from_cli(matches) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    match tauri::async_runtime::block_on(devices::scanner::scan(&request, &app)) {
+        Ok(report) => {
+            println!("responding slaves : {:?}", report.responding_slaves);
+            for r in report.results {
+                match (r.raw, r.decoded, r.error) {
+                    (Some(raw), Some(d), _) => println!(
+                        "slave {} registry {} ({:#06X}) : raw {} i16 {} u32 {:?} i32 {:?} f32 {:?}",
+                        r.slave_id, r.registry, r.registry, raw, d.i16, d.u32, d.i32, d.f32
+                    ),
+                    (Some(raw), None, _) => println!(
+                        "slave {} registry {} ({:#06X}) : {}",
+                        r.slave_id, r.registry, r.registry, raw
+                    ),
+                    (None, _, Some(e)) => println!(
+                        "slave {} registry {} ({:#06X}) : {}",
+                        r.slave_id, r.registry, r.registry, e
+                    ),
+                    _ => (),
+                }
+            }
+        }
+        Err(e) => println!("scan failed : {}", e),
+    }
+}
+
 fn main() {
     const OPEN_FILE: &str = "OPEN_FILE";
     const SAVE_FILE: &str = "SAVE_FILE";
+    const EXPORT_ALOG: &str = "EXPORT_ALOG";
+    const EXPORT_CSV: &str = "EXPORT_CSV";
     const LOAD_GHOST: &str = "LOAD_GHOST";
     const RESET_GHOST: &str = "RESET_GHOST";
+    const AUTOPILOT_START: &str = "AUTOPILOT_START";
+    const AUTOPILOT_PAUSE: &str = "AUTOPILOT_PAUSE";
+    const AUTOPILOT_RESUME: &str = "AUTOPILOT_RESUME";
+    const AUTOPILOT_HAND_OVER: &str = "AUTOPILOT_HAND_OVER";
+    const SAFETY_ACKNOWLEDGE: &str = "SAFETY_ACKNOWLEDGE";
 
     let submenu = Submenu::new(
         "File",
         Menu::new()
             .add_item(CustomMenuItem::new(OPEN_FILE.to_string(), "Open"))
             .add_item(CustomMenuItem::new(SAVE_FILE.to_string(), "Save"))
+            .add_item(CustomMenuItem::new(
+                EXPORT_ALOG.to_string(),
+                "Export Artisan",
+            ))
+            .add_item(CustomMenuItem::new(EXPORT_CSV.to_string(), "Export CSV"))
             .add_native_item(MenuItem::Quit),
     );
 
@@ -135,7 +1596,34 @@ fn main() {
             .add_item(CustomMenuItem::new(RESET_GHOST.to_string(), "Reset")),
     );
 
-    let menu = Menu::new().add_submenu(submenu).add_submenu(ghost_submenu);
+    let autopilot_submenu = Submenu::new(
+        "Autopilot",
+        Menu::new()
+            .add_item(CustomMenuItem::new(
+                AUTOPILOT_START.to_string(),
+                "Follow Ghost",
+            ))
+            .add_item(CustomMenuItem::new(AUTOPILOT_PAUSE.to_string(), "Pause"))
+            .add_item(CustomMenuItem::new(AUTOPILOT_RESUME.to_string(), "Resume"))
+            .add_item(CustomMenuItem::new(
+                AUTOPILOT_HAND_OVER.to_string(),
+                "Manual",
+            )),
+    );
+
+    let safety_submenu = Submenu::new(
+        "Safety",
+        Menu::new().add_item(CustomMenuItem::new(
+            SAFETY_ACKNOWLEDGE.to_string(),
+            "Acknowledge Alarm",
+        )),
+    );
+
+    let menu = Menu::new()
+        .add_submenu(submenu)
+        .add_submenu(ghost_submenu)
+        .add_submenu(autopilot_submenu)
+        .add_submenu(safety_submenu);
 
     tauri::Builder::default()
         .menu(menu)
@@ -146,18 +1634,97 @@ fn main() {
             SAVE_FILE => {
                 event.window().emit("menu_event", SAVE_FILE).unwrap();
             }
+            EXPORT_ALOG => {
+                event.window().emit("menu_event", EXPORT_ALOG).unwrap();
+            }
+            EXPORT_CSV => {
+                event.window().emit("menu_event", EXPORT_CSV).unwrap();
+            }
             LOAD_GHOST => {
                 event.window().emit("menu_event", LOAD_GHOST).unwrap();
             }
             RESET_GHOST => {
                 event.window().emit("menu_event", RESET_GHOST).unwrap();
             }
+            AUTOPILOT_START => {
+                event.window().emit("menu_event", AUTOPILOT_START).unwrap();
+            }
+            AUTOPILOT_PAUSE => {
+                event.window().emit("menu_event", AUTOPILOT_PAUSE).unwrap();
+            }
+            AUTOPILOT_RESUME => {
+                event.window().emit("menu_event", AUTOPILOT_RESUME).unwrap();
+            }
+            AUTOPILOT_HAND_OVER => {
+                event
+                    .window()
+                    .emit("menu_event", AUTOPILOT_HAND_OVER)
+                    .unwrap();
+            }
+            SAFETY_ACKNOWLEDGE => {
+                event
+                    .window()
+                    .emit("menu_event", SAFETY_ACKNOWLEDGE)
+                    .unwrap();
+            }
             _ => {}
         })
         .invoke_handler(tauri::generate_handler![
             button_on_clicked,
             button_off_clicked,
             get_config,
+            modbus_scan,
+            set_output,
+            get_output_log,
+            pid_enable,
+            pid_disable,
+            pid_set_point,
+            pid_tune,
+            get_pid_status,
+            autopilot_start,
+            autopilot_pause,
+            autopilot_resume,
+            autopilot_hand_over,
+            get_autopilot_status,
+            heartbeat,
+            safety_acknowledge,
+            get_safety_alarm,
+            session_start,
+            session_stop,
+            session_reset,
+            session_mark_event,
+            get_session,
+            compute_ror,
+            get_fc_prediction,
+            set_drop_target,
+            get_drop_target,
+            get_drop_countdown,
+            get_phase_report,
+            compute_phases,
+            get_alarms,
+            get_unfinished_journals,
+            recover_journal,
+            discard_journal,
+            load_roast_file,
+            save_roast_file,
+            export_alog,
+            export_csv,
+            library_list,
+            library_tag,
+            library_rename,
+            library_delete,
+            library_import_folder,
+            inventory_list,
+            inventory_add_lot,
+            inventory_update_lot,
+            inventory_remove_lot,
+            inventory_adjust,
+            inventory_movements,
+            batch_queue_list,
+            batch_queue_add,
+            batch_queue_remove,
+            batch_queue_move,
+            autosave_set_notes,
         ])
         .plugin(
             tauri_plugin_log::Builder::default()
@@ -189,6 +1756,13 @@ fn main() {
                             .to_string();
                     }
                     println!("{}", config_file_name);
+
+                    if let Some(sub) = &matches.subcommand {
+                        if sub.name == "scan" {
+                            run_scan_cli(&sub.matches, app.handle());
+                            std::process::exit(0);
+                        }
+                    }
                 }
                 Err(_) => {}
             }
@@ -200,21 +1774,45 @@ fn main() {
             let state_mutex = app.state::<Mutex<RoastCraftState>>();
             let mut state = state_mutex.lock().unwrap();
 
+            if let Some(dir) = app.path_resolver().app_data_dir() {
+                state
+                    .session
+                    .lock()
+                    .unwrap()
+                    .set_journal_dir(dir.join("journal"));
+                state.library.lock().unwrap().open(&dir);
+                state.inventory.lock().unwrap().open(&dir);
+                state.batches.lock().unwrap().open(&dir);
+            }
+
             match File::open(&config_file_name) {
                 Ok(mut file) => {
                     match file.read_to_string(&mut toml_content) {
                         Ok(_) => {
                             // At this point, `contents` contains the content of the TOML file
-                            match toml::from_str::<Config>(toml_content.as_str()) {
+                            let config = toml::from_str::<Config>(toml_content.as_str())
+                                .map_err(|e| {
+                                    format!("Failed to parse {config_file_name} \n{}", e.message())
+                                })
+                                .and_then(|c| {
+                                    c.validate()
+                                        .map(|_| c)
+                                        .map_err(|e| format!("Invalid {config_file_name} \n{}", e))
+                                });
+                            match config {
                                 Ok(c) => {
                                     parse_config_ok = true;
+                                    state.pid.lock().unwrap().configure(&c);
+                                    state.interlock.lock().unwrap().configure(&c);
+                                    state.alarms.lock().unwrap().configure(&c);
+                                    state.analytics.lock().unwrap().configure(&c);
+                                    state.detector.lock().unwrap().configure(&c);
+                                    state.predictor.lock().unwrap().configure(&c);
+                                    state.targeter.lock().unwrap().configure(&c);
                                     state.config = c;
                                 }
                                 Err(e) => {
-                                    parse_config_err_msg = format!(
-                                        "Failed to parse {config_file_name} \n{}",
-                                        e.message()
-                                    );
+                                    parse_config_err_msg = e;
                                 }
                             }
                         }
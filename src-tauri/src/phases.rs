@@ -0,0 +1,361 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::analytics::{self as ror_analytics, RorSettings};
+use crate::config::Config;
+use crate::roastfile::RoastFile;
+use crate::session::{Point, RoastEvent};
+
+// one phase of the roast, or the whole of it. time, percent and temp_rise
+// as calculatePhases() in the webview
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct PhaseStats {
+    pub start: f64, // seconds from START
+    pub time: f64,  // seconds
+    pub percent: f64,
+    pub temp_rise: f64,
+    pub ror_mean: Option<f64>, // BT RoR, from TP on like the temperature rise
+    pub ror_max: Option<f64>,
+    pub auc: f64,                      // area of BT above auc_base, degree minutes
+    pub manual: BTreeMap<String, f64>, // manual channel_id, average over the time, e.g. gas
+}
+
+// drying, maillard and development, from CHARGE to DROP or the latest sample
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct PhaseReport {
+    pub dry: PhaseStats, // CHARGE to DRY_END or FC_START, the whole roast before they are marked
+    pub mai: Option<PhaseStats>,
+    pub dev: Option<PhaseStats>,
+    pub total: PhaseStats,
+    pub auc_base: f64,
+}
+
+pub fn auc_base(config: &Config) -> f64 {
+    let default = if config.temperature_unit == "F" {
+        212.0
+    } else {
+        100.0
+    };
+    config
+        .phases
+        .as_ref()
+        .and_then(|p| p.auc_base)
+        .unwrap_or(default)
+}
+
+// the report of a roast. ror is the BT RoR, None without CHARGE or samples after it
+pub fn report(
+    bt: &[Point],
+    ror: &[Point],
+    events: &BTreeMap<String, RoastEvent>,
+    manual_channels: &BTreeMap<String, Vec<Point>>,
+    auc_base: f64,
+) -> Option<PhaseReport> {
+    let charge = events.get("CHARGE")?;
+    let (end, end_value) = match events.get("DROP") {
+        Some(drop) => (drop.timestamp, drop.value),
+        None => bt.last().map(|p| (p.timestamp, p.value))?,
+    };
+    let total_time = end - charge.timestamp;
+    if total_time <= 0.0 {
+        return None;
+    }
+
+    // the RoR falls steeply from CHARGE to TP, which says nothing of the phase
+    let tp = events.get("TP");
+    let ror_start = tp.map_or(f64::INFINITY, |tp| tp.timestamp);
+    let stats = |start: f64, stop: f64, temp_rise: f64| {
+        let ror: Vec<f64> = ror
+            .iter()
+            .filter(|p| p.timestamp >= start.max(ror_start) && p.timestamp <= stop)
+            .map(|p| p.value)
+            .collect();
+        PhaseStats {
+            start,
+            time: stop - start,
+            percent: (stop - start) / total_time * 100.0,
+            temp_rise,
+            ror_mean: if ror.is_empty() {
+                None
+            } else {
+                Some(ror.iter().sum::<f64>() / ror.len() as f64)
+            },
+            ror_max: ror.iter().copied().reduce(f64::max),
+            auc: auc(bt, start, stop, auc_base),
+            manual: manual_channels
+                .iter()
+                .filter_map(|(id, points)| Some((id.clone(), step_average(points, start, stop)?)))
+                .collect(),
+        }
+    };
+
+    //   charge  tp  de  fc  phases
+    //   o       x   x   x   drying, without a temperature rise
+    //   o       o   x   x   drying
+    //   o       o   o   x   drying + maillard
+    //   o       o   o   o   drying + maillard + develop
+    //   o       o   x   o   drying + develop
+    let de = events.get("DRY_END");
+    let fc = events.get("FC_START");
+    let (dry, mai, dev, total_rise) = match tp {
+        None => (stats(charge.timestamp, end, 0.0), None, None, 0.0),
+        Some(tp) => {
+            let dry = match de.or(fc) {
+                Some(e) => stats(charge.timestamp, e.timestamp, e.value - tp.value),
+                None => stats(charge.timestamp, end, end_value - tp.value),
+            };
+            let mai = de.map(|de| match fc {
+                Some(fc) => stats(de.timestamp, fc.timestamp, fc.value - de.value),
+                None => stats(de.timestamp, end, end_value - de.value),
+            });
+            let dev = fc.map(|fc| stats(fc.timestamp, end, end_value - fc.value));
+            (dry, mai, dev, end_value - tp.value)
+        }
+    };
+
+    Some(PhaseReport {
+        dry,
+        mai,
+        dev,
+        total: stats(charge.timestamp, end, total_rise),
+        auc_base,
+    })
+}
+
+// the report of a saved roast, its BT RoR computed with the settings
+pub fn of_roast(roast: &RoastFile, settings: &RorSettings, auc_base: f64) -> Option<PhaseReport> {
+    let bt = &roast.channel("BT")?.data_arr;
+    let drop = roast.roast_events.get("DROP").map(|e| e.timestamp);
+    let ror = ror_analytics::compute("BT", bt, drop, settings).smoothed;
+    let manual_channels = roast
+        .manual_channel_arr
+        .iter()
+        .map(|c| (c.id.clone(), c.data_arr.clone()))
+        .collect();
+    report(bt, &ror, &roast.roast_events, &manual_channels, auc_base)
+}
+
+// BT above base between start and stop, linear between the readings
+fn auc(bt: &[Point], start: f64, stop: f64, base: f64) -> f64 {
+    let at = |a: &Point, b: &Point, t: f64| {
+        a.value + (b.value - a.value) * (t - a.timestamp) / (b.timestamp - a.timestamp)
+    };
+
+    let mut area = 0.0;
+    for w in bt.windows(2) {
+        let (a, b) = (&w[0], &w[1]);
+        let from = a.timestamp.max(start);
+        let to = b.timestamp.min(stop);
+        if to <= from {
+            continue;
+        }
+        let v0 = at(a, b, from) - base;
+        let v1 = at(a, b, to) - base;
+        area += if v0 >= 0.0 && v1 >= 0.0 {
+            (v0 + v1) / 2.0 * (to - from)
+        } else if v0 > 0.0 || v1 > 0.0 {
+            // crosses the base, only the part above counts
+            let above = v0.max(v1);
+            above / 2.0 * (to - from) * above / (v0 - v1).abs()
+        } else {
+            0.0
+        };
+    }
+    area / 60.0
+}
+
+// a manual channel holds its value until the next change
+fn step_average(points: &[Point], start: f64, stop: f64) -> Option<f64> {
+    let mut sum = 0.0;
+    let mut covered = 0.0;
+    for (i, p) in points.iter().enumerate() {
+        let from = p.timestamp.max(start);
+        let to = points.get(i + 1).map_or(stop, |n| n.timestamp.min(stop));
+        if to > from {
+            sum += p.value * (to - from);
+            covered += to - from;
+        }
+    }
+    if covered > 0.0 {
+        Some(sum / covered)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(values: &[(f64, f64)]) -> Vec<Point> {
+        values
+            .iter()
+            .map(|(timestamp, value)| Point {
+                timestamp: *timestamp,
+                value: *value,
+            })
+            .collect()
+    }
+
+    fn events(events: &[(&str, f64, f64)]) -> BTreeMap<String, RoastEvent> {
+        events
+            .iter()
+            .map(|(id, timestamp, value)| {
+                (
+                    id.to_string(),
+                    RoastEvent {
+                        id: id.to_string(),
+                        timestamp: *timestamp,
+                        value: *value,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    // a reading a minute, TP at 1:00, DRY_END at 3:00, FC_START at 5:00 and DROP at 6:00
+    fn bt() -> Vec<Point> {
+        points(&[
+            (0.0, 200.0),
+            (60.0, 90.0),
+            (120.0, 110.0),
+            (180.0, 140.0),
+            (240.0, 170.0),
+            (300.0, 190.0),
+            (360.0, 200.0),
+        ])
+    }
+
+    fn ror() -> Vec<Point> {
+        points(&[
+            (0.0, -100.0),
+            (60.0, 20.0),
+            (120.0, 25.0),
+            (180.0, 30.0),
+            (240.0, 30.0),
+            (300.0, 20.0),
+            (360.0, 10.0),
+        ])
+    }
+
+    fn gas() -> BTreeMap<String, Vec<Point>> {
+        BTreeMap::from([(
+            String::from("gas"),
+            points(&[(0.0, 50.0), (120.0, 80.0), (330.0, 40.0)]),
+        )])
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn roast_is_split_at_its_events() {
+        let events = events(&[
+            ("CHARGE", 0.0, 200.0),
+            ("TP", 60.0, 90.0),
+            ("DRY_END", 180.0, 140.0),
+            ("FC_START", 300.0, 190.0),
+            ("DROP", 360.0, 200.0),
+        ]);
+        let report = report(&bt(), &ror(), &events, &gas(), 100.0).unwrap();
+        let mai = report.mai.unwrap();
+        let dev = report.dev.unwrap();
+
+        let phases = [&report.dry, &mai, &dev, &report.total];
+        let split: Vec<(f64, f64, f64)> = phases
+            .iter()
+            .map(|p| (p.start, p.time, p.temp_rise))
+            .collect();
+        assert_eq!(
+            split,
+            vec![
+                (0.0, 180.0, 50.0),
+                (180.0, 120.0, 50.0),
+                (300.0, 60.0, 10.0),
+                (0.0, 360.0, 110.0)
+            ]
+        );
+        assert!(close(report.dry.percent, 50.0));
+        assert!(close(mai.percent + dev.percent, 50.0));
+
+        // the RoR from TP on, the -100 at CHARGE left out
+        assert!(close(report.dry.ror_mean.unwrap(), 25.0));
+        assert_eq!(report.dry.ror_max, Some(30.0));
+        assert!(close(mai.ror_mean.unwrap(), 80.0 / 3.0));
+        assert!(close(dev.ror_mean.unwrap(), 15.0));
+        assert_eq!(report.total.ror_max, Some(30.0));
+
+        // BT falls through the base before TP, in the first minute only the
+        // triangle above it counts, 100 down to 0 over 100 / 110 of the minute
+        let first = 100.0 / 2.0 * 60.0 * 100.0 / 110.0;
+        let second = 10.0 / 2.0 * 60.0 * 10.0 / 20.0;
+        assert!(close(report.dry.auc, (first + second + 25.0 * 60.0) / 60.0));
+        assert!(close(mai.auc, 55.0 + 80.0));
+        assert!(close(dev.auc, 95.0));
+        assert!(close(report.total.auc, report.dry.auc + mai.auc + dev.auc));
+
+        assert!(close(report.dry.manual["gas"], 60.0));
+        assert!(close(mai.manual["gas"], 80.0));
+        assert!(close(dev.manual["gas"], 60.0));
+        assert!(close(
+            report.total.manual["gas"],
+            (6000.0 + 16800.0 + 1200.0) / 360.0
+        ));
+    }
+
+    #[test]
+    fn phases_follow_the_marked_events() {
+        // without DRY_END drying runs to FC_START
+        let events = events(&[
+            ("CHARGE", 0.0, 200.0),
+            ("TP", 60.0, 90.0),
+            ("FC_START", 300.0, 190.0),
+        ]);
+        let phases = report(&bt(), &ror(), &events, &gas(), 100.0).unwrap();
+        assert!(phases.mai.is_none());
+        assert_eq!((phases.dry.time, phases.dry.temp_rise), (300.0, 100.0));
+        let dev = phases.dev.unwrap();
+        // to the latest sample without DROP
+        assert_eq!((dev.start, dev.time, dev.temp_rise), (300.0, 60.0, 10.0));
+
+        // without TP there is no rise and no RoR to go by
+        let charge: BTreeMap<String, RoastEvent> = events
+            .into_iter()
+            .filter(|(id, _)| id == "CHARGE")
+            .collect();
+        let phases = report(&bt(), &ror(), &charge, &gas(), 100.0).unwrap();
+        assert_eq!((phases.dry.time, phases.dry.temp_rise), (360.0, 0.0));
+        assert_eq!(phases.dry.ror_mean, None);
+        assert!(phases.mai.is_none() && phases.dev.is_none());
+
+        assert!(report(&bt(), &ror(), &BTreeMap::new(), &gas(), 100.0).is_none());
+    }
+
+    #[test]
+    fn auc_and_averages_between_readings() {
+        // BT 100 at 1:30, 125 at 2:30
+        assert!(close(
+            auc(&bt(), 90.0, 150.0, 100.0),
+            (150.0 + 525.0) / 60.0
+        ));
+        assert!(close(auc(&bt(), 60.0, 120.0, 200.0), 0.0));
+        assert!(close(auc(&bt(), 0.0, 360.0, 0.0), {
+            let bt = bt();
+            bt.windows(2)
+                .map(|w| (w[0].value + w[1].value) / 2.0)
+                .sum::<f64>()
+        }));
+
+        let gas = &gas()["gas"];
+        assert_eq!(step_average(gas, 100.0, 140.0), Some(65.0));
+        assert_eq!(step_average(gas, 330.0, 400.0), Some(40.0));
+        // nothing set before the first change
+        let late = points(&[(60.0, 30.0)]);
+        assert_eq!(step_average(&late, 0.0, 120.0), Some(30.0));
+        assert_eq!(step_average(&late, 0.0, 60.0), None);
+        assert_eq!(step_average(&[], 0.0, 60.0), None);
+    }
+}
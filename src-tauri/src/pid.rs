@@ -0,0 +1,303 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::config::{Config, Pid};
+
+// textbook PID with derivative on measurement, so set-point changes
+// don't kick the output, and integral clamping against windup
+pub struct PidController {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub out_min: f64,
+    pub out_max: f64,
+    pub set_point: f64,
+    integral: f64,
+    last_input: Option<f64>,
+}
+
+impl PidController {
+    pub fn new(kp: f64, ki: f64, kd: f64, out_min: f64, out_max: f64) -> Self {
+        Self {
+            kp,
+            ki,
+            kd,
+            out_min,
+            out_max,
+            set_point: 0.0,
+            integral: 0.0,
+            last_input: None,
+        }
+    }
+
+    // start from the current output, so enabling the loop doesn't jump
+    pub fn reset(&mut self, output: f64) {
+        self.integral = output.clamp(self.out_min, self.out_max);
+        self.last_input = None;
+    }
+
+    // dt in seconds since the previous sample
+    pub fn update(&mut self, input: f64, dt: f64) -> f64 {
+        let error = self.set_point - input;
+
+        self.integral += self.ki * error * dt;
+        self.integral = self.integral.clamp(self.out_min, self.out_max);
+
+        let d_input = match self.last_input {
+            Some(last) if dt > 0.0 => (input - last) / dt,
+            _ => 0.0,
+        };
+        self.last_input = Some(input);
+
+        (self.kp * error + self.integral - self.kd * d_input).clamp(self.out_min, self.out_max)
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct PidStatus {
+    pub enabled: bool,
+    pub input: String,
+    pub mode: String,
+    pub output: String,
+    pub set_point: f64,
+    pub process_value: Option<f64>,
+    pub last_output: Option<f64>,
+}
+
+// runs the controller on every sample of the reader loop
+pub struct PidLoop {
+    config: Option<Pid>,
+    controller: PidController,
+    enabled: bool,
+    last_sample: Option<Instant>,
+    process_value: Option<f64>,
+    last_output: Option<f64>,
+}
+
+impl PidLoop {
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            controller: PidController::new(0.0, 0.0, 0.0, 0.0, 0.0),
+            enabled: false,
+            last_sample: None,
+            process_value: None,
+            last_output: None,
+        }
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        self.config = config.pid.clone();
+
+        if let Some(pid) = &self.config {
+            let channel = config
+                .manual_channel
+                .iter()
+                .flatten()
+                .find(|mc| mc.channel_id == pid.output);
+
+            let (min, max) = match channel {
+                Some(mc) => (mc.min as f64, mc.max as f64),
+                None => {
+                    warn!("pid output {} is not a manual channel", pid.output);
+                    (0.0, 100.0)
+                }
+            };
+
+            self.controller = PidController::new(
+                pid.kp,
+                pid.ki,
+                pid.kd,
+                pid.output_min.unwrap_or(min),
+                pid.output_max.unwrap_or(max),
+            );
+            self.controller.set_point = pid.set_point.unwrap_or(0.0);
+        }
+    }
+
+    pub fn output_channel(&self) -> Option<String> {
+        self.config.as_ref().map(|pid| pid.output.clone())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn enable(&mut self, current_output: f64) -> Result<(), String> {
+        if self.config.is_none() {
+            return Err(String::from("no [pid] in config"));
+        }
+        self.controller.reset(current_output);
+        self.last_sample = None;
+        self.enabled = true;
+        info!("pid enabled, set point {}", self.controller.set_point);
+        Ok(())
+    }
+
+    pub fn disable(&mut self) {
+        if self.enabled {
+            info!("pid disabled");
+        }
+        self.enabled = false;
+    }
+
+    pub fn set_point(&mut self, set_point: f64) {
+        self.controller.set_point = set_point;
+    }
+
+    pub fn tune(&mut self, kp: f64, ki: f64, kd: f64) {
+        self.controller.kp = kp;
+        self.controller.ki = ki;
+        self.controller.kd = kd;
+    }
+
+    pub fn ror_mode(&self) -> bool {
+        self.config
+            .as_ref()
+            .and_then(|pid| pid.mode.as_ref())
+            .map(|m| m == "ror")
+            .unwrap_or(false)
+    }
+
+    // feed a read_channels payload and the current RoR of the analytics,
+    // returns the new output while enabled. the RoR is there while recording
+    pub fn sample(&mut self, channels: &Value, ror: &BTreeMap<String, f64>) -> Option<f64> {
+        let input = self.config.as_ref()?.input.clone();
+        let value = channels.get(&input)?.as_f64()?;
+        let now = Instant::now();
+
+        self.process_value = if self.ror_mode() {
+            ror.get(&input).copied()
+        } else {
+            Some(value)
+        };
+
+        let dt = self
+            .last_sample
+            .map(|t| now.duration_since(t).as_secs_f64())
+            .unwrap_or(0.0);
+        self.last_sample = Some(now);
+
+        if !self.enabled {
+            return None;
+        }
+
+        let output = self.controller.update(self.process_value?, dt);
+        self.last_output = Some(output);
+        Some(output)
+    }
+
+    pub fn status(&self) -> PidStatus {
+        PidStatus {
+            enabled: self.enabled,
+            input: self
+                .config
+                .as_ref()
+                .map(|p| p.input.clone())
+                .unwrap_or_default(),
+            mode: if self.ror_mode() {
+                String::from("ror")
+            } else {
+                String::from("temperature")
+            },
+            output: self.output_channel().unwrap_or_default(),
+            set_point: self.controller.set_point,
+            process_value: self.process_value,
+            last_output: self.last_output,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // first-order plant, heat input 0..100 % against losses to the ambient
+    struct Plant {
+        temperature: f64,
+        ambient: f64,
+        gain: f64, // degrees at 100 % once settled
+        tau: f64,  // seconds
+    }
+
+    impl Plant {
+        fn step(&mut self, output: f64, dt: f64) {
+            let settled = self.ambient + self.gain * output / 100.0;
+            self.temperature += (settled - self.temperature) * dt / self.tau;
+        }
+    }
+
+    fn plant() -> Plant {
+        Plant {
+            temperature: 20.0,
+            ambient: 20.0,
+            gain: 300.0,
+            tau: 60.0,
+        }
+    }
+
+    #[test]
+    fn settles_on_set_point() {
+        let mut plant = plant();
+        let mut pid = PidController::new(2.0, 0.05, 5.0, 0.0, 100.0);
+        pid.set_point = 200.0;
+        pid.reset(0.0);
+
+        for _ in 0..1200 {
+            let output = pid.update(plant.temperature, 1.0);
+            plant.step(output, 1.0);
+        }
+        assert!(
+            (plant.temperature - 200.0).abs() < 0.5,
+            "{}",
+            plant.temperature
+        );
+
+        // and holds it with the output needed against the losses
+        let output = pid.update(plant.temperature, 1.0);
+        assert!((output - 60.0).abs() < 1.0, "{}", output);
+    }
+
+    #[test]
+    fn integral_is_limited_at_saturation() {
+        let mut plant = plant();
+        let mut pid = PidController::new(2.0, 0.05, 0.0, 0.0, 100.0);
+        pid.set_point = 400.0; // out of reach, the output stays at its maximum
+        pid.reset(0.0);
+
+        for _ in 0..1200 {
+            let output = pid.update(plant.temperature, 1.0);
+            assert!(output <= 100.0);
+            plant.step(output, 1.0);
+        }
+        assert_eq!(pid.integral, 100.0);
+
+        // without windup the output comes off the limit as soon as the error turns
+        pid.set_point = plant.temperature - 20.0;
+        let output = pid.update(plant.temperature, 1.0);
+        assert!(output < 100.0, "{}", output);
+    }
+
+    #[test]
+    fn set_point_step_does_not_kick() {
+        let mut pid = PidController::new(0.0, 0.0, 10.0, -100.0, 100.0);
+        pid.set_point = 150.0;
+        pid.reset(0.0);
+
+        let before = pid.update(150.0, 1.0);
+        let steady = pid.update(150.0, 1.0);
+        pid.set_point = 200.0;
+        let after = pid.update(150.0, 1.0);
+        assert_eq!(before, steady);
+        assert_eq!(steady, after);
+
+        // the derivative only acts on the measurement moving
+        let moving = pid.update(152.0, 1.0);
+        assert_eq!(moving, -20.0);
+    }
+}
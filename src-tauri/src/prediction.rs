@@ -0,0 +1,291 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::analytics::linear_fit;
+use crate::config::Config;
+use crate::session::{Point, RoastEvent};
+
+// further away is no prediction, the trend will not hold that long
+const MAX_AHEAD: f64 = 1200.0;
+
+// BT closes in on the air temperature, so its RoR falls off exponentially,
+// ror(s) = ror_now * e^(-k s) with k = -ror_change / ror_now, and
+// BT(s) = BT + ror_now (1 - e^(-k s)) / 60k. a RoR not falling is held, it
+// does not keep rising after TP. ror_change is per second
+
+// the RoR fitted to the window before now, only points after a roast event.
+// its value now and its change per second
+pub fn ror_trend(ror: &[Point], after: f64, now: f64, window: f64) -> Option<(f64, f64)> {
+    let start = ror
+        .iter()
+        .position(|p| p.timestamp > after && p.timestamp >= now - window)?;
+    let recent = &ror[start..];
+    if recent.len() < 3 {
+        return None;
+    }
+    Some(linear_fit(recent, window))
+}
+
+// seconds for BT to rise by rise, None when the trend does not get there in time
+pub fn time_to_rise(rise: f64, ror_now: f64, ror_change: f64) -> Option<f64> {
+    let ahead = if rise <= 0.0 {
+        0.0
+    } else if ror_now <= 0.0 {
+        return None;
+    } else if ror_change >= 0.0 {
+        rise * 60.0 / ror_now
+    } else {
+        let k = -ror_change / ror_now;
+        let reached = rise * 60.0 * k / ror_now;
+        // the RoR dies out before BT gets there
+        if reached >= 1.0 {
+            return None;
+        }
+        -(1.0 - reached).ln() / k
+    };
+    if ahead > MAX_AHEAD {
+        return None;
+    }
+    Some(ahead)
+}
+
+// BT rise in the seconds ahead
+pub fn rise_in(ahead: f64, ror_now: f64, ror_change: f64) -> f64 {
+    if ror_now <= 0.0 || ror_change >= 0.0 {
+        return ror_now * ahead / 60.0;
+    }
+    let k = -ror_change / ror_now;
+    ror_now * (1.0 - (-k * ahead).exp()) / (60.0 * k)
+}
+
+// when BT is expected to reach the first crack temperature
+#[derive(Serialize, Clone)]
+pub struct FcPrediction {
+    pub timestamp: f64,   // seconds from START
+    pub temperature: f64, // BT
+    pub source: String,   // config, lot or default, where the temperature comes from
+    pub ror: f64,         // BT RoR of the fitted trend now
+}
+
+// predicts first crack from the BT RoR trend, between TP and FC_START
+pub struct FcPredictor {
+    temperature: Option<f64>, // configured
+    default_temperature: f64,
+    lot_roasts: usize,
+    window: f64,
+    last: Option<FcPrediction>,
+}
+
+impl FcPredictor {
+    pub fn new() -> Self {
+        Self {
+            temperature: None,
+            default_temperature: 196.0,
+            lot_roasts: 5,
+            window: 90.0,
+            last: None,
+        }
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        let prediction = config.prediction.as_ref();
+        self.temperature = prediction.and_then(|p| p.fc_temperature);
+        self.default_temperature = if config.temperature_unit == "F" {
+            385.0
+        } else {
+            196.0
+        };
+        self.lot_roasts = prediction.and_then(|p| p.lot_roasts).unwrap_or(5).max(1);
+        self.window = prediction.and_then(|p| p.window).unwrap_or(90.0).max(10.0);
+    }
+
+    // roasts of the lot whose first crack is averaged
+    pub fn lot_roasts(&self) -> usize {
+        self.lot_roasts
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    pub fn last(&self) -> Option<FcPrediction> {
+        self.last.clone()
+    }
+
+    // after each sample, the prediction to emit. None when there was none and
+    // still is none, Some(None) when it ended, e.g. FC_START was marked
+    pub fn update(
+        &mut self,
+        bt: &[Point],
+        ror: &[Point],
+        events: &BTreeMap<String, RoastEvent>,
+        lot_temperature: Option<f64>,
+    ) -> Option<Option<FcPrediction>> {
+        let prediction = self.predict(bt, ror, events, lot_temperature);
+        if prediction.is_none() && self.last.is_none() {
+            return None;
+        }
+        self.last = prediction.clone();
+        Some(prediction)
+    }
+
+    fn predict(
+        &self,
+        bt: &[Point],
+        ror: &[Point],
+        events: &BTreeMap<String, RoastEvent>,
+        lot_temperature: Option<f64>,
+    ) -> Option<FcPrediction> {
+        if events.contains_key("FC_START") || events.contains_key("DROP") {
+            return None;
+        }
+        let tp = events.get("TP")?;
+        let now = bt.last()?;
+
+        // the RoR falls steeply before TP, it is no trend for after
+        let (ror_now, ror_change) = ror_trend(ror, tp.timestamp, now.timestamp, self.window)?;
+
+        let (temperature, source) = match (self.temperature, lot_temperature) {
+            (Some(t), _) => (t, "config"),
+            (None, Some(t)) => (t, "lot"),
+            (None, None) => (self.default_temperature, "default"),
+        };
+
+        let ahead = time_to_rise(temperature - now.value, ror_now, ror_change)?;
+
+        Some(FcPrediction {
+            timestamp: now.timestamp + ahead,
+            temperature,
+            source: source.to_string(),
+            ror: ror_now,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::{self, RorSettings};
+    use serde::Deserialize;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[derive(Deserialize)]
+    struct Roast {
+        data: Vec<Point>,
+    }
+
+    // BT of the celsius sample alog, the roast the RoR tests use too
+    fn recorded_bt() -> Vec<Point> {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("samples/ror/ethiopia_guji_bt.json");
+        let roast: Roast = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        roast.data
+    }
+
+    fn predictor(prediction: &str) -> FcPredictor {
+        let config: Config = toml::from_str(&format!(
+            "version = 'v1'\nbrand = ''\nmodel = ''\ntemperature_unit = 'C'\nalarms = []\n{}",
+            prediction
+        ))
+        .unwrap();
+        let mut predictor = FcPredictor::new();
+        predictor.configure(&config);
+        predictor
+    }
+
+    fn tp(timestamp: f64, value: f64) -> BTreeMap<String, RoastEvent> {
+        BTreeMap::from([(
+            String::from("TP"),
+            RoastEvent {
+                id: String::from("TP"),
+                timestamp,
+                value,
+            },
+        )])
+    }
+
+    #[test]
+    fn rise_and_time_to_rise_agree() {
+        // a RoR held, BT rises in a straight line
+        assert_eq!(time_to_rise(10.0, 12.0, 0.0), Some(50.0));
+        assert_eq!(rise_in(50.0, 12.0, 0.0), 10.0);
+        assert_eq!(time_to_rise(10.0, 12.0, 0.01), Some(50.0));
+        assert_eq!(time_to_rise(-2.0, 12.0, -0.01), Some(0.0));
+        assert_eq!(time_to_rise(10.0, 0.0, 0.0), None);
+        assert_eq!(time_to_rise(10.0, -3.0, 0.0), None);
+
+        // a falling RoR takes longer, and the two are each other's inverse
+        for rise in [1.0, 5.0, 20.0] {
+            let ahead = time_to_rise(rise, 12.0, -0.02).unwrap();
+            assert!(ahead > rise * 60.0 / 12.0);
+            assert!((rise_in(ahead, 12.0, -0.02) - rise).abs() < 1e-9);
+        }
+
+        // the RoR dies out at ror_now / 60k = 12 * 12 / (60 * 0.02) = 120 above
+        assert!(rise_in(MAX_AHEAD, 12.0, -0.02) < 120.0);
+        assert_eq!(time_to_rise(120.0, 12.0, -0.02), None);
+        // too far ahead
+        assert_eq!(time_to_rise(100.0, 3.0, 0.0), None);
+    }
+
+    #[test]
+    fn fc_temperature_is_config_then_lot_then_default() {
+        let bt = recorded_bt();
+        let now = bt.iter().position(|p| p.timestamp >= 450.0).unwrap();
+        let bt = &bt[..=now];
+        let ror = analytics::compute("BT", bt, None, &RorSettings::new(None)).smoothed;
+        let events = tp(104.0, 89.0);
+
+        let predict =
+            |predictor: &FcPredictor, lot| predictor.predict(bt, &ror, &events, lot).unwrap();
+        let configured = predictor("[prediction]\nfc_temperature = 200.0\n");
+        let unconfigured = predictor("");
+
+        let p = predict(&configured, Some(190.0));
+        assert_eq!((p.temperature, p.source.as_str()), (200.0, "config"));
+        let p = predict(&unconfigured, Some(190.0));
+        assert_eq!((p.temperature, p.source.as_str()), (190.0, "lot"));
+        let lot = p.timestamp;
+        let p = predict(&unconfigured, None);
+        assert_eq!((p.temperature, p.source.as_str()), (196.0, "default"));
+        let default = p.timestamp;
+        assert!(default > lot);
+
+        // the recording reaches 190 at 534 and 196 at 563
+        assert!(p.ror > 0.0);
+        assert!((lot - 534.0).abs() < 30.0, "{}", lot);
+        assert!((default - 563.0).abs() < 30.0, "{}", default);
+    }
+
+    #[test]
+    fn no_prediction_before_tp_or_after_fc() {
+        let bt = recorded_bt();
+        let bt = &bt[..450];
+        let ror = analytics::compute("BT", bt, None, &RorSettings::new(None)).smoothed;
+        let mut predictor = predictor("");
+
+        assert!(predictor.update(bt, &ror, &BTreeMap::new(), None).is_none());
+        let mut events = tp(104.0, 89.0);
+        assert!(matches!(
+            predictor.update(bt, &ror, &events, None),
+            Some(Some(_))
+        ));
+        events.insert(
+            String::from("FC_START"),
+            RoastEvent {
+                id: String::from("FC_START"),
+                timestamp: 440.0,
+                value: 185.0,
+            },
+        );
+        // the prediction ends once, then there is nothing to emit
+        assert!(matches!(
+            predictor.update(bt, &ror, &events, None),
+            Some(None)
+        ));
+        assert!(predictor.update(bt, &ror, &events, None).is_none());
+    }
+}
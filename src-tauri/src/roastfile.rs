@@ -0,0 +1,416 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use crate::actuators::OutputChange;
+use crate::config::Config;
+use crate::phases::PhaseReport;
+use crate::session::{Point, RoastEvent};
+use crate::targeting::DropTargetRecord;
+
+// version written by save(). bump it, and add a migration, when the layout changes
+pub const FORMAT_VERSION: u32 = 1;
+
+pub const ROAST_EVENT_IDS: [&str; 8] = [
+    "CHARGE", "TP", "DRY_END", "FC_START", "FC_END", "SC_START", "SC_END", "DROP",
+];
+
+// a roast profile on disk, field names as written by saveFile() in the webview
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RoastFile {
+    pub format_version: u32,
+    pub channel_arr: Vec<ChannelData>,
+    pub manual_channel_arr: Vec<ChannelData>,
+    pub roast_events: BTreeMap<String, RoastEvent>,
+    pub title: String,
+    pub country: String,
+    pub process: String,
+    pub notes: String,
+    pub weight_green: f64,
+    pub weight_roasted: f64,
+    pub volume_green: f64,
+    pub volume_roasted: f64,
+    pub density_green: f64,
+    pub density_roasted: f64,
+    pub moisture_green: f64,
+    pub moisture_roasted: f64,
+    pub color_whole: f64,
+    pub color_ground: f64,
+    pub flavor_list: Vec<String>,
+    pub output_log: Vec<OutputChange>,
+    pub config: Option<Config>,   // device config at the time of the roast
+    pub roast_epoch: Option<u64>, // unix time in milliseconds of START
+    pub green_lot: Option<u64>,   // id in the green coffee inventory
+    pub batch: Option<String>,    // batch number, with the configured prefix
+    pub drop_target: Option<DropTargetRecord>, // DTR or BT aimed at, and achieved
+    pub phases: Option<PhaseReport>, // computed when saved
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelData {
+    pub id: String,
+    pub data_arr: Vec<Point>,
+}
+
+impl ChannelData {
+    // the reading at a timestamp, if there is one within half a second
+    pub fn value_at(&self, timestamp: f64) -> Option<f64> {
+        let i = self.data_arr.partition_point(|p| p.timestamp < timestamp);
+        match self.data_arr.get(i) {
+            Some(p) if (p.timestamp - timestamp).abs() < 0.5 => Some(p.value),
+            _ => None,
+        }
+    }
+
+    // the latest value at or before a timestamp, for manual channels
+    pub fn value_before(&self, timestamp: f64) -> Option<f64> {
+        let i = self.data_arr.partition_point(|p| p.timestamp <= timestamp);
+        i.checked_sub(1).map(|i| self.data_arr[i].value)
+    }
+}
+
+impl RoastFile {
+    pub fn new() -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            channel_arr: Vec::new(),
+            manual_channel_arr: Vec::new(),
+            roast_events: BTreeMap::new(),
+            title: String::new(),
+            country: String::new(),
+            process: String::new(),
+            notes: String::new(),
+            weight_green: 0.0,
+            weight_roasted: 0.0,
+            volume_green: 0.0,
+            volume_roasted: 0.0,
+            density_green: 0.0,
+            density_roasted: 0.0,
+            moisture_green: 0.0,
+            moisture_roasted: 0.0,
+            color_whole: 0.0,
+            color_ground: 0.0,
+            flavor_list: Vec::new(),
+            output_log: Vec::new(),
+            config: None,
+            roast_epoch: None,
+            green_lot: None,
+            batch: None,
+            drop_target: None,
+            phases: None,
+        }
+    }
+
+    pub fn channel(&self, id: &str) -> Option<&ChannelData> {
+        self.channel_arr.iter().find(|c| c.id == id)
+    }
+
+    // every timestamp of every channel, in order
+    pub fn timeline(&self) -> Vec<f64> {
+        let mut timeline: Vec<f64> = self
+            .channel_arr
+            .iter()
+            .flat_map(|c| c.data_arr.iter().map(|p| p.timestamp))
+            .collect();
+        timeline.sort_by(|a, b| a.total_cmp(b));
+        timeline.dedup();
+        timeline
+    }
+
+    // everything wrong with the file, not only the first problem
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+
+        if self.format_version != FORMAT_VERSION {
+            errors.push(format!("format version {}", self.format_version));
+        }
+        if self.channel("BT").is_none() {
+            errors.push(String::from("no BT channel"));
+        }
+
+        for (kind, arr) in [
+            ("channel", &self.channel_arr),
+            ("manual channel", &self.manual_channel_arr),
+        ] {
+            for c in arr {
+                if c.data_arr.iter().any(|p| !p.timestamp.is_finite()) {
+                    errors.push(format!("{} {} has an invalid timestamp", kind, c.id));
+                }
+                if c.data_arr
+                    .windows(2)
+                    .any(|w| w[1].timestamp < w[0].timestamp)
+                {
+                    errors.push(format!("{} {} goes back in time", kind, c.id));
+                }
+            }
+        }
+
+        for (key, e) in self.roast_events.iter() {
+            if !ROAST_EVENT_IDS.contains(&key.as_str()) {
+                errors.push(format!("unknown roast event {}", key));
+            } else if e.id != *key {
+                errors.push(format!("roast event {} is stored as {}", e.id, key));
+            }
+        }
+        if let (Some(charge), Some(drop)) = (
+            self.roast_events.get("CHARGE"),
+            self.roast_events.get("DROP"),
+        ) {
+            if drop.timestamp <= charge.timestamp {
+                errors.push(String::from("DROP is not after CHARGE"));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+}
+
+// v0 -> v1 : the unversioned layout. flavorList and outputLog came later,
+// and NaN or Infinity of an empty input were written as null
+fn migrate_v0(roast: &mut Map<String, Value>) {
+    for key in [
+        "weightGreen",
+        "weightRoasted",
+        "volumeGreen",
+        "volumeRoasted",
+        "densityGreen",
+        "densityRoasted",
+        "moistureGreen",
+        "moistureRoasted",
+        "colorWhole",
+        "colorGround",
+    ] {
+        if !roast.get(key).map(|v| v.is_number()).unwrap_or(false) {
+            roast.insert(key.to_string(), json!(0.0));
+        }
+    }
+    for key in ["title", "country", "process", "notes"] {
+        if !roast.get(key).map(|v| v.is_string()).unwrap_or(false) {
+            roast.insert(key.to_string(), json!(""));
+        }
+    }
+    for key in ["manualChannelArr", "flavorList", "outputLog"] {
+        if !roast.get(key).map(|v| v.is_array()).unwrap_or(false) {
+            roast.insert(key.to_string(), json!([]));
+        }
+    }
+
+    // unset events were sometimes written as null
+    let events = match roast.remove("roastEvents") {
+        Some(Value::Object(events)) => events.into_iter().filter(|(_, e)| e.is_object()).collect(),
+        _ => Map::new(),
+    };
+    roast.insert(String::from("roastEvents"), Value::Object(events));
+}
+
+// MIGRATIONS[n] takes a version n file to version n + 1
+const MIGRATIONS: [fn(&mut Map<String, Value>); FORMAT_VERSION as usize] = [migrate_v0];
+
+// bring any known version up to FORMAT_VERSION, then check it
+pub fn parse(content: &str) -> Result<RoastFile, String> {
+    let mut value: Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let roast = value
+        .as_object_mut()
+        .ok_or("roast file is not a json object")?;
+
+    let version = match roast.get("formatVersion") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or("invalid formatVersion")? as u32,
+    };
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "format version {} is newer than this RoastCraft ({})",
+            version, FORMAT_VERSION
+        ));
+    }
+    for migrate in MIGRATIONS.iter().skip(version as usize) {
+        migrate(roast);
+    }
+    roast.insert(String::from("formatVersion"), json!(FORMAT_VERSION));
+
+    let roast: RoastFile = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    roast.validate()?;
+    Ok(roast)
+}
+
+pub fn load(path: &Path) -> Result<RoastFile, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{} : {}", path.display(), e))?;
+    let roast = parse(&content).map_err(|e| format!("{} : {}", path.display(), e))?;
+    info!("roast file loaded : {}", path.display());
+    Ok(roast)
+}
+
+pub fn save(path: &Path, roast: &RoastFile) -> Result<(), String> {
+    roast.validate()?;
+    write_json_atomic(path, roast)?;
+    info!("roast file saved : {}", path.display());
+    Ok(())
+}
+
+// written aside, synced and renamed, so a crash leaves the old file or the new
+// one, never half of it. roast files and the json stores share it
+pub fn write_json_atomic<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("json.tmp");
+    let mut file = File::create(&tmp).map_err(|e| e.to_string())?;
+    file.write_all(content.as_bytes())
+        .and_then(|_| file.sync_all())
+        .map_err(|e| e.to_string())?;
+    fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("roastcraft-roastfile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn channel(id: &str, values: &[f64]) -> ChannelData {
+        ChannelData {
+            id: id.to_string(),
+            data_arr: values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| Point {
+                    timestamp: i as f64,
+                    value: *v,
+                })
+                .collect(),
+        }
+    }
+
+    fn event(id: &str, timestamp: f64, value: f64) -> (String, RoastEvent) {
+        (
+            id.to_string(),
+            RoastEvent {
+                id: id.to_string(),
+                timestamp,
+                value,
+            },
+        )
+    }
+
+    #[test]
+    fn save_then_load_is_equal() {
+        let mut roast = RoastFile::new();
+        roast.channel_arr = vec![
+            channel("BT", &[180.0, 150.5, 120.25, 125.0, 140.0]),
+            channel("ET", &[220.0, 210.0, 205.5, 208.0, 215.0]),
+        ];
+        roast.manual_channel_arr = vec![channel("gas", &[40.0, 40.0, 60.0])];
+        roast.roast_events = BTreeMap::from([
+            event("CHARGE", 0.0, 180.0),
+            event("TP", 2.0, 120.25),
+            event("DROP", 4.0, 140.0),
+        ]);
+        roast.title = String::from("Ethiopia Guji");
+        roast.notes = String::from("sweet, \"floral\"\nline two");
+        roast.weight_green = 250.0;
+        roast.weight_roasted = 212.5;
+        roast.flavor_list = vec![String::from("Jasmine")];
+        roast.roast_epoch = Some(1_700_000_000_000);
+        roast.green_lot = Some(3);
+        roast.batch = Some(String::from("B-0042"));
+
+        let path = temp_path("roundtrip.json");
+        save(&path, &roast).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&roast).unwrap()
+        );
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn save_refuses_an_invalid_roast() {
+        let roast = RoastFile::new(); // no BT
+        let path = temp_path("invalid.json");
+        assert!(save(&path, &roast).is_err());
+        assert!(!path.exists());
+    }
+
+    // as written by saveFile() before the format was versioned
+    const BASELINE: &str = r#"{
+  "channelArr": [
+    { "id": "ET", "dataArr": [ { "timestamp": 0, "value": 230.1 }, { "timestamp": 1, "value": 228 } ] },
+    { "id": "BT", "dataArr": [ { "timestamp": 0, "value": 190.5 }, { "timestamp": 1, "value": 170 } ] }
+  ],
+  "manualChannelArr": [
+    { "id": "gas", "dataArr": [ { "timestamp": 0, "value": 50 } ] }
+  ],
+  "roastEvents": {
+    "CHARGE": { "id": "CHARGE", "timestamp": 0, "value": 190.5 },
+    "TP": null
+  },
+  "title": "Kenya AA",
+  "country": "Kenya",
+  "process": "Washed",
+  "notes": "",
+  "weightGreen": 300,
+  "weightRoasted": null,
+  "volumeGreen": 0,
+  "volumeRoasted": 0,
+  "densityGreen": null,
+  "densityRoasted": 0,
+  "moistureGreen": 0,
+  "moistureRoasted": 0,
+  "colorWhole": 0,
+  "colorGround": 0
+}"#;
+
+    #[test]
+    fn migrates_the_unversioned_layout() {
+        let roast = parse(BASELINE).unwrap();
+
+        assert_eq!(roast.format_version, FORMAT_VERSION);
+        assert_eq!(roast.title, "Kenya AA");
+        assert_eq!(roast.channel("BT").unwrap().data_arr[1].value, 170.0);
+        assert_eq!(roast.channel("ET").unwrap().data_arr.len(), 2);
+        assert_eq!(roast.manual_channel_arr[0].id, "gas");
+        assert_eq!(roast.weight_green, 300.0);
+        assert_eq!(roast.weight_roasted, 0.0);
+        assert_eq!(roast.density_green, 0.0);
+        assert!(roast.flavor_list.is_empty());
+        assert!(roast.output_log.is_empty());
+        assert_eq!(roast.roast_events.len(), 1);
+        assert_eq!(roast.roast_events["CHARGE"].value, 190.5);
+        assert!(roast.config.is_none());
+
+        // and saved as the current version
+        let path = temp_path("migrated.json");
+        save(&path, &roast).unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved["formatVersion"], json!(FORMAT_VERSION));
+    }
+
+    #[test]
+    fn refuses_a_newer_version() {
+        let newer = BASELINE.replacen(
+            '{',
+            &format!("{{ \"formatVersion\": {},", FORMAT_VERSION + 1),
+            1,
+        );
+        assert!(parse(&newer).err().unwrap().contains("newer"));
+    }
+}
@@ -0,0 +1,185 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+use crate::actuators::unix_millis;
+use crate::config::{Config, Safety};
+
+#[derive(Serialize, Clone)]
+pub struct SafetyAlarm {
+    pub reason: String,
+    pub unconfirmed: Vec<String>, // safe outputs their actuators did not confirm yet
+    pub timestamp: u128,          // unix time in milliseconds
+}
+
+// hard limits checked in the backend, whatever the webview is doing.
+// a trip latches an alarm, which stays until acknowledged with the fault gone
+pub struct Interlock {
+    config: Option<Safety>,
+    watching: bool,
+    last_sample: Instant,
+    last_heartbeat: Instant,
+    sample_fault: Option<String>,
+    timeout_fault: Option<String>,
+    alarm: Option<SafetyAlarm>,
+}
+
+impl Interlock {
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            watching: false,
+            last_sample: Instant::now(),
+            last_heartbeat: Instant::now(),
+            sample_fault: None,
+            timeout_fault: None,
+            alarm: None,
+        }
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        self.config = config.safety.clone();
+    }
+
+    // device on, timeouts count from now
+    pub fn start(&mut self) {
+        self.watching = true;
+        self.last_sample = Instant::now();
+        self.last_heartbeat = Instant::now();
+    }
+
+    pub fn stop(&mut self) {
+        self.watching = false;
+        self.timeout_fault = None;
+    }
+
+    pub fn heartbeat(&mut self) {
+        self.last_heartbeat = Instant::now();
+    }
+
+    fn latch(&mut self, reason: &str) -> Option<SafetyAlarm> {
+        if self.alarm.is_some() {
+            return None;
+        }
+        warn!("safety trip : {}", reason);
+        let alarm = SafetyAlarm {
+            reason: reason.to_string(),
+            unconfirmed: Vec::new(),
+            timestamp: unix_millis(),
+        };
+        self.alarm = Some(alarm.clone());
+        Some(alarm)
+    }
+
+    // check a read_channels payload, returns the alarm if this sample trips it
+    pub fn check_sample(&mut self, channels: &Value) -> Option<SafetyAlarm> {
+        self.last_sample = Instant::now();
+
+        let safety = self.config.as_ref()?;
+        let sensor_min = safety.sensor_min.unwrap_or(-50.0);
+        let sensor_max = safety.sensor_max.unwrap_or(1000.0);
+
+        let mut fault = None;
+        for limit in safety.limit.iter().flatten() {
+            match channels.get(&limit.channel_id).and_then(|v| v.as_f64()) {
+                Some(v) if v.is_finite() && v >= sensor_min && v <= sensor_max => {
+                    if v > limit.max {
+                        fault = Some(format!(
+                            "{} {} above maximum {}",
+                            limit.channel_id, v, limit.max
+                        ));
+                        break;
+                    }
+                }
+                Some(v) => {
+                    fault = Some(format!("{} sensor open, reading {}", limit.channel_id, v));
+                    break;
+                }
+                None => {
+                    fault = Some(format!("{} sensor open, no reading", limit.channel_id));
+                    break;
+                }
+            }
+        }
+
+        self.sample_fault = fault.clone();
+        self.latch(&fault?)
+    }
+
+    // stale data and lost heartbeat, called periodically by the watchdog
+    pub fn check_timeouts(&mut self) -> Option<SafetyAlarm> {
+        let safety = self.config.as_ref()?;
+        if !self.watching {
+            return None;
+        }
+
+        let stale_timeout = Duration::from_secs(safety.stale_timeout.unwrap_or(10));
+        let heartbeat_timeout = safety.heartbeat_timeout.map(Duration::from_secs);
+
+        let fault = if self.last_sample.elapsed() > stale_timeout {
+            Some(format!(
+                "no data from the device for {} s",
+                self.last_sample.elapsed().as_secs()
+            ))
+        } else {
+            match heartbeat_timeout {
+                Some(t) if self.last_heartbeat.elapsed() > t => Some(format!(
+                    "no heartbeat from the window for {} s",
+                    self.last_heartbeat.elapsed().as_secs()
+                )),
+                _ => None,
+            }
+        };
+
+        self.timeout_fault = fault.clone();
+        self.latch(&fault?)
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.alarm.is_some()
+    }
+
+    pub fn alarm(&self) -> Option<SafetyAlarm> {
+        self.alarm.clone()
+    }
+
+    // a safe output failed or was confirmed, returns the alarm when that changed it
+    pub fn confirm_output(&mut self, channel_id: &str, confirmed: bool) -> Option<SafetyAlarm> {
+        let alarm = self.alarm.as_mut()?;
+        let listed = alarm.unconfirmed.iter().any(|id| id == channel_id);
+        if confirmed == listed {
+            alarm.unconfirmed.retain(|id| id != channel_id);
+            if !confirmed {
+                alarm.unconfirmed.push(channel_id.to_string());
+            }
+            return Some(alarm.clone());
+        }
+        None
+    }
+
+    pub fn acknowledge(&mut self) -> Result<(), String> {
+        if let Some(fault) = self.sample_fault.as_ref().or(self.timeout_fault.as_ref()) {
+            return Err(format!("safety fault still active : {}", fault));
+        }
+        if self.alarm.take().is_some() {
+            info!("safety alarm acknowledged");
+        }
+        Ok(())
+    }
+
+    // manual channels forced on a trip, and their values
+    pub fn safe_outputs(&self) -> Vec<(String, f64)> {
+        self.config
+            .iter()
+            .flat_map(|s| s.output.iter())
+            .map(|o| (o.channel_id.clone(), o.value))
+            .collect()
+    }
+
+    pub fn is_safe_output(&self, channel_id: &str) -> bool {
+        self.safe_outputs().iter().any(|(id, _)| id == channel_id)
+    }
+}
@@ -0,0 +1,393 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::actuators::unix_millis;
+use crate::config::Config;
+use crate::journal::Journal;
+use crate::roastfile::{ChannelData, RoastFile};
+
+const JOURNAL_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SessionStatus {
+    Off,       // device off, nothing recorded
+    On,        // reading channels
+    Recording, // reading channels and recording them
+    Stopped,   // device off, keeping the recorded roast
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Point {
+    pub timestamp: f64, // seconds from START, whole seconds like the webview timer
+    pub value: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct RoastEvent {
+    pub id: String, // CHARGE, TP, DRY_END, FC_START, FC_END, SC_START, SC_END, DROP
+    pub timestamp: f64,
+    pub value: f64, // BT when marked
+}
+
+// one change of the session, emitted as "session_update" and journaled
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SessionUpdate {
+    Status {
+        status: SessionStatus,
+        timer: f64,
+    },
+    Sample {
+        timestamp: f64,
+        values: BTreeMap<String, f64>,
+    },
+    ManualChange {
+        channel_id: String,
+        point: Point,
+    },
+    RoastEvent {
+        event: RoastEvent,
+    },
+}
+
+// the whole session, for a webview which (re)loads
+#[derive(Serialize, Clone)]
+pub struct SessionSnapshot {
+    pub status: SessionStatus,
+    pub timer: f64,
+    pub channels: BTreeMap<String, Vec<Point>>,
+    pub manual_channels: BTreeMap<String, Vec<Point>>,
+    pub roast_events: BTreeMap<String, RoastEvent>,
+    pub batch: Option<String>,
+}
+
+// owns the roast being recorded, independent of the webview
+pub struct RoastSession {
+    status: SessionStatus,
+    started: Option<Instant>,   // START, the time base of all points
+    started_epoch: Option<u64>, // unix time in milliseconds of START
+    stopped_at: f64,
+    channels: BTreeMap<String, Vec<Point>>,
+    manual_channels: BTreeMap<String, Vec<Point>>,
+    manual_values: BTreeMap<String, f64>, // latest value of each manual channel
+    roast_events: BTreeMap<String, RoastEvent>,
+    latest_bt: Option<f64>,
+    batch: Option<String>, // numbered at START
+    journal_dir: Option<PathBuf>,
+    journal: Option<Journal>,
+}
+
+impl RoastSession {
+    pub fn new() -> Self {
+        Self {
+            status: SessionStatus::Off,
+            started: None,
+            started_epoch: None,
+            stopped_at: 0.0,
+            channels: BTreeMap::new(),
+            manual_channels: BTreeMap::new(),
+            manual_values: BTreeMap::new(),
+            roast_events: BTreeMap::new(),
+            latest_bt: None,
+            batch: None,
+            journal_dir: None,
+            journal: None,
+        }
+    }
+
+    pub fn set_journal_dir(&mut self, dir: PathBuf) {
+        self.journal_dir = Some(dir);
+    }
+
+    pub fn journal_dir(&self) -> Option<PathBuf> {
+        self.journal_dir.clone()
+    }
+
+    // the journal of this session, not a leftover one
+    pub fn active_journal(&self) -> Option<PathBuf> {
+        self.journal.as_ref().map(|j| j.path().to_path_buf())
+    }
+
+    fn journal(&mut self, update: &SessionUpdate) {
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(e) = journal.append(update) {
+                warn!("failed to write journal : {}", e);
+            }
+        }
+    }
+
+    pub fn status(&self) -> SessionStatus {
+        self.status
+    }
+
+    pub fn timer(&self) -> f64 {
+        match (self.status, self.started) {
+            (SessionStatus::Recording, Some(started)) => started.elapsed().as_secs_f64().floor(),
+            _ => self.stopped_at,
+        }
+    }
+
+    fn status_update(&self) -> SessionUpdate {
+        SessionUpdate::Status {
+            status: self.status,
+            timer: self.timer(),
+        }
+    }
+
+    // forget the recorded roast, manual channels back to their defaults
+    pub fn reset(&mut self, config: &Config) -> SessionUpdate {
+        self.started = None;
+        self.started_epoch = None;
+        self.stopped_at = 0.0;
+        self.channels.clear();
+        self.manual_channels.clear();
+        self.roast_events.clear();
+        self.latest_bt = None;
+        self.batch = None;
+        if let Some(journal) = self.journal.take() {
+            journal.discard();
+        }
+        self.manual_values = config
+            .manual_channel
+            .iter()
+            .flatten()
+            .map(|mc| (mc.channel_id.clone(), mc.default_value as f64))
+            .collect();
+        if self.status == SessionStatus::Stopped {
+            self.status = SessionStatus::Off;
+        }
+        self.status_update()
+    }
+
+    // device on, implicitly starting a new roast like the ON button
+    pub fn on(&mut self, config: &Config) -> SessionUpdate {
+        self.reset(config);
+        self.status = SessionStatus::On;
+        info!("session on");
+        self.status_update()
+    }
+
+    pub fn start(&mut self) -> Result<SessionUpdate, String> {
+        if self.status != SessionStatus::On {
+            return Err(format!("cannot start recording when {:?}", self.status));
+        }
+        self.started = Some(Instant::now());
+        self.started_epoch = Some(unix_millis() as u64);
+        self.status = SessionStatus::Recording;
+
+        if let Some(dir) = &self.journal_dir {
+            match Journal::create(dir, JOURNAL_SYNC_INTERVAL) {
+                Ok(j) => self.journal = Some(j),
+                Err(e) => warn!("failed to create journal : {}", e),
+            }
+        }
+        let update = self.status_update();
+        self.journal(&update);
+
+        // manual channels start where they are
+        let initial: Vec<(String, f64)> = self
+            .manual_values
+            .iter()
+            .map(|(id, v)| (id.clone(), *v))
+            .collect();
+        for (id, value) in initial {
+            let point = Point {
+                timestamp: 0.0,
+                value,
+            };
+            self.manual_channels.insert(id.clone(), vec![point.clone()]);
+            self.journal(&SessionUpdate::ManualChange {
+                channel_id: id,
+                point,
+            });
+        }
+        info!("session recording");
+        Ok(update)
+    }
+
+    // device off, a recording roast is kept as stopped
+    pub fn off(&mut self) -> SessionUpdate {
+        self.stopped_at = self.timer();
+        self.status = match self.status {
+            SessionStatus::Recording | SessionStatus::Stopped => SessionStatus::Stopped,
+            _ => SessionStatus::Off,
+        };
+        info!("session {:?}", self.status);
+        let update = self.status_update();
+        self.journal(&update);
+        update
+    }
+
+    pub fn stop(&mut self) -> Result<SessionUpdate, String> {
+        if self.status != SessionStatus::Recording {
+            return Err(String::from("not recording"));
+        }
+        self.stopped_at = self.timer();
+        self.status = SessionStatus::Stopped;
+        info!("session stopped at {} s", self.stopped_at);
+        let update = self.status_update();
+        self.journal(&update);
+        Ok(update)
+    }
+
+    // a read_channels payload, recorded while recording
+    pub fn sample(&mut self, channels: &Value) -> Option<SessionUpdate> {
+        let values: BTreeMap<String, f64> = channels
+            .as_object()?
+            .iter()
+            .filter_map(|(id, v)| Some((id.clone(), v.as_f64()?)))
+            .collect();
+        if let Some(bt) = values.get("BT") {
+            self.latest_bt = Some(*bt);
+        }
+
+        if self.status != SessionStatus::Recording {
+            return None;
+        }
+
+        let timestamp = self.timer();
+        for (id, value) in values.iter() {
+            self.channels.entry(id.clone()).or_default().push(Point {
+                timestamp,
+                value: *value,
+            });
+        }
+        let update = SessionUpdate::Sample { timestamp, values };
+        self.journal(&update);
+        Some(update)
+    }
+
+    pub fn manual_change(&mut self, channel_id: &str, value: f64) -> Option<SessionUpdate> {
+        self.manual_values.insert(channel_id.to_string(), value);
+
+        if self.status != SessionStatus::Recording {
+            return None;
+        }
+
+        let point = Point {
+            timestamp: self.timer(),
+            value,
+        };
+        self.manual_channels
+            .entry(channel_id.to_string())
+            .or_default()
+            .push(point.clone());
+        let update = SessionUpdate::ManualChange {
+            channel_id: channel_id.to_string(),
+            point,
+        };
+        self.journal(&update);
+        Some(update)
+    }
+
+    // mark, or move, a roast event. timestamp defaults to now, value to the latest BT
+    pub fn mark_event(
+        &mut self,
+        id: &str,
+        timestamp: Option<f64>,
+        value: Option<f64>,
+    ) -> Result<Option<SessionUpdate>, String> {
+        if self.status != SessionStatus::Recording && self.status != SessionStatus::Stopped {
+            return Err(String::from("roast events need a recording"));
+        }
+
+        let event = RoastEvent {
+            id: id.to_string(),
+            timestamp: timestamp.unwrap_or(self.timer()),
+            value: value.or(self.latest_bt).unwrap_or(0.0),
+        };
+        if self.roast_events.get(id) == Some(&event) {
+            return Ok(None);
+        }
+
+        info!("roast event {} at {} s", id, event.timestamp);
+        self.roast_events.insert(id.to_string(), event.clone());
+        let update = SessionUpdate::RoastEvent { event };
+        self.journal(&update);
+        Ok(Some(update))
+    }
+
+    // a roast was saved. once it holds every sample of a session no longer
+    // recording, the journal is not needed to recover it
+    pub fn saved(&mut self, roast: &RoastFile) {
+        if self.status == SessionStatus::Recording || self.journal.is_none() {
+            return;
+        }
+        let holds_all = self.channels.iter().all(|(id, points)| {
+            roast
+                .channel(id)
+                .map(|c| c.data_arr.len() == points.len() && c.data_arr.last() == points.last())
+                .unwrap_or(false)
+        });
+        if holds_all {
+            if let Some(journal) = self.journal.take() {
+                journal.discard();
+            }
+        }
+    }
+
+    pub fn set_batch(&mut self, batch: String) {
+        self.batch = Some(batch);
+    }
+
+    pub fn channel(&self, channel_id: &str) -> &[Point] {
+        self.channels
+            .get(channel_id)
+            .map(|c| c.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn manual_channels(&self) -> &BTreeMap<String, Vec<Point>> {
+        &self.manual_channels
+    }
+
+    pub fn roast_events(&self) -> &BTreeMap<String, RoastEvent> {
+        &self.roast_events
+    }
+
+    // roast event id and timestamp, for the alarm engine
+    pub fn event_times(&self) -> HashMap<String, f64> {
+        self.roast_events
+            .iter()
+            .map(|(id, e)| (id.clone(), e.timestamp))
+            .collect()
+    }
+
+    // the recorded roast, without the notes which the webview keeps
+    pub fn roast_file(&self) -> RoastFile {
+        let channel_data = |map: &BTreeMap<String, Vec<Point>>| {
+            map.iter()
+                .map(|(id, data_arr)| ChannelData {
+                    id: id.clone(),
+                    data_arr: data_arr.clone(),
+                })
+                .collect()
+        };
+
+        let mut roast = RoastFile::new();
+        roast.channel_arr = channel_data(&self.channels);
+        roast.manual_channel_arr = channel_data(&self.manual_channels);
+        roast.roast_events = self.roast_events.clone();
+        roast.roast_epoch = self.started_epoch;
+        roast.batch = self.batch.clone();
+        roast
+    }
+
+    pub fn snapshot(&self) -> SessionSnapshot {
+        SessionSnapshot {
+            status: self.status,
+            timer: self.timer(),
+            channels: self.channels.clone(),
+            manual_channels: self.manual_channels.clone(),
+            roast_events: self.roast_events.clone(),
+            batch: self.batch.clone(),
+        }
+    }
+}
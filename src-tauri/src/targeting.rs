@@ -0,0 +1,449 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::actuators::unix_millis;
+use crate::config::Config;
+use crate::prediction::{rise_in, ror_trend, time_to_rise};
+use crate::session::{Point, RoastEvent};
+
+// what DROP aims at, the one reached first decides
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DropTarget {
+    pub dtr: Option<f64>, // development time ratio, % of the roast after FC_START
+    pub temperature: Option<f64>, // BT
+}
+
+impl DropTarget {
+    fn is_set(&self) -> bool {
+        self.dtr.is_some() || self.temperature.is_some()
+    }
+
+    // zero or less is no target, like an empty input in the webview
+    fn valid(self) -> Self {
+        Self {
+            dtr: self.dtr.filter(|d| *d > 0.0 && *d < 100.0),
+            temperature: self.temperature.filter(|t| *t > 0.0),
+        }
+    }
+}
+
+// the target and what the roast came to, kept in the roast file
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DropTargetRecord {
+    pub target: DropTarget,
+    pub source: String, // roast, lot or config, where the target comes from
+    pub projected: Option<f64>, // DROP last projected before it was marked, seconds from START
+    pub dtr: Option<f64>, // achieved, once FC_START and DROP are marked
+    pub temperature: Option<f64>,
+}
+
+// emitted as "drop_countdown" with every sample between FC_START and DROP
+#[derive(Serialize, Clone)]
+pub struct DropCountdown {
+    pub timestamp: f64, // projected DROP, seconds from START
+    pub remaining: f64, // seconds
+    pub by: String,     // dtr or temperature, the target reached first
+    pub dtr: f64,       // now
+    pub dtr_at_drop: f64,
+    pub temperature_at_drop: Option<f64>, // BT, from the RoR trend
+}
+
+// what changed with a sample, None when there is nothing to emit
+pub struct DropTargetUpdate {
+    pub countdown: Option<Option<DropCountdown>>, // Some(None) when it ended, e.g. DROP was marked
+    pub alarms: Vec<DropAlarm>,
+    pub record: Option<Option<DropTargetRecord>>,
+}
+
+// a countdown second of [drop_target] passed, emitted as "drop_alarm"
+#[derive(Serialize, Clone)]
+pub struct DropAlarm {
+    pub label: String,
+    pub remaining: f64,
+    pub sound: bool,
+    pub timestamp: u128, // unix time in milliseconds
+}
+
+fn dtr(charge: f64, fc: f64, drop: f64) -> Option<f64> {
+    if drop > charge {
+        Some((drop - fc).max(0.0) / (drop - charge) * 100.0)
+    } else {
+        None
+    }
+}
+
+// projects DROP for a development time ratio or BT target after FC_START
+pub struct DropTargeter {
+    config: DropTarget,
+    lot: DropTarget,
+    roast: Option<DropTarget>, // set in the webview for this roast
+    countdown: Vec<f64>,       // seconds before DROP, largest first
+    sound: bool,
+    window: f64,
+    fired: Vec<f64>,
+    last: Option<DropCountdown>,
+    projected: Option<f64>,
+    record: Option<DropTargetRecord>,
+}
+
+impl DropTargeter {
+    pub fn new() -> Self {
+        Self {
+            config: DropTarget::default(),
+            lot: DropTarget::default(),
+            roast: None,
+            countdown: Vec::new(),
+            sound: true,
+            window: 90.0,
+            fired: Vec::new(),
+            last: None,
+            projected: None,
+            record: None,
+        }
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        let drop_target = config.drop_target.as_ref();
+        self.config = DropTarget {
+            dtr: drop_target.and_then(|d| d.dtr),
+            temperature: drop_target.and_then(|d| d.temperature),
+        }
+        .valid();
+        self.countdown = drop_target
+            .and_then(|d| d.countdown.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| c as f64)
+            .collect();
+        self.countdown.sort_by(|a, b| b.total_cmp(a));
+        self.sound = drop_target.and_then(|d| d.sound).unwrap_or(true);
+        // the BT RoR trend is the one of the first crack prediction
+        self.window = config
+            .prediction
+            .as_ref()
+            .and_then(|p| p.window)
+            .unwrap_or(90.0)
+            .max(10.0);
+    }
+
+    pub fn reset(&mut self) {
+        self.roast = None;
+        self.fired.clear();
+        self.last = None;
+        self.projected = None;
+        self.record = None;
+    }
+
+    // targets of the green lot being roasted, none when there is no lot
+    pub fn set_lot(&mut self, target: DropTarget) {
+        self.lot = target.valid();
+    }
+
+    // the target of this roast, None goes back to the lot or config
+    pub fn set_roast(&mut self, target: Option<DropTarget>) {
+        self.roast = target.map(|t| t.valid());
+        self.fired.clear();
+        let (target, source) = self.target();
+        info!(
+            "drop target of the {} : DTR {:?}, BT {:?}",
+            source, target.dtr, target.temperature
+        );
+    }
+
+    // the target and its source
+    fn target(&self) -> (DropTarget, &'static str) {
+        match &self.roast {
+            Some(t) => (t.clone(), "roast"),
+            None if self.lot.is_set() => (self.lot.clone(), "lot"),
+            None => (self.config.clone(), "config"),
+        }
+    }
+
+    pub fn last(&self) -> Option<DropCountdown> {
+        self.last.clone()
+    }
+
+    pub fn record(&self) -> Option<DropTargetRecord> {
+        self.record.clone()
+    }
+
+    // after each sample, the countdown, the countdown seconds passed and the record
+    pub fn update(
+        &mut self,
+        bt: &[Point],
+        ror: &[Point],
+        events: &BTreeMap<String, RoastEvent>,
+    ) -> DropTargetUpdate {
+        let countdown = self.project(bt, ror, events);
+        if let Some(c) = &countdown {
+            self.projected = Some(c.timestamp);
+        }
+        let alarms = self.alarms(countdown.as_ref());
+
+        let countdown = if countdown.is_none() && self.last.is_none() {
+            None
+        } else {
+            self.last = countdown.clone();
+            Some(countdown)
+        };
+        DropTargetUpdate {
+            countdown,
+            alarms,
+            record: self.update_record(events),
+        }
+    }
+
+    // the record, when the target or roast events changed it
+    pub fn update_record(
+        &mut self,
+        events: &BTreeMap<String, RoastEvent>,
+    ) -> Option<Option<DropTargetRecord>> {
+        let record = self.new_record(events);
+        if record == self.record {
+            return None;
+        }
+        self.record = record.clone();
+        Some(record)
+    }
+
+    fn new_record(&self, events: &BTreeMap<String, RoastEvent>) -> Option<DropTargetRecord> {
+        let (target, source) = self.target();
+        if !target.is_set() {
+            return None;
+        }
+        let charge = events.get("CHARGE").map_or(0.0, |e| e.timestamp);
+        let drop = events.get("DROP");
+        Some(DropTargetRecord {
+            target,
+            source: source.to_string(),
+            projected: self.projected,
+            dtr: match (events.get("FC_START"), drop) {
+                (Some(fc), Some(drop)) => dtr(charge, fc.timestamp, drop.timestamp),
+                _ => None,
+            },
+            temperature: drop.map(|e| e.value),
+        })
+    }
+
+    fn project(
+        &self,
+        bt: &[Point],
+        ror: &[Point],
+        events: &BTreeMap<String, RoastEvent>,
+    ) -> Option<DropCountdown> {
+        if events.contains_key("DROP") {
+            return None;
+        }
+        let (target, _) = self.target();
+        let fc = events.get("FC_START")?.timestamp;
+        let charge = events.get("CHARGE").map_or(0.0, |e| e.timestamp);
+        let now = bt.last()?;
+        let trend = ror_trend(ror, fc, now.timestamp, self.window);
+
+        // dtr = (drop - fc) / (drop - charge)
+        let by_dtr = target.dtr.map(|d| {
+            let r = d / 100.0;
+            (fc - r * charge) / (1.0 - r)
+        });
+        let by_temperature = match (target.temperature, trend) {
+            (Some(t), Some((ror_now, ror_change))) => {
+                time_to_rise(t - now.value, ror_now, ror_change).map(|a| now.timestamp + a)
+            }
+            _ => None,
+        };
+        let (timestamp, by) = match (by_dtr, by_temperature) {
+            (Some(d), Some(t)) if t < d => (t, "temperature"),
+            (Some(d), _) => (d, "dtr"),
+            (None, Some(t)) => (t, "temperature"),
+            (None, None) => return None,
+        };
+
+        let remaining = (timestamp - now.timestamp).max(0.0);
+        Some(DropCountdown {
+            timestamp,
+            remaining,
+            by: by.to_string(),
+            dtr: dtr(charge, fc, now.timestamp).unwrap_or(0.0),
+            dtr_at_drop: dtr(charge, fc, timestamp).unwrap_or(0.0),
+            temperature_at_drop: trend
+                .map(|(ror_now, ror_change)| now.value + rise_in(remaining, ror_now, ror_change)),
+        })
+    }
+
+    // one alarm for the countdown seconds passed since the last sample
+    fn alarms(&mut self, countdown: Option<&DropCountdown>) -> Vec<DropAlarm> {
+        let remaining = match countdown {
+            Some(c) => c.remaining,
+            None => return Vec::new(),
+        };
+        let passed: Vec<f64> = self
+            .countdown
+            .iter()
+            .filter(|c| remaining <= **c && !self.fired.contains(c))
+            .copied()
+            .collect();
+        if passed.is_empty() {
+            return Vec::new();
+        }
+        self.fired.extend(passed);
+
+        let label = if remaining < 1.0 {
+            String::from("drop")
+        } else {
+            format!("drop in {} seconds", remaining.round())
+        };
+        info!("drop alarm : {}", label);
+        vec![DropAlarm {
+            label,
+            remaining,
+            sound: self.sound,
+            timestamp: unix_millis(),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targeter(drop_target: &str) -> DropTargeter {
+        let config: Config = toml::from_str(&format!(
+            "version = 'v1'\nbrand = ''\nmodel = ''\ntemperature_unit = 'C'\nalarms = []\n{}",
+            drop_target
+        ))
+        .unwrap();
+        let mut targeter = DropTargeter::new();
+        targeter.configure(&config);
+        targeter
+    }
+
+    fn event(id: &str, timestamp: f64, value: f64) -> (String, RoastEvent) {
+        (
+            String::from(id),
+            RoastEvent {
+                id: String::from(id),
+                timestamp,
+                value,
+            },
+        )
+    }
+
+    // a RoR held at 9 per minute, CHARGE at 30 and FC_START at 400
+    fn roast(now: usize) -> (Vec<Point>, Vec<Point>, BTreeMap<String, RoastEvent>) {
+        let bt = (0..=now)
+            .map(|t| Point {
+                timestamp: t as f64,
+                value: 100.0 + 0.15 * t as f64,
+            })
+            .collect();
+        let ror = (0..=now)
+            .map(|t| Point {
+                timestamp: t as f64,
+                value: 9.0,
+            })
+            .collect();
+        let events = BTreeMap::from([
+            event("CHARGE", 30.0, 104.5),
+            event("FC_START", 400.0, 160.0),
+        ]);
+        (bt, ror, events)
+    }
+
+    fn countdown(targeter: &mut DropTargeter, now: usize) -> DropCountdown {
+        let (bt, ror, events) = roast(now);
+        targeter
+            .update(&bt, &ror, &events)
+            .countdown
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn dtr_target_projects_drop() {
+        let mut targeter = targeter("[drop_target]\ndtr = 20.0\n");
+        let c = countdown(&mut targeter, 420);
+
+        // (fc - r * charge) / (1 - r)
+        assert_eq!(c.timestamp, (400.0 - 0.2 * 30.0) / 0.8);
+        assert_eq!(c.by, "dtr");
+        assert_eq!(c.remaining, c.timestamp - 420.0);
+        assert!((c.dtr_at_drop - 20.0).abs() < 1e-9);
+        assert!((c.dtr - 20.0 / 390.0 * 100.0).abs() < 1e-9);
+        // BT 163 now, rising 9 per minute
+        let at_drop = 163.0 + 9.0 * c.remaining / 60.0;
+        assert!((c.temperature_at_drop.unwrap() - at_drop).abs() < 1e-6);
+    }
+
+    #[test]
+    fn the_target_reached_first_decides() {
+        // BT 163 now, 175 is 80 seconds away, after DTR 20 at 492.5
+        let mut targeter = targeter("[drop_target]\ndtr = 20.0\ntemperature = 175.0\n");
+        let c = countdown(&mut targeter, 420);
+        assert_eq!(c.by, "dtr");
+        assert_eq!(c.timestamp, 492.5);
+
+        // 170 is 46.7 seconds away
+        targeter.set_roast(Some(DropTarget {
+            dtr: Some(20.0),
+            temperature: Some(170.0),
+        }));
+        let c = countdown(&mut targeter, 420);
+        assert_eq!(c.by, "temperature");
+        assert!((c.timestamp - (420.0 + 7.0 * 60.0 / 9.0)).abs() < 1e-6);
+        assert!((c.temperature_at_drop.unwrap() - 170.0).abs() < 1e-6);
+
+        // a BT target alone
+        targeter.set_roast(Some(DropTarget {
+            dtr: None,
+            temperature: Some(175.0),
+        }));
+        let c = countdown(&mut targeter, 420);
+        assert_eq!(c.by, "temperature");
+        assert!((c.timestamp - 500.0).abs() < 1e-6);
+        let record = targeter.record().unwrap();
+        assert_eq!(record.source, "roast");
+        assert_eq!(record.projected, Some(c.timestamp));
+    }
+
+    #[test]
+    fn countdown_alarms_fire_once() {
+        // DROP projected at 492.5
+        let mut targeter = targeter("[drop_target]\ndtr = 20.0\ncountdown = [10, 30]\n");
+        let mut fired = Vec::new();
+        for now in 420..=495 {
+            let (bt, ror, events) = roast(now);
+            for alarm in targeter.update(&bt, &ror, &events).alarms {
+                fired.push((now, alarm.label));
+            }
+        }
+        assert_eq!(
+            fired,
+            vec![
+                (463, String::from("drop in 30 seconds")),
+                (483, String::from("drop in 10 seconds"))
+            ]
+        );
+
+        // a sample past both fires one alarm for them
+        targeter.reset();
+        let (bt, ror, events) = roast(493);
+        let alarms = targeter.update(&bt, &ror, &events).alarms;
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(alarms[0].label, "drop");
+        assert!(targeter.update(&bt, &ror, &events).alarms.is_empty());
+
+        // none after DROP, the countdown ends
+        let (bt, ror, mut events) = roast(494);
+        events.extend([event("DROP", 494.0, 174.1)]);
+        let update = targeter.update(&bt, &ror, &events);
+        assert!(update.alarms.is_empty());
+        assert!(matches!(update.countdown, Some(None)));
+        let record = update.record.unwrap().unwrap();
+        assert!((record.dtr.unwrap() - 94.0 / 464.0 * 100.0).abs() < 1e-9);
+    }
+}
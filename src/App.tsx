@@ -1,44 +1,67 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-import { onMount, onCleanup, For, Show } from "solid-js";
+import { onMount, onCleanup, createEffect, For, Show } from "solid-js";
 import { trace, attachConsole } from "tauri-plugin-log-api";
 import { UnlistenFn, listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
+import { message } from "@tauri-apps/api/dialog";
 
 import MainChart from "./MainChart";
-import { GET, SET, BT, AppStatus, Point, appStateSig, Channel, resetGhost } from "./AppState";
-import { autoDetectChargeDrop, calculatePhases, calculateRor, detectAlarm, findDryEnd, findRorOutlier, findTurningPoint } from "./calculate";
+import { GET, SET, appStateSig, resetGhost } from "./AppState";
 import SecondaryChart from "./SecondaryChart";
-import { openFile, loadGhost, saveFile } from "./fileUtil";
+import { openFile, loadGhost, saveFile, exportAlog, exportCsv, roastNotes } from "./fileUtil";
+import { applyRorUpdate, applySessionUpdate, recoverJournals, restoreSession, syncNotes, syncRoastEvents } from "./session";
+import { startAutopilot, pauseAutopilot, resumeAutopilot, handOverAutopilot } from "./autopilot";
 import DashboardPanel, { buttonOffClicked, buttonOnClicked, buttonResetClicked, buttonStartClicked, handleCharge, handleDrop, handleDryEnd, handleFCEnd, handleFCStart, handleSCEnd, handleSCStart } from "./DashboardPanel";
 import NotesPanel from "./NotesPanel";
 import SettingsPanel from "./SettingsPanel";
+import LibraryPanel from "./LibraryPanel";
+import InventoryPanel from "./InventoryPanel";
+import QueuePanel from "./QueuePanel";
 import hotkeys from "hotkeys-js";
 
 function App() {
 
     const [appState, _setAppState] = appStateSig;
-    const [status, _setStatus] = appState().statusSig;
-    const [timer, _setTimer] = appState().timerSig;
     const [channelArr, _setChannelArr] = appState().channelArrSig;
     const [logArr, setLogArr] = appState().logArrSig;
     const [roastEvents, _setRoastEvents] = appState().roastEventsSig;
     const [manualChannelArr, _setManualChannelArr] = appState().manualChannelArrSig;
     const [currentTabId, setCurrentTabId] = appState().currentTabIdSig;
-    const [_dryingPhase, setDryingPhase] = appState().dryingPhaseSig;
-    const [_maillardPhase, setMaillardPhase] = appState().maillardPhaseSig;
-    const [_developPhase, setDevelopPhase] = appState().developPhaseSig;
     const [phaseChartWidth, setPhaseChartWidth] = appState().phaseChartWidthSig;
     const channelIdList = channelArr().map(m => m.id);
-    const bt = channelArr().find(c => c.id == BT) as Channel;
+
+    // roast events marked, moved or auto detected here are recorded by the backend
+    createEffect(() => syncRoastEvents(roastEvents()));
+
+    // and the notes, for the autosaved file
+    createEffect(() => syncNotes(roastNotes()));
 
     const DASHBOARD = "DASHBOARD";
     const NOTES = "NOTES";
     const SETTINGS = "SETTINGS";
+    const LIBRARY = "LIBRARY";
+    const INVENTORY = "INVENTORY";
+    const QUEUE = "QUEUE";
 
     let detach: UnlistenFn;
     let unlisten_read_channels: UnlistenFn;
+    let unlisten_session_update: UnlistenFn;
+    let unlisten_ror_update: UnlistenFn;
     let unlisten_menu_event: UnlistenFn;
     let unlisten_log_event: UnlistenFn;
+    let unlisten_modbus_error: UnlistenFn;
+    let unlisten_output_changed: UnlistenFn;
+    let unlisten_safety_alarm: UnlistenFn;
+    let unlisten_alarm: UnlistenFn;
+    let unlisten_autosaved: UnlistenFn;
+    let unlisten_roast_event_detected: UnlistenFn;
+    let unlisten_fc_prediction: UnlistenFn;
+    let unlisten_drop_countdown: UnlistenFn;
+    let unlisten_drop_alarm: UnlistenFn;
+    let unlisten_drop_target: UnlistenFn;
+    let unlisten_phase_report: UnlistenFn;
+    let heartbeat_timer: number;
 
     onMount(async () => {
 
@@ -79,35 +102,20 @@ function App() {
                     (Math.floor(delta / time_elapsed_sec * 60 * 10)) / 10 || 0
                 );
                 /* calculate ROR end */
-
-                // write into history data
-                if (status() == AppStatus.RECORDING) {
-                    channelArr()[i].setDataArr(
-                        [...channelArr()[i].dataArr(), new Point(timer(), event.payload[channelIdList[i]])]
-                    )
-                }
             }
 
-            // BT only for now
-            calculateRor(bt, roastEvents());
-            findRorOutlier(bt);
-
-            autoDetectChargeDrop();
-            findTurningPoint();
-            findDryEnd();
-            let result = calculatePhases(timer(), bt.currentDataSig[GET](), roastEvents());
-            setDryingPhase(result.dry);
-            setMaillardPhase(result.mai);
-            setDevelopPhase(result.dev);
-
-            detectAlarm();
+        });
 
-            // dump bt data to console
-            // console.log(bt.dataArr());
-            // console.log(bt.rorArrSig[GET]());
-            // console.log(bt.rorFilteredArrSig[GET]());
-            // console.log(bt.rorConvolveArrSig[GET]());
+        // event listener
+        // history data is recorded by the backend session, mirrored here
+        unlisten_session_update = await listen("session_update", (event: any) => {
+            applySessionUpdate(event.payload);
+        });
 
+        // event listener
+        // RoR computed by the backend, sent before the sample it belongs to
+        unlisten_ror_update = await listen("ror_update", (event: any) => {
+            applyRorUpdate(event.payload);
         });
 
         // event listener
@@ -119,12 +127,33 @@ function App() {
                 case "SAVE_FILE":
                     saveFile();
                     break;
+                case "EXPORT_ALOG":
+                    exportAlog();
+                    break;
+                case "EXPORT_CSV":
+                    exportCsv();
+                    break;
                 case "LOAD_GHOST":
                     loadGhost();
                     break;
                 case "RESET_GHOST":
                     resetGhost();
                     break;
+                case "AUTOPILOT_START":
+                    startAutopilot();
+                    break;
+                case "AUTOPILOT_PAUSE":
+                    pauseAutopilot();
+                    break;
+                case "AUTOPILOT_RESUME":
+                    resumeAutopilot();
+                    break;
+                case "AUTOPILOT_HAND_OVER":
+                    handOverAutopilot();
+                    break;
+                case "SAFETY_ACKNOWLEDGE":
+                    acknowledgeSafetyAlarm();
+                    break;
                 default:
                     break;
             }
@@ -135,6 +164,90 @@ function App() {
             setLogArr([...logArr(), event.payload as string]);
         });
 
+        // event listener
+        unlisten_autosaved = await listen("autosaved", (event) => {
+            appState().autosavePathSig[SET](event.payload as string);
+            setLogArr([...logArr(), "autosaved: " + (event.payload as string).replace(/^.*[\\/]/, '')]);
+        });
+
+        // event listener
+        // the event itself comes with session_update
+        unlisten_roast_event_detected = await listen("roast_event_detected", (event: any) => {
+            let d = event.payload;
+            setLogArr([...logArr(), "detected " + d.event.id + ", confidence " + d.confidence.toFixed(2)]);
+        });
+
+        // event listener
+        unlisten_fc_prediction = await listen("fc_prediction", (event) => {
+            appState().fcPredictionSig[SET](event.payload);
+        });
+
+        // event listener
+        unlisten_drop_countdown = await listen("drop_countdown", (event) => {
+            appState().dropCountdownSig[SET](event.payload);
+        });
+
+        // event listener
+        unlisten_drop_alarm = await listen("drop_alarm", (event: any) => {
+            if (event.payload.sound) {
+                window.speechSynthesis.speak(new SpeechSynthesisUtterance(event.payload.label));
+            }
+        });
+
+        // event listener
+        unlisten_drop_target = await listen("drop_target", (event) => {
+            appState().dropTargetSig[SET](event.payload);
+        });
+
+        // event listener
+        unlisten_phase_report = await listen("phase_report", (event) => {
+            appState().phaseReportSig[SET](event.payload);
+        });
+
+        // event listener
+        unlisten_modbus_error = await listen("modbus_error", (event: any) => {
+            setLogArr([...logArr(), event.payload.message + " (errors: " + event.payload.error_count + ")"]);
+        });
+
+        // event listener
+        unlisten_output_changed = await listen("output_changed", (event: any) => {
+            // manual changes are already on the slider
+            if (event.payload.source == "manual") {
+                return;
+            }
+            let mc = manualChannelArr().find((mc) => mc.id == event.payload.channel_id);
+            if (mc != undefined) {
+                mc.currentDataSig[SET](Number(event.payload.value));
+            }
+        });
+
+        // event listener
+        unlisten_alarm = await listen("alarm", (event: any) => {
+            let alarm = appState().alarmsArrSig[GET]()[event.payload.index];
+            if (alarm != undefined) {
+                alarm.triggeredSig[SET](true);
+            }
+            if (event.payload.sound) {
+                window.speechSynthesis.speak(new SpeechSynthesisUtterance(event.payload.label));
+            }
+            // its roast event is marked by the backend, and comes with session_update
+        });
+
+        // event listener
+        // the alarm stays latched until acknowledged from the menu or the alarm bar
+        unlisten_safety_alarm = await listen("safety_alarm", async (event: any) => {
+            let latched = appState().safetyAlarmSig[GET]() == null;
+            appState().safetyAlarmSig[SET](event.payload);
+            if (latched) {
+                window.speechSynthesis.speak(new SpeechSynthesisUtterance("safety alarm"));
+                await message(event.payload.reason, { title: "Safety Alarm", type: "error" });
+            }
+        });
+        appState().safetyAlarmSig[SET](await invoke("get_safety_alarm"));
+
+        // lets the backend know the window is alive, see [safety] heartbeat_timeout
+        heartbeat_timer = setInterval(() => invoke("heartbeat"), 1000);
+
         window.speechSynthesis.onvoiceschanged = function () {
             // window.speechSynthesis.speak(new SpeechSynthesisUtterance("歡迎使用roastcraft"));
             if (window.speechSynthesis.getVoices().length > 0) {
@@ -186,17 +299,45 @@ function App() {
 
         initResizerFn();
 
+        // pick up a roast recorded before the window was (re)loaded
+        await restoreSession();
+
         setLogArr([...logArr(), "RoastCraft is ready"]);
 
+        await recoverJournals();
+
     });
 
     onCleanup(() => {
         detach();
         unlisten_read_channels();
+        unlisten_session_update();
+        unlisten_ror_update();
         unlisten_menu_event();
         unlisten_log_event();
+        unlisten_modbus_error();
+        unlisten_output_changed();
+        unlisten_safety_alarm();
+        unlisten_alarm();
+        unlisten_autosaved();
+        unlisten_roast_event_detected();
+        unlisten_fc_prediction();
+        unlisten_drop_countdown();
+        unlisten_drop_alarm();
+        unlisten_drop_target();
+        unlisten_phase_report();
+        clearInterval(heartbeat_timer);
     })
 
+    async function acknowledgeSafetyAlarm() {
+        try {
+            await invoke("safety_acknowledge");
+            appState().safetyAlarmSig[SET](null);
+        } catch (e) {
+            setLogArr([...logArr(), e as string]);
+        }
+    }
+
     function initResizerFn() {
 
         let resizer = document.querySelector(".resizer") as HTMLElement;
@@ -253,6 +394,18 @@ function App() {
                     </div>
                 </Show>
 
+                <Show when={appState().safetyAlarmSig[GET]() != null}>
+                    <div role="alert" class="alert alert-error rounded mx-1 mb-1">
+                        <div>
+                            <h3 class="font-bold">SAFETY : {appState().safetyAlarmSig[GET]().reason}</h3>
+                            <Show when={appState().safetyAlarmSig[GET]().unconfirmed.length > 0}>
+                                <div>safe output not confirmed : {appState().safetyAlarmSig[GET]().unconfirmed.join(", ")}</div>
+                            </Show>
+                        </div>
+                        <button class="btn btn-sm rounded" onClick={acknowledgeSafetyAlarm}>Acknowledge</button>
+                    </div>
+                </Show>
+
                 <MainChart />
 
                 <For each={manualChannelArr()}>
@@ -294,6 +447,27 @@ function App() {
                             }}>
                             Settings
                         </a>
+                        <a role="tab" class={`tab ${currentTabId() == 3 ? "tab-active" : ""}`}
+                            onClick={() => {
+                                setCurrentTabId(3);
+                                hotkeys.setScope(LIBRARY); // hotkeys is disabled in Library panel
+                            }}>
+                            Library
+                        </a>
+                        <a role="tab" class={`tab ${currentTabId() == 4 ? "tab-active" : ""}`}
+                            onClick={() => {
+                                setCurrentTabId(4);
+                                hotkeys.setScope(INVENTORY); // hotkeys is disabled in Inventory panel
+                            }}>
+                            Inventory
+                        </a>
+                        <a role="tab" class={`tab ${currentTabId() == 5 ? "tab-active" : ""}`}
+                            onClick={() => {
+                                setCurrentTabId(5);
+                                hotkeys.setScope(QUEUE); // hotkeys is disabled in Queue panel
+                            }}>
+                            Queue
+                        </a>
                     </div>
 
                     <div class={`flex flex-col gap-y-1 ${currentTabId() == 0 ? "" : "hidden"}`}>
@@ -305,6 +479,15 @@ function App() {
                     <div class={`flex flex-col gap-y-1 ${currentTabId() == 2 ? "" : "hidden"}`}>
                         <SettingsPanel></SettingsPanel>
                     </div>
+                    <div class={`flex flex-col gap-y-1 ${currentTabId() == 3 ? "" : "hidden"}`}>
+                        <LibraryPanel></LibraryPanel>
+                    </div>
+                    <div class={`flex flex-col gap-y-1 ${currentTabId() == 4 ? "" : "hidden"}`}>
+                        <InventoryPanel></InventoryPanel>
+                    </div>
+                    <div class={`flex flex-col gap-y-1 ${currentTabId() == 5 ? "" : "hidden"}`}>
+                        <QueuePanel></QueuePanel>
+                    </div>
 
                 </div>
                 {/* scrollable end*/}
@@ -429,6 +429,10 @@ async function init_appStateSig() {
     let config: any;
     await invoke("get_config").then(c => config = c);
 
+    // alarm rules, evaluated in the backend
+    let alarms: any;
+    await invoke("get_alarms").then(a => alarms = a);
+
     console.log("config");
     console.log(config);
 
@@ -513,6 +517,15 @@ async function init_appStateSig() {
     return {
         statusSig: createSignal(AppStatus.OFF),
         timerSig: createSignal(0),
+        roastEpochSig: createSignal(0), // unix time in milliseconds of START, 0 unknown
+        batchSig: createSignal(""), // numbered by the backend at START
+        plannedBatchSig: createSignal(null as any), // id of the queued batch loaded for START, null when none
+        autosavePathSig: createSignal(""), // file the backend saved the roast to
+        fcPredictionSig: createSignal(null as any), // first crack predicted by the backend, null when none
+        dropCountdownSig: createSignal(null as any), // DROP projected for the target, null when none
+        dropTargetSig: createSignal(null as any), // DROP target and achieved values, kept in the roast file
+        phaseReportSig: createSignal(null as any), // per phase statistics from the backend, null before CHARGE
+        safetyAlarmSig: createSignal(null as any), // latched by the backend until acknowledged, null when none
         timeDeltaSig: createSignal(0),
         channelArrSig: createSignal(channelArr),
         manualChannelArrSig: createSignal(manualChannelArr),
@@ -552,9 +565,10 @@ async function init_appStateSig() {
         moistureRoastedSig: createSignal(0.0),
         colorWholeSig: createSignal(0),
         colorGroundSig: createSignal(0),
+        greenLotSig: createSignal(0), // id in the green coffee inventory, 0 none
         flavorListSig: createSignal(new Array<string>()),
         flavorWheelSig: createSignal(init_flavorWheel()),
-        alarmsArrSig: createSignal(config.alarms.map((a: number) => { return { temperature: a, triggeredSig: createSignal(false) } })),
+        alarmsArrSig: createSignal(alarms.map((a: any) => { return { label: a.label, triggeredSig: createSignal(false) } })),
     }
 }
 
@@ -566,6 +580,14 @@ export function resetChannels() {
     const [manualChannelArr, _setManualChannelArr] = appState().manualChannelArrSig;
 
     appState().timerSig[SET](0);
+    appState().roastEpochSig[SET](0);
+    appState().batchSig[SET]("");
+    appState().plannedBatchSig[SET](null);
+    appState().autosavePathSig[SET]("");
+    appState().fcPredictionSig[SET](null);
+    appState().dropCountdownSig[SET](null);
+    appState().dropTargetSig[SET](null);
+    appState().phaseReportSig[SET](null);
     appState().timeDeltaSig[SET](0);
 
     // reset channelArr
@@ -630,6 +652,7 @@ export function resetNotes() {
     appState().moistureRoastedSig[SET](0.0);
     appState().colorWholeSig[SET](0);
     appState().colorGroundSig[SET](0);
+    appState().greenLotSig[SET](0);
     appState().flavorListSig[SET](new Array<string>());
     appState().flavorWheelSig[SET](init_flavorWheel());
 }
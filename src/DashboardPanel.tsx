@@ -19,6 +19,7 @@ import WorkerFactory from "./WorkerFactory";
 import timerWorker from "./timer.worker";
 import RangeInput from "./RangeInput";
 import PhaseChart from "./PhaseChart";
+import { loadPlannedBatch } from "./fileUtil";
 
 const [appState, _setAppState] = appStateSig;
 const [status, setStatus] = appState().statusSig;
@@ -31,6 +32,10 @@ const [dryingPhase, setDryingPhase] = appState().dryingPhaseSig;
 const [maillardPhase, setMaillardPhase] = appState().maillardPhaseSig;
 const [developPhase, setDevelopPhase] = appState().developPhaseSig;
 const [ghost, _setGhost] = appState().ghostSig;
+const [fcPrediction, _setFcPrediction] = appState().fcPredictionSig;
+const [dropCountdown, _setDropCountdown] = appState().dropCountdownSig;
+const [dropTarget, setDropTarget] = appState().dropTargetSig;
+const [phaseReport, _setPhaseReport] = appState().phaseReportSig;
 const bt = channelArr().find((c) => c.id == BT) as Channel;
 let timer_worker: Worker;
 
@@ -132,26 +137,88 @@ export async function buttonOffClicked() {
     setLogArr([...logArr(), "stopped reading channels"]);
 }
 
-export async function buttonStartClicked() {
+export function startTimer(start: number) {
+    if (timer_worker) {
+        timer_worker.terminate();
+    }
     timer_worker = new WorkerFactory(timerWorker) as Worker;
-    timer_worker.postMessage(1000);
+    timer_worker.postMessage({ interval: 1000, start: start });
     timer_worker.onmessage = (event: any) => {
         setTimer(event.data);
     };
+}
+
+export async function buttonStartClicked() {
+    let start: any;
+    try {
+        start = await invoke("session_start", {
+            utcOffset: -new Date().getTimezoneOffset(),
+            planned: appState().plannedBatchSig[GET](),
+        });
+    } catch (e) {
+        setLogArr([...logArr(), e as string]);
+        return;
+    }
+    startTimer(0);
+    appState().batchSig[SET](start.batch);
 
     setStatus(AppStatus.RECORDING);
-    setLogArr([...logArr(), "start recording"]);
+    setLogArr([...logArr(), "start recording batch " + start.batch]);
 }
 
 export async function buttonResetClicked() {
+    await invoke("session_reset");
     resetChannels();
     resetNotes();
     setStatus(AppStatus.OFF);
+
+    // the next roast of the queue
+    let planned: Array<any> = await invoke("batch_queue_list");
+    if (planned.length > 0) {
+        loadPlannedBatch(planned[0]);
+    }
+}
+
+// the target of this roast, both empty go back to the green lot or config
+async function updateDropTarget(dtr: number, temperature: number) {
+    try {
+        setDropTarget(await invoke("set_drop_target", {
+            target: dtr > 0 || temperature > 0 ? {
+                dtr: dtr > 0 ? dtr : null,
+                temperature: temperature > 0 ? temperature : null,
+            } : null,
+        }));
+    } catch (e) {
+        setLogArr([...logArr(), "failed to set drop target: " + e]);
+    }
 }
 
 export default function DashboardPanel() {
     return (
         <>
+            <Show
+                when={
+                    status() == AppStatus.RECORDING &&
+                    roastEvents().FC_START == undefined &&
+                    fcPrediction() != null
+                }
+            >
+                <div class="bg-base-300 rounded w-full px-1 text-2xl text-orange-600 font-bold py-1 flex" title="first crack predicted from the BT RoR trend">
+                    <span class="flex-grow text-center">
+                        {"FC " + timestamp_format(
+                            Math.round(fcPrediction().timestamp + appState().timeDeltaSig[GET]())
+                        )}
+                    </span>
+                    <span class="flex-grow text-center">
+                        {"in " + timestamp_format(
+                            Math.max(0, Math.round(fcPrediction().timestamp - timer()))
+                        )}
+                    </span>
+                    <span class="flex-grow text-center">
+                        {fcPrediction().temperature.toFixed(1) + "°"}
+                    </span>
+                </div>
+            </Show>
             <Show
                 when={
                     status() == AppStatus.RECORDING &&
@@ -175,6 +242,33 @@ export default function DashboardPanel() {
                     </span>
                 </div>
             </Show>
+            <Show
+                when={
+                    status() == AppStatus.RECORDING &&
+                    roastEvents().FC_START != undefined &&
+                    roastEvents().DROP == undefined &&
+                    dropCountdown() != null
+                }
+            >
+                <div class="bg-base-300 rounded w-full px-1 text-2xl text-red-600 font-bold py-1 flex" title={"DROP projected for the target " + dropCountdown().by}>
+                    <span class="flex-grow text-center">
+                        {"DROP " + timestamp_format(
+                            Math.round(dropCountdown().timestamp + appState().timeDeltaSig[GET]())
+                        )}
+                    </span>
+                    <span class="flex-grow text-center">
+                        {"in " + timestamp_format(Math.round(dropCountdown().remaining))}
+                    </span>
+                    <span class="flex-grow text-center">
+                        {dropCountdown().dtr_at_drop.toFixed(1) + "%"}
+                    </span>
+                    <Show when={dropCountdown().temperature_at_drop != null}>
+                        <span class="flex-grow text-center">
+                            {dropCountdown().temperature_at_drop.toFixed(1) + "°"}
+                        </span>
+                    </Show>
+                </div>
+            </Show>
             {/* timer and on/off buttons */}
             <div class="flex flex-wrap gap-1">
                 <div class="flex items-center justify-center bg-black text-white rounded text-4xl font-extrabold w-28 ">
@@ -295,6 +389,70 @@ export default function DashboardPanel() {
                     ]}
                 ></PhaseChart>
             </div>
+            {/* phase statistics, computed by the backend */}
+            <Show when={phaseReport() != null}>
+                <table class="table table-xs">
+                    <thead>
+                        <tr>
+                            <th></th>
+                            <th>time</th>
+                            <th>%</th>
+                            <th>rise</th>
+                            <th>RoR avg/max</th>
+                            <th title={"BT above " + phaseReport().auc_base + "°, degree minutes"}>AUC</th>
+                            <For each={Object.keys(phaseReport().total.manual)}>
+                                {(id) => <th>{id}</th>}
+                            </For>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        <For each={[
+                            ["dry", phaseReport().dry],
+                            ["mai", phaseReport().mai],
+                            ["dev", phaseReport().dev],
+                            ["total", phaseReport().total],
+                        ].filter(([_, p]) => p != null)}>
+                            {([name, p]: any) => (
+                                <tr>
+                                    <th>{name}</th>
+                                    <td>{timestamp_format(Math.round(p.time))}</td>
+                                    <td>{p.percent.toFixed(1)}</td>
+                                    <td>{p.temp_rise.toFixed(1)}</td>
+                                    <td>
+                                        {p.ror_mean != null ? p.ror_mean.toFixed(1) + "/" + p.ror_max.toFixed(1) : "-"}
+                                    </td>
+                                    <td>{p.auc.toFixed(0)}</td>
+                                    <For each={Object.keys(phaseReport().total.manual)}>
+                                        {(id) => <td>{p.manual[id]?.toFixed(0) ?? "-"}</td>}
+                                    </For>
+                                </tr>
+                            )}
+                        </For>
+                    </tbody>
+                </table>
+            </Show>
+            {/* drop target, of the roast, its green lot or the config */}
+            <div class="flex flex-wrap gap-1 items-center text-sm">
+                <span>target DTR</span>
+                <input class="input input-bordered input-xs rounded w-16" type="number"
+                    value={dropTarget()?.target.dtr ?? ""}
+                    onChange={(e) => {
+                        updateDropTarget(Number(e.currentTarget.value), dropTarget()?.target.temperature ?? 0);
+                    }}
+                />
+                <span>% BT</span>
+                <input class="input input-bordered input-xs rounded w-16" type="number"
+                    value={dropTarget()?.target.temperature ?? ""}
+                    onChange={(e) => {
+                        updateDropTarget(dropTarget()?.target.dtr ?? 0, Number(e.currentTarget.value));
+                    }}
+                />
+                <Show when={dropTarget()?.dtr != null}>
+                    <span class="font-bold">
+                        {"achieved " + dropTarget().dtr.toFixed(1) + "% " + dropTarget().temperature.toFixed(1) + "°"}
+                    </span>
+                </Show>
+            </div>
             <div class="flex flex-wrap gap-1">
                 <For each={appState().alarmsArrSig[GET]()}>
                     {(alarm) => (
@@ -303,7 +461,7 @@ export default function DashboardPanel() {
                                 alarm.triggeredSig[GET]() ? "" : "badge-outline"
                             }`}
                         >
-                            {alarm.temperature + "°"}
+                            {alarm.label}
                         </div>
                     )}
                 </For>
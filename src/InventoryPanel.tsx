@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+import { For, Show, createSignal, onCleanup, onMount } from "solid-js";
+import { UnlistenFn, listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
+import { ask } from "@tauri-apps/api/dialog";
+import { appStateSig } from "./AppState";
+
+function emptyLot() {
+    return {
+        name: "",
+        origin: "",
+        process: "",
+        supplier: "",
+        purchase_date: new Date().toISOString().slice(0, 10),
+        price: 0.0,
+        initial_weight: 0.0,
+        density: 0.0,
+        moisture: 0.0,
+        target_dtr: null,
+        target_drop_temperature: null,
+    };
+}
+
+export default function InventoryPanel() {
+
+    const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
+
+    const [lots, setLots] = createSignal(new Array<any>());
+    const [newLot, setNewLot] = createSignal(emptyLot() as any);
+    const [openId, setOpenId] = createSignal(0);
+    const [movements, setMovements] = createSignal(new Array<any>());
+
+    async function refresh() {
+        try {
+            setLots(await invoke("inventory_list"));
+        } catch (e) {
+            console.log(e);
+        }
+        if (openId() != 0) {
+            showMovements(openId());
+        }
+    }
+
+    async function showMovements(lotId: number) {
+        setOpenId(lotId);
+        try {
+            setMovements(await invoke("inventory_movements", { lotId: lotId }));
+        } catch (e) {
+            console.log(e);
+        }
+    }
+
+    async function invokeAndRefresh(cmd: string, args: any) {
+        try {
+            await invoke(cmd, args);
+        } catch (e) {
+            setLogArr([...logArr(), cmd + " failed: " + e]);
+        }
+        refresh();
+    }
+
+    async function addLot() {
+        if (newLot().name == "") return;
+        await invokeAndRefresh("inventory_add_lot", { lot: newLot() });
+        setNewLot(emptyLot());
+    }
+
+    async function removeLot(lot: any) {
+        if (!await ask(`Remove "${lot.name}" and its stock history?`, { title: "Inventory", type: "warning" })) return;
+        if (openId() == lot.id) {
+            setOpenId(0);
+        }
+        invokeAndRefresh("inventory_remove_lot", { id: lot.id });
+    }
+
+    function field(label: string, key: string, type: string) {
+        return (
+            <div class="flex flex-col">
+                <label class="text-xs">{label}</label>
+                <input class="input input-bordered input-sm rounded w-full" type={type}
+                    value={newLot()[key]}
+                    onChange={(e) => {
+                        let value = type == "number" ? Number(e.currentTarget.value) : e.currentTarget.value;
+                        setNewLot({ ...newLot(), [key]: value });
+                    }}
+                />
+            </div>
+        )
+    }
+
+    let unlisten_inventory_changed: UnlistenFn;
+
+    onMount(async () => {
+        // a roast was saved
+        unlisten_inventory_changed = await listen("inventory_changed", () => {
+            refresh();
+        });
+        refresh();
+    });
+
+    onCleanup(() => {
+        unlisten_inventory_changed();
+    });
+
+    return (
+        <div class="flex flex-col gap-1 text-sm">
+            <div class="grid grid-cols-2 gap-1">
+                {field("Name", "name", "text")}
+                {field("Origin", "origin", "text")}
+                {field("Process", "process", "text")}
+                {field("Supplier", "supplier", "text")}
+                {field("Purchase date", "purchase_date", "date")}
+                {field("Price", "price", "number")}
+                {field("Weight (g)", "initial_weight", "number")}
+                {field("Density (g/l)", "density", "number")}
+                {field("Moisture (%)", "moisture", "number")}
+                {field("Target DTR (%)", "target_dtr", "number")}
+                {field("Target drop BT", "target_drop_temperature", "number")}
+            </div>
+            <button class="btn btn-sm btn-accent rounded" onClick={addLot}>ADD LOT</button>
+
+            <For each={lots()}>
+                {(l) => (
+                    <div class="flex flex-col border rounded p-1">
+                        <div class="flex flex-row gap-1 items-center">
+                            <a class="grow font-bold cursor-pointer" title="click for stock history"
+                                onClick={() => openId() == l.lot.id ? setOpenId(0) : showMovements(l.lot.id)}
+                            >{l.lot.name}</a>
+                            <span class={l.stock <= 0 ? "text-error" : ""}>{l.stock.toFixed(0)}g</span>
+                            <button class="btn btn-xs btn-ghost rounded" onClick={() => removeLot(l.lot)}>✕</button>
+                        </div>
+                        <div class="flex flex-row gap-2 text-xs">
+                            <span>{l.lot.origin}</span>
+                            <span>{l.lot.process}</span>
+                            <span>{l.lot.supplier}</span>
+                            <span>{l.lot.purchase_date}</span>
+                        </div>
+                        <Show when={openId() == l.lot.id}>
+                            <input class="input input-bordered input-xs rounded" placeholder="adjust stock, e.g. -50 for 50g thrown away"
+                                type="number"
+                                onKeyDown={(e: KeyboardEvent) => {
+                                    if (e.key === "Enter") {
+                                        (e.target as HTMLInputElement).blur();
+                                    }
+                                }}
+                                onChange={(e) => {
+                                    let delta = Number(e.currentTarget.value);
+                                    e.currentTarget.value = "";
+                                    if (delta == 0 || isNaN(delta)) return;
+                                    invokeAndRefresh("inventory_adjust", { lotId: l.lot.id, delta: delta, reason: "adjustment" });
+                                }}
+                            />
+                            <For each={movements()}>
+                                {(m) => (
+                                    <div class="flex flex-row gap-2 text-xs">
+                                        <span>{new Date(m.timestamp).toLocaleDateString()}</span>
+                                        <span>{m.delta > 0 ? "+" : ""}{m.delta.toFixed(1)}g</span>
+                                        <span class="truncate" title={m.roast_path ?? ""}>
+                                            {m.roast_path != null ? m.roast_path.replace(/^.*[\\/]/, '') : m.reason}
+                                        </span>
+                                    </div>
+                                )}
+                            </For>
+                        </Show>
+                    </div>
+                )}
+            </For>
+        </div>
+    )
+}
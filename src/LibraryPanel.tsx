@@ -0,0 +1,185 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+import { For, Show, createSignal, onCleanup, onMount } from "solid-js";
+import { UnlistenFn, listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
+import { ask, open } from "@tauri-apps/api/dialog";
+import { appStateSig } from "./AppState";
+import { openRoastFile } from "./fileUtil";
+
+function formatTime(seconds: number) {
+    let s = Math.round(seconds);
+    return Math.floor(s / 60) + ":" + (s % 60).toString().padStart(2, '0');
+}
+
+export default function LibraryPanel() {
+
+    const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
+
+    const [entries, setEntries] = createSignal(new Array<any>());
+    const [text, setText] = createSignal("");
+    const [sort, setSort] = createSignal("date");
+    const [descending, setDescending] = createSignal(true);
+    const [editingId, setEditingId] = createSignal(0);
+
+    async function refresh() {
+        try {
+            setEntries(await invoke("library_list", {
+                filter: {
+                    text: text() != "" ? text() : undefined,
+                    sort: sort(),
+                    descending: descending(),
+                }
+            }));
+        } catch (e) {
+            setLogArr([...logArr(), "library list failed: " + e]);
+        }
+    }
+
+    async function importFolder() {
+        let dir = await open({ directory: true }) as string;
+        if (!dir) return;
+
+        try {
+            let report: any = await invoke("library_import_folder", { dir: dir });
+            setLogArr([
+                ...logArr(),
+                "library imported " + report.imported + " roasts, skipped " + report.skipped.length,
+                ...report.skipped.map((s: string) => "skipped " + s),
+            ]);
+        } catch (e) {
+            setLogArr([...logArr(), "library import failed: " + e]);
+        }
+        refresh();
+    }
+
+    async function deleteEntry(entry: any) {
+        if (!await ask(`Remove "${entry.title}" from the library?`, { title: "Library", type: "warning" })) return;
+        let deleteFile = await ask("Delete the roast file as well?", { title: "Library", type: "warning" });
+
+        try {
+            await invoke("library_delete", { id: entry.id, deleteFile: deleteFile });
+        } catch (e) {
+            setLogArr([...logArr(), "library delete failed: " + e]);
+        }
+        refresh();
+    }
+
+    async function invokeAndRefresh(cmd: string, args: any) {
+        try {
+            await invoke(cmd, args);
+        } catch (e) {
+            setLogArr([...logArr(), cmd + " failed: " + e]);
+        }
+        refresh();
+    }
+
+    let unlisten_library_changed: UnlistenFn;
+
+    onMount(async () => {
+        // a roast was saved
+        unlisten_library_changed = await listen("library_changed", () => {
+            refresh();
+        });
+        refresh();
+    });
+
+    onCleanup(() => {
+        unlisten_library_changed();
+    });
+
+    return (
+        <div class="flex flex-col gap-1 text-sm">
+            <div class="flex flex-row gap-1">
+                <input class="basis-3/5 input input-bordered input-sm rounded" placeholder="search"
+                    value={text()}
+                    onInput={(e) => {
+                        setText(e.currentTarget.value);
+                        refresh();
+                    }}
+                />
+                <select class="basis-1/5 select select-bordered select-sm rounded"
+                    onChange={(e) => {
+                        setSort(e.currentTarget.value);
+                        refresh();
+                    }}>
+                    <option value="date">date</option>
+                    <option value="title">title</option>
+                    <option value="country">country</option>
+                    <option value="weight_green">weight</option>
+                    <option value="development_time">dev time</option>
+                    <option value="drop_temperature">drop temp</option>
+                    <option value="development_percent">dev %</option>
+                    <option value="auc">AUC</option>
+                </select>
+                <button class="basis-1/5 btn btn-sm rounded"
+                    onClick={() => {
+                        setDescending(!descending());
+                        refresh();
+                    }}
+                >{descending() ? "DESC" : "ASC"}</button>
+            </div>
+
+            <button class="btn btn-sm btn-accent rounded" onClick={importFolder}>IMPORT FOLDER</button>
+
+            <For each={entries()}>
+                {(entry) => (
+                    <div class="flex flex-col border rounded p-1">
+                        <div class="flex flex-row gap-1 items-center">
+                            <Show when={editingId() == entry.id}
+                                fallback={
+                                    <a class="grow font-bold cursor-pointer" title="double click to rename"
+                                        onClick={() => openRoastFile(entry.path)}
+                                        onDblClick={() => setEditingId(entry.id)}
+                                    >{entry.title != "" ? entry.title : "(untitled)"}</a>
+                                }>
+                                <input class="grow input input-bordered input-xs rounded" value={entry.title}
+                                    onKeyDown={(e: KeyboardEvent) => {
+                                        if (e.key === "Enter") {
+                                            (e.target as HTMLInputElement).blur();
+                                        }
+                                    }}
+                                    onChange={(e) => {
+                                        setEditingId(0);
+                                        invokeAndRefresh("library_rename", { id: entry.id, title: e.currentTarget.value });
+                                    }}
+                                />
+                            </Show>
+                            <button class="btn btn-xs btn-ghost rounded" onClick={() => deleteEntry(entry)}>✕</button>
+                        </div>
+                        <div class="flex flex-row gap-2 text-xs">
+                            <span>{new Date(entry.date).toLocaleDateString()}</span>
+                            <span>{entry.country}</span>
+                            <span>{entry.process}</span>
+                            <span>{entry.weight_green}g</span>
+                            <Show when={entry.development_time != null}>
+                                <span>dev {formatTime(entry.development_time)}</span>
+                            </Show>
+                            <Show when={entry.drop_temperature != null}>
+                                <span>drop {entry.drop_temperature.toFixed(1)}</span>
+                            </Show>
+                            <Show when={entry.phases?.dev != null}>
+                                <span>dev {entry.phases.dev.percent.toFixed(1)}%</span>
+                            </Show>
+                            <Show when={entry.phases != null}>
+                                <span>AUC {entry.phases.total.auc.toFixed(0)}</span>
+                            </Show>
+                        </div>
+                        <input class="input input-bordered input-xs rounded" placeholder="tags, comma separated"
+                            value={entry.tags.join(", ")}
+                            onKeyDown={(e: KeyboardEvent) => {
+                                if (e.key === "Enter") {
+                                    (e.target as HTMLInputElement).blur();
+                                }
+                            }}
+                            onChange={(e) => {
+                                invokeAndRefresh("library_tag", { id: entry.id, tags: e.currentTarget.value.split(",") });
+                            }}
+                        />
+                    </div>
+                )}
+            </For>
+        </div>
+    )
+}
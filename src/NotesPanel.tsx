@@ -1,8 +1,10 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-import { For, Show, createEffect, createSignal } from "solid-js";
+import { For, Show, createEffect, createSignal, onCleanup, onMount } from "solid-js";
+import { UnlistenFn, listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
 import { GET, SET, appStateSig } from "./AppState";
-import { loadGreenBeanInfo } from "./fileUtil";
+import { applyGreenLot, loadGreenBeanInfo } from "./fileUtil";
 
 function agtronLevel(agtron: number) {
     if (agtron < 20) return "Over Developed";
@@ -30,6 +32,8 @@ export default function NotesPanel() {
     const [moistureRoasted, setMoistureRoasted] = appState().moistureRoastedSig;
     const [colorWhole, setColorWhole] = appState().colorWholeSig;
     const [colorGround, setColorGround] = appState().colorGroundSig;
+    const [greenLot, _setGreenLot] = appState().greenLotSig;
+    const [lots, setLots] = createSignal(new Array<any>());
 
     const [recentTitles, setRecentTitles] = createSignal(JSON.parse(localStorage.getItem("recentTitles") || "[]") as Array<string>);
     const [recentCountries, setRecentCountries] = createSignal(JSON.parse(localStorage.getItem("recentCountries") || "[]") as Array<string>);
@@ -58,6 +62,27 @@ export default function NotesPanel() {
 
     });
 
+    async function refreshLots() {
+        try {
+            setLots(await invoke("inventory_list"));
+        } catch (e) {
+            console.log(e);
+        }
+    }
+
+    let unlisten_inventory_changed: UnlistenFn;
+
+    onMount(async () => {
+        unlisten_inventory_changed = await listen("inventory_changed", () => {
+            refreshLots();
+        });
+        refreshLots();
+    });
+
+    onCleanup(() => {
+        unlisten_inventory_changed();
+    });
+
     return (
 
         <div class="flex flex-col gap-1 text-sm">
@@ -106,6 +131,20 @@ export default function NotesPanel() {
                     onClick={() => loadGreenBeanInfo()}
                 >LOAD</button>
             </div>
+            <div class="flex flex-col">
+                <label class="">Green Lot</label>
+                <select class="select select-bordered select-sm rounded w-full"
+                    onChange={(e) => applyGreenLot(Number(e.currentTarget.value))}>
+                    <option value="0" selected={greenLot() == 0}>(none)</option>
+                    <For each={lots()}>
+                        {(l) => (
+                            <option value={l.lot.id} selected={greenLot() == l.lot.id}>
+                                {l.lot.name} ({l.stock.toFixed(0)}g)
+                            </option>
+                        )}
+                    </For>
+                </select>
+            </div>
             <div class="flex flex-row gap-1">
                 <div class="basis-1/2 flex flex-col " >
                     <label class="">Country</label>
@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+import { For, Show, createSignal, onCleanup, onMount } from "solid-js";
+import { UnlistenFn, listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
+import { open } from "@tauri-apps/api/dialog";
+import { GET, appStateSig } from "./AppState";
+import { loadPlannedBatch } from "./fileUtil";
+
+function emptyBatch() {
+    return {
+        title: "",
+        green_lot: null,
+        target_weight: 0.0,
+        profile: null,
+    };
+}
+
+export default function QueuePanel() {
+
+    const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
+
+    const [planned, setPlanned] = createSignal(new Array<any>());
+    const [lots, setLots] = createSignal(new Array<any>());
+    const [newBatch, setNewBatch] = createSignal(emptyBatch() as any);
+
+    async function refresh() {
+        try {
+            setPlanned(await invoke("batch_queue_list"));
+            setLots(await invoke("inventory_list"));
+        } catch (e) {
+            console.log(e);
+        }
+    }
+
+    async function invokeAndRefresh(cmd: string, args: any) {
+        try {
+            await invoke(cmd, args);
+        } catch (e) {
+            setLogArr([...logArr(), cmd + " failed: " + e]);
+        }
+        refresh();
+    }
+
+    async function browseProfile() {
+        let filepath = await open({
+            filters: [{
+                name: 'Profile',
+                extensions: ['json', 'alog', 'csv']
+            }]
+        }) as string;
+        if (!filepath) return;
+
+        setNewBatch({ ...newBatch(), profile: filepath });
+    }
+
+    async function addBatch() {
+        await invokeAndRefresh("batch_queue_add", { planned: newBatch() });
+        setNewBatch(emptyBatch());
+    }
+
+    function lotName(id: number) {
+        return lots().find((l) => l.lot.id == id)?.lot.name ?? "";
+    }
+
+    let unlisten_batch_queue_changed: UnlistenFn;
+
+    onMount(async () => {
+        // a planned batch was dropped
+        unlisten_batch_queue_changed = await listen("batch_queue_changed", () => {
+            refresh();
+        });
+        refresh();
+    });
+
+    onCleanup(() => {
+        unlisten_batch_queue_changed();
+    });
+
+    return (
+        <div class="flex flex-col gap-1 text-sm">
+            <Show when={appState().batchSig[GET]() != ""}>
+                <div class="font-bold">Batch {appState().batchSig[GET]()}</div>
+            </Show>
+
+            <div class="flex flex-col">
+                <label class="text-xs">Title</label>
+                <input class="input input-bordered input-sm rounded w-full"
+                    value={newBatch().title}
+                    onChange={(e) => setNewBatch({ ...newBatch(), title: e.currentTarget.value })}
+                />
+            </div>
+            <div class="flex flex-row gap-1">
+                <div class="basis-1/2 flex flex-col">
+                    <label class="text-xs">Green Lot</label>
+                    <select class="select select-bordered select-sm rounded w-full"
+                        onChange={(e) => {
+                            let id = Number(e.currentTarget.value);
+                            setNewBatch({ ...newBatch(), green_lot: id != 0 ? id : null });
+                        }}>
+                        <option value="0" selected={newBatch().green_lot == null}>(none)</option>
+                        <For each={lots()}>
+                            {(l) => (
+                                <option value={l.lot.id} selected={newBatch().green_lot == l.lot.id}>{l.lot.name}</option>
+                            )}
+                        </For>
+                    </select>
+                </div>
+                <div class="basis-1/2 flex flex-col">
+                    <label class="text-xs">Target weight (g)</label>
+                    <input class="input input-bordered input-sm rounded w-full" type="number"
+                        value={newBatch().target_weight}
+                        onChange={(e) => setNewBatch({ ...newBatch(), target_weight: Number(e.currentTarget.value) })}
+                    />
+                </div>
+            </div>
+            <div class="flex flex-row gap-1 items-center">
+                <span class="grow truncate text-xs" title={newBatch().profile ?? ""}>
+                    {newBatch().profile != null ? newBatch().profile.replace(/^.*[\\/]/, '') : "no reference profile"}
+                </span>
+                <button class="btn btn-sm rounded" onClick={browseProfile}>PROFILE</button>
+            </div>
+            <button class="btn btn-sm btn-accent rounded" onClick={addBatch}>ADD BATCH</button>
+
+            <For each={planned()}>
+                {(p, i) => (
+                    <div class="flex flex-row gap-1 items-center border rounded p-1"
+                        classList={{ "border-accent": appState().plannedBatchSig[GET]() == p.id }}>
+                        <a class="grow flex flex-col cursor-pointer" title="click to load"
+                            onClick={() => loadPlannedBatch(p)}>
+                            <span class="font-bold">{i() + 1}. {p.title != "" ? p.title : lotName(p.green_lot)}</span>
+                            <span class="text-xs">
+                                {p.target_weight}g {p.profile != null ? p.profile.replace(/^.*[\\/]/, '') : ""}
+                            </span>
+                        </a>
+                        <button class="btn btn-xs btn-ghost rounded" disabled={i() == 0}
+                            onClick={() => invokeAndRefresh("batch_queue_move", { id: p.id, index: i() - 1 })}>▲</button>
+                        <button class="btn btn-xs btn-ghost rounded" disabled={i() == planned().length - 1}
+                            onClick={() => invokeAndRefresh("batch_queue_move", { id: p.id, index: i() + 1 })}>▼</button>
+                        <button class="btn btn-xs btn-ghost rounded"
+                            onClick={() => invokeAndRefresh("batch_queue_remove", { id: p.id })}>✕</button>
+                    </div>
+                )}
+            </For>
+        </div>
+    )
+}
@@ -1,14 +1,15 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+import { invoke } from "@tauri-apps/api/tauri";
 import { For, } from "solid-js";
-import { SET, Point, appStateSig, ManualChannel, AppStatus, GET } from "./AppState";
+import { SET, appStateSig, ManualChannel, AppStatus, GET } from "./AppState";
 
 export default function RangeInput(props: { channel_id: string }) {
 
     const [appState, _setAppState] = appStateSig;
     const [status, _setStatus] = appState().statusSig;
-    const [timer, _setTimer] = appState().timerSig;
     const [manualChannelArr, _setManualChannelArr] = appState().manualChannelArrSig;
+    const [logArr, setLogArr] = appState().logArrSig;
 
     let mc = manualChannelArr().find(mc => mc.id == props.channel_id) as ManualChannel;
 
@@ -22,14 +23,22 @@ export default function RangeInput(props: { channel_id: string }) {
     }
     pips.push(max);
 
+    // send value to the roaster, if the channel is bound to an output in config
+    async function writeOutput(value: number) {
+        try {
+            await invoke("set_output", { channelId: mc.id, value: value });
+        } catch (e) {
+            setLogArr([...logArr(), e as string]);
+        }
+    }
+
     function handleInput(event: InputEvent) {
 
         let value = (event.target as HTMLInputElement).value;
 
+        // history is recorded by the backend session
         mc.currentDataSig[SET](Number(value));
-        mc.setDataArr(
-            [...mc.dataArr(), new Point(timer(), Number(value))]
-        );
+        writeOutput(Number(value));
 
     }
 
@@ -37,10 +46,9 @@ export default function RangeInput(props: { channel_id: string }) {
 
         let value = pip;
 
+        // history is recorded by the backend session
         mc.currentDataSig[SET](Number(value));
-        mc.setDataArr(
-            [...mc.dataArr(), new Point(timer(), Number(value))]
-        );
+        writeOutput(Number(value));
 
     }
 
@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+import { invoke } from "@tauri-apps/api/tauri";
+import { GET, BT, Point, appStateSig } from "./AppState";
+
+// follow the loaded ghost, replaying its manual channels
+export async function startAutopilot() {
+    const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
+    const ghost = appState().ghostSig[GET]();
+
+    let bt = ghost.channelArr.find((c) => c.id == BT);
+    if (bt == undefined || bt.dataArr.length == 0) {
+        setLogArr([...logArr(), "autopilot needs a ghost, load one first"]);
+        return;
+    }
+
+    // backend times are seconds from CHARGE, like the chart
+    let align = (arr: Array<Point>) => arr.map((p) => ({ timestamp: p.timestamp + ghost.timeDelta, value: p.value }));
+
+    let profile = {
+        bt: align(bt.dataArr),
+        ror: align(bt.rorConvolveArr),
+        manual_channels: ghost.manualChannelArr.map((mc) => ({ channel_id: mc.id, data: align(mc.dataArr) })),
+    };
+
+    try {
+        await invoke("autopilot_start", {
+            profile: profile,
+            time: appState().timerSig[GET]() + appState().timeDeltaSig[GET](),
+            replay: ghost.manualChannelArr.map((mc) => mc.id),
+        });
+    } catch (e) {
+        setLogArr([...logArr(), e as string]);
+    }
+}
+
+export async function pauseAutopilot() {
+    await invokeAutopilot("autopilot_pause");
+}
+
+export async function resumeAutopilot() {
+    await invokeAutopilot("autopilot_resume");
+}
+
+export async function handOverAutopilot() {
+    await invokeAutopilot("autopilot_hand_over");
+}
+
+async function invokeAutopilot(command: string) {
+    const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
+
+    try {
+        await invoke(command);
+    } catch (e) {
+        setLogArr([...logArr(), e as string]);
+    }
+}
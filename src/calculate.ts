@@ -1,276 +1,49 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-import { GET, SET, Point, RoastEvent, RoastEvents, RoastEventId, Phase, appStateSig, AppStatus, Channel, BT } from "./AppState";
-import { mean, standardDeviation } from "simple-statistics";
-// import { median, medianAbsoluteDeviation } from "simple-statistics";
-import { info, warn } from "tauri-plugin-log-api";
+import { GET, SET, RoastEvents, Phase, appStateSig, Channel } from "./AppState";
+import { invoke } from "@tauri-apps/api/tauri";
+import { warn } from "tauri-plugin-log-api";
 
 const [appState, _setAppState] = appStateSig;
 const [timer, _setTimer] = appState().timerSig;
-const [channelArr, _setChannelArr] = appState().channelArrSig;
-const [roastEvents, setRoastEvents] = appState().roastEventsSig;
+
+// provisional points at the end of each smoothed RoR
+let smoothedTails: { [id: string]: number } = {};
 
 export function timestamp_format(timestamp: number) {
     return Math.floor(timestamp / 60).toString().padStart(2, '0') + ":" + (timestamp % 60).toString().padStart(2, '0');
 }
 
-export function calculateRor(channel: Channel, roastEvents: RoastEvents) {
-
-    let data: Array<Point> = channel.dataArr();
-
-    let ror_array = Array<Point>();
-
-    for (let i = 0; i < data.length; i++) {
-
-        let window_size = 5
-        let window = data.slice(Math.max(0, i - window_size + 1), i + 1);
-
-        let delta = window[window.length - 1].value - window[0].value;
-        let time_elapsed_sec = window[window.length - 1].timestamp - window[0].timestamp;
-
-        let ror = (Math.floor(delta / time_elapsed_sec * 60 * 10)) / 10 || 0
-
-        ror_array.push(
-            new Point(data[i].timestamp, ror)
-        );
+// the RoR of a channel from the backend. new points are appended, the
+// provisional end of the smoothed RoR is replaced every time
+export function applyRor(channel: Channel, r: any) {
+    if (r.replace) {
+        channel.rorArrSig[SET](r.ror);
+        channel.rorOutlierArrSig[SET](r.outliers);
+        channel.rorFilteredArrSig[SET](r.filtered);
+        smoothedTails[channel.id] = 0;
+    } else {
+        channel.rorArrSig[SET]([...channel.rorArrSig[GET](), ...r.ror]);
+        channel.rorOutlierArrSig[SET]([...channel.rorOutlierArrSig[GET](), ...r.outliers]);
+        channel.rorFilteredArrSig[SET]([...channel.rorFilteredArrSig[GET](), ...r.filtered]);
+    }
+
+    let smoothed = r.replace ? [] : channel.rorConvolveArrSig[GET]();
+    smoothed = smoothed.slice(0, Math.max(0, smoothed.length - (smoothedTails[channel.id] ?? 0)));
+    smoothed = [...smoothed, ...r.smoothed, ...r.smoothed_tail];
+    smoothedTails[channel.id] = r.smoothed_tail.length;
+    channel.rorConvolveArrSig[SET](smoothed);
+
+    if (smoothed.at(-1) != undefined) {
+        channel.lastRorConvolveTimestampSig[SET](smoothed.at(-1)!.timestamp);
+        channel.lastRorConvolveValueSig[SET](smoothed.at(-1)!.value);
     }
-
-    // remove ror data points after drop
-    let drop = roastEvents.DROP;
-    if (drop) {
-        ror_array = ror_array.filter((p) =>
-            (p.timestamp <= (drop as RoastEvent).timestamp)
-        );
-    }
-
-    channel.rorArrSig[SET](ror_array);
 }
 
-export function findRorOutlier(channel: Channel) {
-
-    let ror: Array<Point> = channel.rorArrSig[GET]();
-
-    let ror_outlier = new Array<Point>();
-    let ror_filtered = new Array<Point>();
-
-    for (let i = 0; i < ror.length; i++) {
-        if (i == 0) {
-            continue;
-        }
-
-        let window_size = 5
-        let window = ror.slice(Math.max(0, i - window_size), i).map(r => r.value); // window doesn't include i
-
-        let ma = mean(window); // moving average
-        let sd = standardDeviation(window); // standard deviation
-        let zScore = Math.abs((ror[i].value - ma) / sd);
-
-        // https://eurekastatistics.com/using-the-median-absolute-deviation-to-find-outliers/
-        // The MAD=0 Problem
-        // If more than 50% of your data have identical values, your MAD will equal zero. 
-        // All points in your dataset except those that equal the median will then be flagged as outliers, 
-        // regardless of the level at which you've set your outlier cutoff. 
-        // (By constrast, if you use the standard-deviations-from-mean approach to finding outliers, 
-        // Chebyshev's inequality puts a hard limit on the percentage of points that may be flagged as outliers.) 
-        // So at the very least check that you don't have too many identical data points before using the MAD to flag outliers.
-
-        // let m = median(window);
-        // let mad = medianAbsoluteDeviation(window);
-        // let modifiedZScore = Math.abs(0.6745 * (ror[i].value - m) / mad);
-        // console.log(m);
-        // console.log(mad);
-        // console.log("modifiedZScore: " + modifiedZScore);
-
-        if (zScore > 3) {
-            ror_outlier.push(
-                channel.rorArrSig[GET]()[i]
-            )
-
-        } else {
-            ror_filtered.push(
-                channel.rorArrSig[GET]()[i]
-            )
-        }
-    }
-
-    let hann_window = [];
-    let window_len = 11;
-    let half_window_len = Math.floor((window_len - 1) / 2);
-
-    for (let i = 0; i < window_len; i++) {
-        hann_window.push(hann(i, window_len))
-    }
-
-    let sum = hann_window.reduce((partialSum, a) => partialSum + a, 0);
-
-    let filter = hann_window.map((m) => (m / sum));
-
-    // let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20];
-    let data = ror_filtered.map(p => p.value);
-    let dataLeft = data.slice(1, window_len).reverse();
-    let dataRight = data.slice(-window_len, -1).reverse();
-    let input = [...dataLeft, ...data, ...dataRight];
-    let conv = convValid(filter, input);
-    let result = conv.slice(half_window_len, -half_window_len);
-
-    let ror_convolve = new Array<Point>();
-
-    let lag = 0;
-    if (appState().statusSig[GET]() == AppStatus.RECORDING) {
-        lag = 3; // lag few samples, so that end of convolve line won't look like jumping up and down
-    }
-
-    for (let i = 0; i < ror_filtered.length - lag; i++) {
-        ror_convolve.push(new Point(
-            ror_filtered[i].timestamp,
-            result[i]
-        ));
-    }
-
-    channel.rorOutlierArrSig[SET](ror_outlier);
-    channel.rorFilteredArrSig[SET](ror_filtered);
-    channel.rorConvolveArrSig[SET](ror_convolve);
-
-    if (ror_convolve.at(-1) != undefined && ror_convolve.at(-1)!.value != undefined) {
-        channel.lastRorConvolveTimestampSig[SET](ror_convolve.at(-1)!.timestamp);
-        channel.lastRorConvolveValueSig[SET](ror_convolve.at(-1)!.value);
-        // console.log("lastRorConvolve");
-        // console.log(channel.lastRorConvolveTimestampSig[GET]());
-        // console.log(channel.lastRorConvolveValueSig[GET]());
-    }
-
-}
-
-
-// reference: artisan/src/artisanlib/main.py  BTbreak()
-// idea:
-// . average delta before i-2 is not negative
-// . average delta after i-2 is negative and twice as high (absolute) as the one before
-export function autoDetectChargeDrop() {
-    const bt = channelArr().find(c => c.id == BT) as Channel;
-
-    let data: Array<Point> = bt.dataArr();
-    let ror: Array<Point> = bt.rorArrSig[GET]();
-
-    let window_size = 5
-    if (ror.length >= window_size) {
-
-        let window = ror.slice(Math.max(0, ror.length - window_size), ror.length).map(r => r.value);
-
-        // window array: [    0][    1][     2][    3][    4]
-        //    ror array: [len-5][len-4][*len-3][len-2][len-1]
-        //                              ^^^^^^
-        //                              CHARGE
-
-        let dpre = (window[0] + window[1]) / 2.0;
-        let dpost = (window[3] + window[4]) / 2.0;
-        if (window[0] > 0.0 && window[1] > 0.0
-            && window[3] < 0.0 && window[4] < 0.0
-            && Math.abs(dpost) > Math.abs(dpre) * 2) {
-            let target_index = ror.length - 3;
-
-            if (roastEvents().CHARGE == undefined) {
-                info("auto detected charge at ror index: " + (target_index));
-
-                appState().timeDeltaSig[SET](- data[target_index].timestamp);
-
-                setRoastEvents({
-                    ...roastEvents(),
-                    CHARGE: new RoastEvent(
-                        RoastEventId.CHARGE,
-                        data[target_index].timestamp,
-                        data[target_index].value)
-                });
-
-            } else if (roastEvents().CHARGE != undefined && roastEvents().TP != undefined && roastEvents().DROP == undefined) {
-                info("auto detected drop at ror index: " + (target_index));
-
-                setRoastEvents({
-                    ...roastEvents(),
-                    DROP: new RoastEvent(
-                        RoastEventId.DROP,
-                        data[target_index].timestamp,
-                        data[target_index].value
-                    )
-                });
-
-            }
-        }
-    }
-}
-
-// find lowest point in BT
-export function findTurningPoint() {
-    const bt = channelArr().find(c => c.id == BT) as Channel;
-
-    // only detect turning point after charge
-    if (roastEvents().CHARGE == undefined || roastEvents().TP != undefined) {
-        return
-    }
-
-    let tp = 1000;
-    let high_temp = 0;
-
-    // last 2 BT value greater than updated min tp
-    let data: Array<Point> = bt.dataArr();
-
-    let target_index = 0;
-    let tp_found = false;
-    let temp_drop = 0;
-    for (let i = 0; i < data.length; i++) {
-
-        tp = Math.min(data[i].value, tp);
-        high_temp = Math.max(data[i].value, high_temp);
-
-        temp_drop = high_temp - tp;
-
-        // last 2 BT reading > tp
-        if (data[data.length - 1].value > tp && data[data.length - 2].value > tp && temp_drop > 50) {
-            target_index = data.length - 3;
-            tp_found = true;
-            break;
-        }
-    }
-
-    if (tp_found) {
-
-        setRoastEvents({
-            ...roastEvents(),
-            TP: new RoastEvent(
-                RoastEventId.TP,
-                data[target_index].timestamp,
-                data[target_index].value
-            )
-        });
-
-    }
-}
-
-export function findDryEnd() {
-    const bt = channelArr().find(c => c.id == BT) as Channel;
-
-    // only detect dry end after turning point
-    if (roastEvents().TP == undefined || roastEvents().DRY_END != undefined) {
-        return
-    }
-
-    let data: Array<Point> = bt.dataArr();
-
-    let dry_end = 150;
-
-    // last 2 BT reading > tp
-    if (data[data.length - 1].value > dry_end && data[data.length - 2].value > dry_end) {
-        let target_index = data.length - 2;
-
-        setRoastEvents({
-            ...roastEvents(),
-            DRY_END: new RoastEvent(
-                RoastEventId.DRY_END,
-                data[target_index].timestamp,
-                data[target_index].value
-            )
-        });
-    }
+// RoR of a whole loaded roast, computed by the backend
+export async function computeRor(channel: Channel, roastEvents: RoastEvents) {
+    let r = await invoke("compute_ror", { channelId: channel.id, data: channel.dataArr(), drop: roastEvents.DROP?.timestamp });
+    applyRor(channel, r);
 }
 
 export function calculatePhases(t: number, lastTemp: number, roastEvents: RoastEvents) {
@@ -398,42 +171,3 @@ export function calculatePhases(t: number, lastTemp: number, roastEvents: RoastE
     }
     return result;
 }
-
-function hann(i: number, N: number) {
-    return 0.5 * (1 - Math.cos(6.283185307179586 * i / (N - 1)))
-}
-
-// reference: https://stackoverflow.com/questions/24518989/how-to-perform-1-dimensional-valid-convolution
-function convValid(f: Array<number>, g: Array<number>) {
-    const nf = f.length;
-    const ng = g.length;
-    const minV = (nf < ng) ? f : g;
-    const maxV = (nf < ng) ? g : f;
-    const n = Math.max(nf, ng) - Math.min(nf, ng) + 1;
-    const out = new Array(n).fill(0);
-
-    for (let i = 0; i < n; ++i) {
-        for (let j = minV.length - 1, k = i; j >= 0; --j) {
-            out[i] += minV[j] * maxV[k];
-            ++k;
-        }
-    }
-
-    return out;
-}
-
-export function detectAlarm() {
-    const bt = channelArr().find(c => c.id == BT) as Channel;
-
-    if (roastEvents().TP != undefined) {
-        let alarms = appState().alarmsArrSig[GET]().filter((a: any) => a.triggeredSig[GET]() == false);
-
-        if (alarms[0] != undefined && bt.currentDataSig[GET]() >= alarms[0].temperature) {
-
-            window.speechSynthesis.speak(new SpeechSynthesisUtterance(alarms[0]?.temperature.toString()));
-            alarms[0].triggeredSig[SET](true);
-            console.log(alarms);
-
-        }
-    }
-}
\ No newline at end of file
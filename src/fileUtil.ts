@@ -1,12 +1,24 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+import { invoke } from "@tauri-apps/api/tauri";
 import { open, save } from '@tauri-apps/api/dialog';
-import { readTextFile, writeTextFile } from "@tauri-apps/api/fs";
-import { GET, SET, Point, appStateSig, Channel, Ghost, GhostChannel, BT, init_flavorWheel } from "./AppState";
-import { calculatePhases, calculateRor, findRorOutlier } from './calculate';
-import { createSignal } from 'solid-js';
+import { GET, SET, Point, appStateSig, Ghost, GhostChannel, init_flavorWheel } from "./AppState";
+import { calculatePhases, computeRor } from './calculate';
 
 export async function openFile() {
+    let filepath = await open({
+        filters: [{
+            name: 'Profile',
+            extensions: ['json', 'alog', 'csv']
+        }]
+    }) as string;
+    if (!filepath) return;
+
+    await openRoastFile(filepath);
+}
+
+// a roast file into the webview, from the Open dialog or the library
+export async function openRoastFile(filepath: string) {
     const [appState, _setAppState] = appStateSig;
     const [channelArr, _setChannelArr] = appState().channelArrSig;
     const [roastEvents, _setRoastEvents] = appState().roastEventsSig;
@@ -14,18 +26,10 @@ export async function openFile() {
     const [_dryingPhase, setDryingPhase] = appState().dryingPhaseSig;
     const [_maillardPhase, setMaillardPhase] = appState().maillardPhaseSig;
     const [_developPhase, setDevelopPhase] = appState().developPhaseSig;
-    const bt = channelArr().find(c => c.id == BT) as Channel;
 
     try {
-        let filepath = await open({
-            filters: [{
-                name: 'Profile',
-                extensions: ['json']
-            }]
-        }) as string;
-        let content = await readTextFile(filepath);
-
-        let loadObject = JSON.parse(content);
+        // migrated to the current format, or imported from artisan, and validated by the backend
+        let loadObject: any = await invoke("load_roast_file", { path: filepath });
 
         loadObject.channelArr.forEach((c: any) => {
             let channel = appState().channelArrSig[GET]().find((channel) => channel.id == c.id);
@@ -55,8 +59,9 @@ export async function openFile() {
             appState().timeDeltaSig[SET](- chargeEvent.timestamp);
         }
 
-        calculateRor(bt, roastEvents());
-        findRorOutlier(bt);
+        for (const c of channelArr()) {
+            await computeRor(c, roastEvents());
+        }
 
         // use BT last Point as timer and currentData
         let btLoaded = loadObject.channelArr.find((c: any) => c.id == "BT");
@@ -69,6 +74,8 @@ export async function openFile() {
             setDevelopPhase(result.dev);
         }
 
+        appState().roastEpochSig[SET](loadObject.roastEpoch ?? 0);
+
         appState().titleSig[SET](loadObject.title);
         appState().countrySig[SET](loadObject.country);
         appState().processSig[SET](loadObject.process);
@@ -84,6 +91,11 @@ export async function openFile() {
         appState().moistureRoastedSig[SET](loadObject.moistureRoasted);
         appState().colorWholeSig[SET](loadObject.colorWhole);
         appState().colorGroundSig[SET](loadObject.colorGround);
+        appState().greenLotSig[SET](loadObject.greenLot ?? 0);
+        appState().batchSig[SET](loadObject.batch ?? "");
+        appState().dropTargetSig[SET](loadObject.dropTarget ?? null);
+        appState().phaseReportSig[SET](loadObject.phases ?? await invoke("compute_phases", { roast: loadObject }));
+        appState().autosavePathSig[SET]("");
 
         if (loadObject.flavorList != undefined) {
             appState().flavorListSig[SET](loadObject.flavorList);
@@ -108,6 +120,7 @@ export async function openFile() {
         setLogArr([...logArr(), "opened file: " + filepath.replace(/^.*[\\/]/, '')]);
     } catch (e) {
         console.log(e);
+        setLogArr([...logArr(), "failed to open file: " + e]);
     }
 }
 
@@ -120,9 +133,9 @@ export async function loadGreenBeanInfo() {
             extensions: ['json']
         }]
     }) as string;
-    let content = await readTextFile(filepath);
+    if (!filepath) return;
 
-    let loadObject = JSON.parse(content);
+    let loadObject: any = await invoke("load_roast_file", { path: filepath });
 
     appState().titleSig[SET](loadObject.title);
     appState().countrySig[SET](loadObject.country);
@@ -133,20 +146,26 @@ export async function loadGreenBeanInfo() {
 }
 
 export async function loadGhost() {
+    let filepath = await open({
+        filters: [{
+            name: 'Profile',
+            extensions: ['json', 'alog', 'csv']
+        }]
+    }) as string;
+    if (!filepath) return;
+
+    await loadGhostFile(filepath);
+}
+
+// a roast file as the ghost, from the Load Ghost dialog or the roast queue
+export async function loadGhostFile(filepath: string) {
     const [appState, _setAppState] = appStateSig;
     const [logArr, setLogArr] = appState().logArrSig;
     const [_ghost, setGhost] = appState().ghostSig;
 
     try {
-        let filepath = await open({
-            filters: [{
-                name: 'Profile',
-                extensions: ['json']
-            }]
-        }) as string;
-        let content = await readTextFile(filepath);
-
-        let loadObject = JSON.parse(content);
+        // migrated to the current format and validated by the backend
+        let loadObject: any = await invoke("load_roast_file", { path: filepath });
 
         // use BT last Point as timer and currentData
         let btLoaded = loadObject.channelArr.find((c: any) => c.id == "BT");
@@ -156,38 +175,19 @@ export async function loadGhost() {
         let channelArr = new Array<GhostChannel>;
         let roastEvents = loadObject.roastEvents;
 
-        loadObject.channelArr.forEach((c: any) => {
+        for (const c of loadObject.channelArr) {
             let channel = appState().channelArrSig[GET]().find((channel) => channel.id == c.id);
             if (channel) {
-
-                let tempChannel = new Channel(
-                    channel.id,            // id
-                    channel.label,         // label 
-                    channel.color,         // color
-                    channel.rorColor,      // ror_color
-                    createSignal(0), //currentDataSig
-                    createSignal(0), //currentRorSig
-                    [], //data_window
-                    createSignal(new Array<Point>()), // dataSig
-                    createSignal(new Array<Point>()), // rorSig
-                    createSignal(new Array<Point>()), // rorOutlierSig
-                    createSignal(new Array<Point>()), // rorFilteredSig
-                    createSignal(new Array<Point>()), // rorConvolveSig
-                    createSignal(0),
-                    createSignal(-100),
-                );
-                tempChannel.setDataArr(c.dataArr);
-                calculateRor(tempChannel, roastEvents);
-                findRorOutlier(tempChannel);
+                let r: any = await invoke("compute_ror", { channelId: c.id, data: c.dataArr, drop: roastEvents?.DROP?.timestamp });
 
                 channelArr.push(new GhostChannel(
                     channel.id,
                     channel.color,
                     channel.rorColor,
                     c.dataArr,
-                    tempChannel.rorConvolveArrSig[GET]()));
+                    r.smoothed));
             }
-        });
+        }
 
         if (roastEvents != undefined && roastEvents.CHARGE != undefined) {
             timeDelta = - roastEvents.CHARGE.timestamp;
@@ -214,6 +214,45 @@ export async function loadGhost() {
     }
 }
 
+// the green bean info comes from the lot, the stock is taken when the roast is saved
+export async function applyGreenLot(id: number) {
+    const [appState, _setAppState] = appStateSig;
+
+    appState().greenLotSig[SET](id);
+    if (id == 0) return;
+
+    let lots: Array<any> = await invoke("inventory_list");
+    let lot = lots.find((l) => l.lot.id == id)?.lot;
+    if (lot == undefined) return;
+
+    appState().titleSig[SET](lot.name);
+    appState().countrySig[SET](lot.origin);
+    appState().processSig[SET](lot.process);
+    appState().densityGreenSig[SET](lot.density);
+    appState().moistureGreenSig[SET](lot.moisture);
+}
+
+// pre-load a batch of the roast queue, before its START
+export async function loadPlannedBatch(planned: any) {
+    const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
+
+    if (planned.green_lot != null) {
+        await applyGreenLot(planned.green_lot);
+    }
+    if (planned.title != "") {
+        appState().titleSig[SET](planned.title);
+    }
+    appState().weightGreenSig[SET](planned.target_weight);
+    if (planned.profile != null) {
+        await loadGhostFile(planned.profile);
+    }
+    // START takes this batch off the queue at DROP
+    appState().plannedBatchSig[SET](planned.id);
+
+    setLogArr([...logArr(), "next batch: " + (planned.title != "" ? planned.title : planned.id)]);
+}
+
 function getCurrentFormattedTime() {
     const now = new Date();
 
@@ -227,12 +266,86 @@ function getCurrentFormattedTime() {
     return formattedTime;
 }
 
+// what the user writes about the roast, also sent to the backend for autosave
+export function roastNotes() {
+    const [appState, _setAppState] = appStateSig;
+
+    return {
+        title: appState().titleSig[GET](),
+        country: appState().countrySig[GET](),
+        process: appState().processSig[GET](),
+        notes: appState().notesSig[GET](),
+        weightGreen: appState().weightGreenSig[GET](),
+        weightRoasted: appState().weightRoastedSig[GET](),
+        volumeGreen: appState().volumeGreenSig[GET](),
+        volumeRoasted: appState().volumeRoastedSig[GET](),
+        densityGreen: appState().densityGreenSig[GET](),
+        densityRoasted: appState().densityRoastedSig[GET](),
+        moistureGreen: appState().moistureGreenSig[GET](),
+        moistureRoasted: appState().moistureRoastedSig[GET](),
+        colorWhole: appState().colorWholeSig[GET](),
+        colorGround: appState().colorGroundSig[GET](),
+        flavorList: appState().flavorListSig[GET](),
+        greenLot: appState().greenLotSig[GET]() || undefined,
+    };
+}
+
+// the roast in the webview, in the layout of the roast file
+async function roastObject() {
+    const [appState, _setAppState] = appStateSig;
+
+    let saveObject = {
+        formatVersion: 1,
+        channelArr: new Array<any>(),
+        manualChannelArr: new Array<any>(),
+        roastEvents: appState().roastEventsSig[GET](),
+        ...roastNotes(),
+        outputLog: await invoke("get_output_log"),
+        roastEpoch: appState().roastEpochSig[GET]() || undefined,
+        batch: appState().batchSig[GET]() || undefined,
+        dropTarget: appState().dropTargetSig[GET]() ?? undefined,
+    };
+
+    appState().channelArrSig[GET]().forEach((c) => {
+
+        let saveDataArr = new Array<Point>();
+
+        c.dataArr().forEach((p) => {
+            saveDataArr.push(p)
+        });
+
+        saveObject.channelArr.push({
+            id: c.id,
+            dataArr: saveDataArr
+        });
+    });
+
+    appState().manualChannelArrSig[GET]().forEach((mc) => {
+
+        let saveDataArr = new Array<Point>();
+
+        mc.dataArr().forEach((p) => {
+            saveDataArr.push(p)
+        });
+
+        saveObject.manualChannelArr.push({
+            id: mc.id,
+            dataArr: saveDataArr
+        });
+    });
+
+    return saveObject;
+}
+
 export async function saveFile() {
     const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
     try {
 
+        // an autosaved roast is saved over its file by default
         let filepath = await save({
-            defaultPath: appState().titleSig[GET]()
+            defaultPath: appState().autosavePathSig[GET]() != "" ? appState().autosavePathSig[GET]() :
+                appState().titleSig[GET]()
                 + (appState().titleSig[GET]() != "" ? "_" : "")
                 + getCurrentFormattedTime()
                 + ".json",
@@ -243,58 +356,75 @@ export async function saveFile() {
         }) as string;
         if (!filepath) return;
 
-        let saveObject = {
-            channelArr: new Array<any>(),
-            manualChannelArr: new Array<any>(),
-            roastEvents: appState().roastEventsSig[GET](),
-            title: appState().titleSig[GET](),
-            country: appState().countrySig[GET](),
-            process: appState().processSig[GET](),
-            notes: appState().notesSig[GET](),
-            weightGreen: appState().weightGreenSig[GET](),
-            weightRoasted: appState().weightRoastedSig[GET](),
-            volumeGreen: appState().volumeGreenSig[GET](),
-            volumeRoasted: appState().volumeRoastedSig[GET](),
-            densityGreen: appState().densityGreenSig[GET](),
-            densityRoasted: appState().densityRoastedSig[GET](),
-            moistureGreen: appState().moistureGreenSig[GET](),
-            moistureRoasted: appState().moistureRoastedSig[GET](),
-            colorWhole: appState().colorWholeSig[GET](),
-            colorGround: appState().colorGroundSig[GET](),
-            flavorList: appState().flavorListSig[GET](),
-        };
-
-        appState().channelArrSig[GET]().forEach((c) => {
-
-            let saveDataArr = new Array<Point>();
-
-            c.dataArr().forEach((p) => {
-                saveDataArr.push(p)
-            });
-
-            saveObject.channelArr.push({
-                id: c.id,
-                dataArr: saveDataArr
-            });
-        });
+        let saveObject = await roastObject();
 
-        appState().manualChannelArrSig[GET]().forEach((mc) => {
+        // the backend adds the device config and validates before writing
+        await invoke("save_roast_file", { path: filepath, roast: saveObject });
 
-            let saveDataArr = new Array<Point>();
+    } catch (e) {
+        console.log(e);
+        setLogArr([...logArr(), "failed to save file: " + e]);
+    }
+}
+
+export async function exportAlog() {
+    const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
+    try {
 
-            mc.dataArr().forEach((p) => {
-                saveDataArr.push(p)
-            });
+        let filepath = await save({
+            defaultPath: appState().titleSig[GET]()
+                + (appState().titleSig[GET]() != "" ? "_" : "")
+                + getCurrentFormattedTime()
+                + ".alog",
+            filters: [{
+                name: 'Artisan',
+                extensions: ['alog']
+            }]
+        }) as string;
+        if (!filepath) return;
 
-            saveObject.manualChannelArr.push({
-                id: mc.id,
-                dataArr: saveDataArr
-            });
+        // getTimezoneOffset() is minutes west of UTC
+        await invoke("export_alog", {
+            path: filepath,
+            roast: await roastObject(),
+            utcOffset: -new Date().getTimezoneOffset()
         });
+        setLogArr([...logArr(), "exported file: " + filepath.replace(/^.*[\\/]/, '')]);
 
-        await writeTextFile(filepath, JSON.stringify(saveObject, null, 2));
+    } catch (e) {
+        console.log(e);
+        setLogArr([...logArr(), "failed to export file: " + e]);
+    }
+}
+
+export async function exportCsv() {
+    const [appState, _setAppState] = appStateSig;
+    const [logArr, setLogArr] = appState().logArrSig;
+    try {
+
+        let filepath = await save({
+            defaultPath: appState().titleSig[GET]()
+                + (appState().titleSig[GET]() != "" ? "_" : "")
+                + getCurrentFormattedTime()
+                + ".csv",
+            filters: [{
+                name: 'CSV',
+                extensions: ['csv']
+            }]
+        }) as string;
+        if (!filepath) return;
+
+        // getTimezoneOffset() is minutes west of UTC
+        await invoke("export_csv", {
+            path: filepath,
+            roast: await roastObject(),
+            utcOffset: -new Date().getTimezoneOffset()
+        });
+        setLogArr([...logArr(), "exported file: " + filepath.replace(/^.*[\\/]/, '')]);
 
     } catch (e) {
         console.log(e);
+        setLogArr([...logArr(), "failed to export file: " + e]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+import { invoke } from "@tauri-apps/api/tauri";
+import { ask, save } from "@tauri-apps/api/dialog";
+import { GET, SET, BT, AppStatus, Point, RoastEvent, RoastEvents, appStateSig, Channel } from "./AppState";
+import { applyRor, calculatePhases, computeRor } from "./calculate";
+import { startTimer } from "./DashboardPanel";
+
+const [appState, _setAppState] = appStateSig;
+const [_status, setStatus] = appState().statusSig;
+const [timer, setTimer] = appState().timerSig;
+const [channelArr, _setChannelArr] = appState().channelArrSig;
+const [manualChannelArr, _setManualChannelArr] = appState().manualChannelArrSig;
+const [roastEvents, setRoastEvents] = appState().roastEventsSig;
+const [logArr, setLogArr] = appState().logArrSig;
+
+// roast events as last sent to, or received from, the backend
+let syncedEvents: any = {};
+let notesTimeout: ReturnType<typeof setTimeout> | undefined;
+
+function toAppStatus(status: string): AppStatus {
+    switch (status) {
+        case "ON":
+            return AppStatus.ON;
+        case "RECORDING":
+            return AppStatus.RECORDING;
+        default:
+            // STOPPED keeps the roast, but the device is off
+            return AppStatus.OFF;
+    }
+}
+
+function updatePhases() {
+    const bt = channelArr().find(c => c.id == BT) as Channel;
+    let result = calculatePhases(timer(), bt.currentDataSig[GET](), roastEvents());
+    appState().dryingPhaseSig[SET](result.dry);
+    appState().maillardPhaseSig[SET](result.mai);
+    appState().developPhaseSig[SET](result.dev);
+}
+
+// one "session_update" event of the backend
+export function applySessionUpdate(update: any) {
+    switch (update.kind) {
+        case "status":
+            setStatus(toAppStatus(update.status));
+            if (update.status == "RECORDING") {
+                appState().roastEpochSig[SET](Date.now() - update.timer * 1000);
+                // manual channels start where they are
+                manualChannelArr().forEach((mc) => {
+                    mc.setDataArr([new Point(0, mc.currentDataSig[GET]())]);
+                });
+            }
+            break;
+
+        case "sample":
+            // write into history data
+            channelArr().forEach((c) => {
+                if (update.values[c.id] != undefined) {
+                    c.setDataArr([...c.dataArr(), new Point(update.timestamp, update.values[c.id])]);
+                }
+            });
+
+            // the RoR of the sample came with ror_update, detected roast events
+            // come after it
+            updatePhases();
+            break;
+
+        case "manual_change": {
+            let mc = manualChannelArr().find((mc) => mc.id == update.channel_id);
+            if (mc != undefined) {
+                mc.setDataArr([...mc.dataArr(), new Point(update.point.timestamp, update.point.value)]);
+            }
+            break;
+        }
+
+        case "roast_event": {
+            let e = update.event;
+            syncedEvents[e.id] = e;
+            let current = (roastEvents() as any)[e.id];
+            if (current == undefined || current.timestamp != e.timestamp || current.value != e.value) {
+                setRoastEvents({ ...roastEvents(), [e.id]: new RoastEvent(e.id, e.timestamp, e.value) });
+                if (e.id == "CHARGE") {
+                    appState().timeDeltaSig[SET](-e.timestamp);
+                }
+                updatePhases();
+            }
+            break;
+        }
+
+        default:
+            break;
+    }
+}
+
+// one "ror_update" event of the backend, the RoR of each channel of a sample
+export function applyRorUpdate(update: any) {
+    channelArr().forEach((c) => {
+        if (update[c.id] != undefined) {
+            applyRor(c, update[c.id]);
+        }
+    });
+}
+
+// send roast events marked, moved or auto detected in the webview to the backend
+export function syncRoastEvents(events: RoastEvents) {
+    let defined = Object.values(events).filter((e) => e != undefined) as Array<RoastEvent>;
+    if (defined.length == 0) {
+        syncedEvents = {};
+    }
+
+    defined.forEach((e) => {
+        let synced = syncedEvents[e.id];
+        if (synced == undefined || synced.timestamp != e.timestamp || synced.value != e.value) {
+            syncedEvents[e.id] = { id: e.id, timestamp: e.timestamp, value: e.value };
+            invoke("session_mark_event", { eventId: e.id, timestamp: e.timestamp, value: e.value })
+                .catch((err) => console.log(err));
+        }
+    });
+}
+
+// notes of the session roast to the backend, which updates its autosaved file.
+// typing is sent once it pauses
+export function syncNotes(notes: any) {
+    if (appState().statusSig[GET]() != AppStatus.RECORDING && appState().autosavePathSig[GET]() == "") {
+        return;
+    }
+    clearTimeout(notesTimeout);
+    notesTimeout = setTimeout(() => {
+        invoke("autosave_set_notes", { notes: notes }).catch((err) => console.log(err));
+    }, 1000);
+}
+
+// rebuild the webview state from the backend session, e.g. after a reload
+export async function restoreSession() {
+    let snapshot: any = await invoke("get_session");
+
+    if (snapshot.status == "OFF") {
+        return;
+    }
+
+    channelArr().forEach((c) => {
+        let points = snapshot.channels[c.id];
+        if (points != undefined) {
+            c.setDataArr(points.map((p: any) => new Point(p.timestamp, p.value)));
+            if (points.length > 0) {
+                c.currentDataSig[SET](points[points.length - 1].value);
+            }
+        }
+    });
+
+    manualChannelArr().forEach((mc) => {
+        let points = snapshot.manual_channels[mc.id];
+        if (points != undefined && points.length > 0) {
+            mc.setDataArr(points.map((p: any) => new Point(p.timestamp, p.value)));
+            mc.currentDataSig[SET](points[points.length - 1].value);
+        }
+    });
+
+    let events: any = { ...roastEvents() };
+    Object.values(snapshot.roast_events).forEach((e: any) => {
+        events[e.id] = new RoastEvent(e.id, e.timestamp, e.value);
+        syncedEvents[e.id] = e;
+    });
+    setRoastEvents(events);
+    if (events.CHARGE != undefined) {
+        appState().timeDeltaSig[SET](-events.CHARGE.timestamp);
+    }
+
+    for (const c of channelArr()) {
+        await computeRor(c, roastEvents());
+    }
+
+    setTimer(snapshot.timer);
+    appState().batchSig[SET](snapshot.batch ?? "");
+    appState().fcPredictionSig[SET](await invoke("get_fc_prediction"));
+    appState().dropCountdownSig[SET](await invoke("get_drop_countdown"));
+    appState().dropTargetSig[SET](await invoke("get_drop_target"));
+    appState().phaseReportSig[SET](await invoke("get_phase_report"));
+    if (snapshot.status == "RECORDING") {
+        appState().roastEpochSig[SET](Date.now() - snapshot.timer * 1000);
+    }
+    updatePhases();
+
+    setStatus(toAppStatus(snapshot.status));
+    if (snapshot.status == "RECORDING") {
+        startTimer(snapshot.timer);
+    }
+}
+
+// offer to recover roasts whose journal was left behind by a crash
+export async function recoverJournals() {
+    let journals: Array<any> = await invoke("get_unfinished_journals");
+
+    for (const j of journals) {
+        // nothing was recorded before it was left, the backend refuses to recover it
+        if (j.samples == 0) {
+            await invoke("discard_journal", { journal: j.path }).catch((err) => console.log(err));
+            continue;
+        }
+
+        let created = new Date(j.created);
+        let recover = await ask(
+            `An unfinished roast from ${created.toLocaleString()} was found, ${j.samples} samples up to ${Math.floor(j.last_timestamp)} s. Recover it?`,
+            { title: "Unfinished roast", type: "warning" }
+        );
+
+        try {
+            if (recover) {
+                let stamp = created.toISOString().slice(0, 19).replace(/[-:]/g, "").replace("T", "_");
+                let filepath = await save({
+                    defaultPath: `recovered_${stamp}.json`,
+                    filters: [{
+                        name: 'Profile',
+                        extensions: ['json']
+                    }]
+                });
+                if (filepath == null) {
+                    // keep the journal, ask again next time
+                    continue;
+                }
+                await invoke("recover_journal", { journal: j.path, path: filepath });
+                setLogArr([...logArr(), "roast recovered : " + filepath]);
+            } else if (await ask("Discard the unfinished roast?", { title: "Unfinished roast", type: "warning" })) {
+                await invoke("discard_journal", { journal: j.path });
+                setLogArr([...logArr(), "unfinished roast discarded"]);
+            }
+        } catch (e) {
+            console.log(e);
+            setLogArr([...logArr(), "failed to recover the unfinished roast : " + e]);
+        }
+    }
+}
@@ -6,8 +6,9 @@ export default () => {
     self.onmessage = (event) => {
         if (started == false) {
 
-            const interval = event.data;
-            timer = 0;
+            // { interval: milliseconds, start: seconds }
+            const interval = event.data.interval;
+            timer = event.data.start;
             started = true;
 
             setInterval(() => {